@@ -0,0 +1,111 @@
+//! `cargo migrator` subcommand, running the migrator installed next to it with
+//! the migration directory of the current crate.
+//!
+//! The migration directory defaults to `<crate>/migrations`, or the
+//! `source` key of the crate's `[package.metadata.migrator]` table. From a
+//! workspace root, the crate is the only one with such a table or a
+//! `migrations` directory.
+use std::{
+    env,
+    ffi::OsString,
+    path::{Path, PathBuf},
+    process::{self, Command},
+};
+
+use anyhow::{bail, Context, Result};
+
+const MIGRATOR_BIN: &str = "sqlite_migrator";
+
+fn main() -> Result<()> {
+    // cargo runs `cargo-migrator migrator <ARGS>`
+    let mut args = env::args_os().skip(1).peekable();
+    if args.peek().is_some_and(|a| a == "migrator") {
+        args.next();
+    }
+
+    let args = args.collect::<Vec<_>>();
+
+    let mut migrator = Command::new(migrator_path());
+    migrator.args(&args);
+
+    // an explicit directory or config file takes precedence
+    if env::var_os("MIGRATION_DIR").is_none()
+        && env::var_os("MIGRATOR_CONFIG").is_none()
+        && !has_option(&args, "-s", "--source")
+        && !has_option(&args, "-c", "--config")
+        && !Path::new(".migrate-config.yaml").exists()
+    {
+        if let Some(source) = crate_migrations_dir()? {
+            migrator.env("MIGRATION_DIR", source);
+        }
+    }
+
+    let status = migrator
+        .status()
+        .context(format!("Failed to run {MIGRATOR_BIN}"))?;
+    process::exit(status.code().unwrap_or(1));
+}
+
+// Whether the option `short` or `long` of the migrator is among `args`
+fn has_option(args: &[OsString], short: &str, long: &str) -> bool {
+    args.iter().filter_map(|arg| arg.to_str()).any(|arg| {
+        arg == long
+            || arg.starts_with(&format!("{long}="))
+            || (arg.starts_with(short) && !arg.starts_with("--"))
+    })
+}
+
+// The migrator binary installed in the same directory, or else from the PATH
+fn migrator_path() -> PathBuf {
+    env::current_exe()
+        .ok()
+        .and_then(|exe| Some(exe.parent()?.join(MIGRATOR_BIN)))
+        .filter(|path| path.with_extension(env::consts::EXE_EXTENSION).exists())
+        .unwrap_or_else(|| PathBuf::from(MIGRATOR_BIN))
+}
+
+// Migration directory of the crate in the current directory, found with
+// `cargo metadata`
+fn crate_migrations_dir() -> Result<Option<PathBuf>> {
+    let output = Command::new(env::var_os("CARGO").unwrap_or_else(|| "cargo".into()))
+        .args(["metadata", "--no-deps", "--format-version", "1"])
+        .output()
+        .context("Failed to run cargo metadata")?;
+    if !output.status.success() {
+        // not in a cargo project
+        return Ok(None);
+    }
+    let metadata: serde_json::Value = serde_json::from_slice(&output.stdout)?;
+    let current_dir = env::current_dir()?;
+
+    let candidates = metadata["packages"]
+        .as_array()
+        .map(Vec::as_slice)
+        .unwrap_or_default()
+        .iter()
+        .filter_map(|package| {
+            let dir = Path::new(package["manifest_path"].as_str()?).parent()?;
+            let source = match package["metadata"]["migrator"]["source"].as_str() {
+                Some(source) => dir.join(source),
+                None => dir.join("migrations"),
+            };
+            Some((dir.to_owned(), source))
+        })
+        .collect::<Vec<_>>();
+
+    if let Some((_, source)) = candidates.iter().find(|(dir, _)| *dir == current_dir) {
+        return Ok(Some(source.clone()));
+    }
+
+    let existing = candidates
+        .into_iter()
+        .filter(|(_, source)| source.is_dir())
+        .collect::<Vec<_>>();
+    match existing.as_slice() {
+        [] => Ok(None),
+        [(_, source)] => Ok(Some(source.clone())),
+        _ => bail!(
+            "Several workspace crates have migrations, run from the crate directory or pass --source"
+        ),
+    }
+}
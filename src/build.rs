@@ -0,0 +1,24 @@
+//! Validation of a migration directory from build scripts, so that broken
+//! migrations fail the build instead of the deployment.
+use std::path::Path;
+
+use anyhow::{Context, Result};
+use rusqlite::Connection;
+
+use crate::migration::Migrations;
+
+/// Load the migrations of `dir`, checking their ids, and apply them to an
+/// in-memory database. Meant to be called from `build.rs`, it also tells
+/// cargo to rerun the build script when the directory changes.
+///
+/// Only the SQL is run: the shell commands of the migrations are left out.
+pub fn validate(dir: impl AsRef<Path>) -> Result<()> {
+    let dir = dir.as_ref();
+    println!("cargo:rerun-if-changed={}", dir.display());
+
+    let migrations = Migrations::from_directory(dir)
+        .context(format!("Invalid migration directory {}", dir.display()))?;
+    migrations.migrated_schema(&Connection::open_in_memory()?)?;
+
+    Ok(())
+}
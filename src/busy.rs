@@ -0,0 +1,66 @@
+//! Retries of operations failing because another connection, e.g. the running
+//! application, holds a lock on the database.
+use std::time::Duration;
+
+use rusqlite::ErrorCode;
+use tracing::warn;
+
+/// How many times, and how long apart, operations on a locked database are
+/// retried
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Retry {
+    pub retries: u32,
+    /// Wait before the first retry, doubled before each following one
+    pub backoff: Duration,
+}
+
+impl Default for Retry {
+    fn default() -> Self {
+        Self {
+            retries: 0,
+            backoff: Duration::from_millis(100),
+        }
+    }
+}
+
+impl Retry {
+    /// Run `f`, running it again while `busy` reports its error as caused by
+    /// a lock and retries are left. The last error is returned once they are
+    /// exhausted.
+    pub fn run<T, E>(
+        self,
+        busy: impl Fn(&E) -> bool,
+        mut f: impl FnMut() -> Result<T, E>,
+    ) -> Result<T, E> {
+        let mut backoff = self.backoff;
+        let mut attempt = 0;
+        loop {
+            match f() {
+                Err(e) if busy(&e) && attempt < self.retries => {
+                    attempt += 1;
+                    warn!(
+                        "Database is locked, retrying in {backoff:?} ({attempt}/{})",
+                        self.retries
+                    );
+                    std::thread::sleep(backoff);
+                    backoff *= 2;
+                }
+                res => return res,
+            }
+        }
+    }
+}
+
+/// Whether SQLite failed because another connection holds a lock
+pub fn is_busy(e: &rusqlite::Error) -> bool {
+    matches!(
+        e.sqlite_error_code(),
+        Some(ErrorCode::DatabaseBusy | ErrorCode::DatabaseLocked)
+    )
+}
+
+/// Whether any error of the chain is a [`is_busy`] SQLite error
+pub fn is_busy_chain(e: &anyhow::Error) -> bool {
+    e.chain()
+        .any(|e| e.downcast_ref::<rusqlite::Error>().is_some_and(is_busy))
+}
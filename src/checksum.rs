@@ -0,0 +1,77 @@
+//! Checksums of migration contents, stored in the history table to detect
+//! applied migrations whose files changed afterwards.
+//!
+//! The SQL is canonicalized before hashing, so that checksums don't depend on
+//! line endings or trailing whitespace. Checksums other than SHA-256 are
+//! prefixed with their algorithm name, so stored checksums stay verifiable
+//! after the configured algorithm changes.
+use sha2::{Digest, Sha256};
+
+/// Hash function used for new checksums
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, serde::Deserialize, serde::Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Algorithm {
+    /// Hex encoded SHA-256, stored without prefix
+    #[default]
+    Sha256,
+    /// Hex encoded 128-bit XXH3, faster on large migrations, stored as
+    /// `xxh3:<hex>`
+    Xxh3,
+}
+
+impl Algorithm {
+    const XXH3_PREFIX: &'static str = "xxh3:";
+
+    /// Checksum of the canonicalized `sql`
+    pub fn checksum(self, sql: &str) -> String {
+        let sql = canonicalize(sql);
+        match self {
+            Algorithm::Sha256 => sha256(&sql),
+            Algorithm::Xxh3 => format!(
+                "{}{:032x}",
+                Self::XXH3_PREFIX,
+                xxhash_rust::xxh3::xxh3_128(sql.as_bytes())
+            ),
+        }
+    }
+
+    /// Algorithm a stored checksum was computed with
+    pub fn of(checksum: &str) -> Self {
+        if checksum.starts_with(Self::XXH3_PREFIX) {
+            Algorithm::Xxh3
+        } else {
+            Algorithm::Sha256
+        }
+    }
+}
+
+/// Normalize `sql` before hashing: line endings become `\n`, trailing
+/// whitespace is removed from every line and trailing blank lines are dropped.
+pub fn canonicalize(sql: &str) -> String {
+    sql.lines()
+        .map(str::trim_end)
+        .collect::<Vec<_>>()
+        .join("\n")
+        .trim_end()
+        .to_owned()
+}
+
+/// Checksum of `sql` with the default algorithm
+pub fn checksum(sql: &str) -> String {
+    Algorithm::default().checksum(sql)
+}
+
+/// Whether a stored checksum matches `sql`, computed again with the
+/// algorithm it was stored with. SHA-256 checksums of the raw SQL, stored
+/// before canonicalization was introduced, match too.
+pub fn matches(stored: &str, sql: &str) -> bool {
+    let algorithm = Algorithm::of(stored);
+    algorithm.checksum(sql) == stored || (algorithm == Algorithm::Sha256 && sha256(sql) == stored)
+}
+
+fn sha256(sql: &str) -> String {
+    Sha256::digest(sql.as_bytes())
+        .iter()
+        .map(|b| format!("{b:02x}"))
+        .collect()
+}
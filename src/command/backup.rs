@@ -0,0 +1,29 @@
+use std::path::{Path, PathBuf};
+
+use anyhow::{Context, Result};
+use chrono::Local;
+use rusqlite::Connection;
+use tracing::info;
+
+/// Copy the database to a timestamped file in `backup_dir`, or next to the
+/// database when not set, returning the path of the copy.
+pub fn backup(conn: &Connection, db_path: &Path, backup_dir: Option<&Path>) -> Result<PathBuf> {
+    let file_name = db_path
+        .file_name()
+        .context("Database path has no file name")?
+        .to_string_lossy();
+    let backup_name = format!("{file_name}.{}.bak", Local::now().format("%Y%m%d%H%M%S"));
+    let backup_path = match backup_dir {
+        Some(dir) => dir.join(backup_name),
+        None => db_path.with_file_name(backup_name),
+    };
+
+    info!("Backing up database to {}", backup_path.display());
+    conn.execute("VACUUM INTO ?1", [backup_path.to_string_lossy()])
+        .context(format!(
+            "Could not back up database to {}",
+            backup_path.display()
+        ))?;
+
+    Ok(backup_path)
+}
@@ -0,0 +1,59 @@
+use std::path::Path;
+
+use anyhow::{Context, Result};
+use rusqlite::{types::Value, Connection, OpenFlags};
+use sqlite_migrator::compare::{self, format_value};
+
+/// Compare two databases, reporting the differences of their schemas and the
+/// row-level differences of `data_tables`. Fails if any difference is found.
+pub fn compare(a: &Path, b: &Path, data_tables: &[String]) -> Result<()> {
+    let conn_a = open_read_only(a)?;
+    let conn_b = open_read_only(b)?;
+
+    let schema = compare::schema_diff(&conn_a, &conn_b)?;
+    let mut identical = schema.is_empty();
+    if !identical {
+        println!("Schema:");
+        for difference in &schema {
+            println!("  {difference}");
+        }
+    }
+
+    for table in data_tables {
+        let diff = compare::data_diff(&conn_a, &conn_b, table)?;
+        if diff.is_empty() {
+            continue;
+        }
+        identical = false;
+
+        println!("Table {} ({}):", diff.table, diff.columns.join(", "));
+        for row in &diff.deleted {
+            println!("  - {}", format_row(row));
+        }
+        for row in &diff.inserted {
+            println!("  + {}", format_row(row));
+        }
+        for (row_a, row_b) in &diff.updated {
+            println!("  ~ {} -> {}", format_row(row_a), format_row(row_b));
+        }
+    }
+
+    if !identical {
+        anyhow::bail!("Databases differ");
+    }
+
+    println!("Databases are identical");
+    Ok(())
+}
+
+fn format_row(row: &[Value]) -> String {
+    format!(
+        "({})",
+        row.iter().map(format_value).collect::<Vec<_>>().join(", ")
+    )
+}
+
+fn open_read_only(path: &Path) -> Result<Connection> {
+    Connection::open_with_flags(path, OpenFlags::SQLITE_OPEN_READ_ONLY)
+        .context(format!("Could not open database {}", path.display()))
+}
@@ -0,0 +1,229 @@
+use std::{collections::HashMap, fs, path::Path};
+
+use anyhow::{Context, Result};
+use serde_yaml::{Mapping, Value};
+use sqlite_migrator::vars;
+
+// Stand-in default of the values without a known key, such as the items of
+// lists and of the `vars` mapping, which are only ever strings
+static FREE_FORM: Value = Value::Sequence(Vec::new());
+
+/// Expand the `${VAR}` placeholders of the string values of the config from
+/// the environment, `known` being the default config and `key` the path of
+/// `value` in the config.
+///
+/// A placeholder of a key which is a number, a boolean or optional by
+/// default, e.g. `busy_retries: ${RETRIES:-3}`, is parsed again once
+/// expanded, so that it keeps its type.
+pub fn expand_env(value: &mut Value, known: &Value, key: &str) -> Result<()> {
+    match value {
+        Value::String(s) => {
+            let expanded = vars::expand(s, &HashMap::new())
+                .context(format!("Could not expand the config key {key}"))?;
+            let typed = matches!(known, Value::Bool(_) | Value::Number(_) | Value::Null);
+            *value = match serde_yaml::from_str(&expanded) {
+                Ok(parsed @ (Value::Bool(_) | Value::Number(_))) if typed && expanded != *s => {
+                    parsed
+                }
+                _ => Value::String(expanded),
+            };
+        }
+        Value::Sequence(items) => {
+            for (i, item) in items.iter_mut().enumerate() {
+                expand_env(item, &FREE_FORM, &format!("{key}[{i}]"))?;
+            }
+        }
+        Value::Mapping(entries) => {
+            for (name, item) in entries.iter_mut() {
+                let known = known.get(name).unwrap_or(&FREE_FORM);
+                expand_env(item, known, &child_key(key, name))?;
+            }
+        }
+        _ => {}
+    }
+
+    Ok(())
+}
+
+fn child_key(key: &str, name: &Value) -> String {
+    let name = name.as_str().unwrap_or_default();
+    if key.is_empty() {
+        name.to_owned()
+    } else {
+        format!("{key}.{name}")
+    }
+}
+
+/// What `config check` validates the config file against
+pub struct ConfigSchema<'a> {
+    /// The default config, serialized: its keys are the known ones
+    pub known: &'a Value,
+    /// Deserialize the config, failing on wrong types
+    pub parse: &'a dyn Fn(&Value) -> Result<(), serde_yaml::Error>,
+    /// Required keys missing from the config, the command line and the
+    /// environment
+    pub missing: &'a [&'a str],
+    /// The valid keys of the config, see [`valid_keys`], resolved with the
+    /// command line and the environment
+    pub effective: &'a Value,
+}
+
+/// Report the problems of the config file at `path`: invalid YAML, unknown
+/// keys, values of the wrong type, undefined variables and missing required
+/// keys, with their line when known. Then print the effective config, and
+/// fail if any problem was found.
+pub fn config_check(path: &Path, schema: &ConfigSchema) -> Result<()> {
+    let mut problems = Vec::new();
+
+    match fs::read_to_string(path) {
+        Ok(text) => match serde_yaml::from_str::<Value>(&text) {
+            Ok(mut value) => {
+                unknown_keys(&text, &value, schema.known, "", &mut problems);
+                // the types are those of the expanded values
+                match expand_env(&mut value, schema.known, "") {
+                    Ok(()) => {
+                        let (_, errors) = valid_keys(&value, schema.parse);
+                        problems.extend(errors.into_iter().map(|(key, err)| {
+                            match key_line(&text, &key) {
+                                Some(line) => format!("{key} at line {line}: {err}"),
+                                None if key.is_empty() => err.to_string(),
+                                None => format!("{key}: {err}"),
+                            }
+                        }));
+                    }
+                    Err(err) => problems.push(format!("{err:#}")),
+                }
+            }
+            Err(err) => problems.push(err.to_string()),
+        },
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => {
+            println!("No config file at {}", path.display());
+        }
+        Err(err) => {
+            return Err(err).context(format!("Could not read {}", path.display()));
+        }
+    }
+    problems.extend(schema.missing.iter().map(|key| {
+        format!(
+            "{key} is required but not set in the config file, the arguments or the environment"
+        )
+    }));
+
+    println!("# Effective configuration");
+    print!("{}", serde_yaml::to_string(schema.effective)?);
+
+    if !problems.is_empty() {
+        for problem in &problems {
+            eprintln!("{}: {problem}", path.display());
+        }
+        anyhow::bail!("{} problems found in the config file", problems.len());
+    }
+
+    Ok(())
+}
+
+/// Split the config `value` into the keys which `parse` accepts, and the
+/// paths of the other ones with their error. The valid keys are a best
+/// effort config, so that a single bad key doesn't discard the whole file.
+pub fn valid_keys(
+    value: &Value,
+    parse: &dyn Fn(&Value) -> Result<(), serde_yaml::Error>,
+) -> (Value, Vec<(String, serde_yaml::Error)>) {
+    let mut errors = Vec::new();
+    let valid = match value {
+        Value::Mapping(entries) => valid_entries(entries, &|v| v, parse, "", &mut errors),
+        Value::Null => Mapping::new(),
+        _ => {
+            errors.extend(parse(value).err().map(|err| (String::new(), err)));
+            Mapping::new()
+        }
+    };
+
+    (Value::Mapping(valid), errors)
+}
+
+// The entries of the mapping at `key` which `parse` accepts on their own,
+// placed back at their path by `wrap`. The entries of a rejected mapping are
+// tried one by one, so that only its bad keys are dropped.
+fn valid_entries(
+    entries: &Mapping,
+    wrap: &dyn Fn(Value) -> Value,
+    parse: &dyn Fn(&Value) -> Result<(), serde_yaml::Error>,
+    key: &str,
+    errors: &mut Vec<(String, serde_yaml::Error)>,
+) -> Mapping {
+    let mut valid = Mapping::new();
+    for (name, item) in entries {
+        let path = child_key(key, name);
+        let single = Mapping::from_iter([(name.clone(), item.clone())]);
+        match (parse(&wrap(Value::Mapping(single))), item) {
+            (Ok(()), _) => {
+                valid.insert(name.clone(), item.clone());
+            }
+            (Err(_), Value::Mapping(children)) if !children.is_empty() => {
+                let wrap_child =
+                    |child| wrap(Value::Mapping(Mapping::from_iter([(name.clone(), child)])));
+                let children = valid_entries(children, &wrap_child, parse, &path, errors);
+                if !children.is_empty() {
+                    valid.insert(name.clone(), Value::Mapping(children));
+                }
+            }
+            (Err(err), _) => errors.push((path, err)),
+        }
+    }
+
+    valid
+}
+
+// Report the keys of `value` which `known` doesn't have. Mappings which are
+// empty by default, such as `vars`, take any key.
+fn unknown_keys(text: &str, value: &Value, known: &Value, key: &str, problems: &mut Vec<String>) {
+    let (Value::Mapping(entries), Value::Mapping(known_entries)) = (value, known) else {
+        return;
+    };
+    if known_entries.is_empty() {
+        return;
+    }
+
+    for (name, item) in entries {
+        let path = child_key(key, name);
+        match known_entries.get(name) {
+            Some(known) => unknown_keys(text, item, known, &path, problems),
+            None => problems.push(match key_line(text, &path) {
+                Some(line) => format!("unknown key {path} at line {line}"),
+                None => format!("unknown key {path}"),
+            }),
+        }
+    }
+}
+
+// Line of the key at `path`, e.g. `open_flags.read_only`, found by its
+// indentation below its parent key
+fn key_line(text: &str, path: &str) -> Option<usize> {
+    let mut lines = text.lines().enumerate();
+    let mut parent_indent = None;
+    let mut found = None;
+    for name in path.split('.') {
+        found = None;
+        for (i, line) in lines.by_ref() {
+            let trimmed = line.trim_start();
+            let indent = line.len() - trimmed.len();
+            if trimmed.is_empty() || trimmed.starts_with('#') {
+                continue;
+            }
+            if parent_indent.is_some_and(|parent| indent <= parent) {
+                return None;
+            }
+            let is_key = trimmed
+                .strip_prefix(name)
+                .is_some_and(|rest| rest.trim_start().starts_with(':'));
+            if is_key && (parent_indent.is_some() || indent == 0) {
+                found = Some(i + 1);
+                parent_indent = Some(indent);
+                break;
+            }
+        }
+    }
+
+    found
+}
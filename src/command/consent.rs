@@ -0,0 +1,69 @@
+use std::path::Path;
+
+use anyhow::Result;
+use sqlite_migrator::{migration::Migrations, statements};
+
+use super::prompt::{confirm_for, PromptCategory};
+
+/// Ask for confirmation before migrating from version `from` to `to` when the
+/// database is protected or the SQL to run destroys data, unless `assumed`
+/// consents to the matching prompt categories.
+pub fn confirm_migration(
+    migrations: &Migrations,
+    from: usize,
+    to: usize,
+    db_path: &Path,
+    protected: bool,
+    assumed: &[PromptCategory],
+) -> Result<()> {
+    if from == to {
+        return Ok(());
+    }
+    let up = from < to;
+    let direction = if up { "up" } else { "down" };
+
+    if protected {
+        confirm_for(
+            PromptCategory::ProtectedEnvironment,
+            assumed,
+            &format!(
+                "Database {} is protected. Migrate it {direction} from version {from} to {to}?",
+                db_path.display()
+            ),
+            "Migration of a protected database aborted",
+        )?;
+    }
+
+    let destructive = migrations
+        .iter()
+        .filter(|(v, _)| (from.min(to) + 1..=from.max(to)).contains(v))
+        .flat_map(|(version, m)| {
+            let sql = if up { Some(m.get_up()) } else { m.get_down() };
+            statements::split(sql.unwrap_or_default())
+                .into_iter()
+                .filter(|s| statements::is_destructive(&s.sql))
+                .map(move |s| (version, m.get_comment().unwrap_or_default(), s.sql))
+        })
+        .collect::<Vec<_>>();
+    let category = if up {
+        PromptCategory::DestructiveSql
+    } else {
+        PromptCategory::IrreversibleDown
+    };
+    if destructive.is_empty() || assumed.contains(&category) {
+        return Ok(());
+    }
+
+    for (version, name, sql) in &destructive {
+        eprintln!("{version:04} {name}: {sql}");
+    }
+    confirm_for(
+        category,
+        assumed,
+        &format!(
+            "Migrating {direction} runs {} statements destroying data. Continue?",
+            destructive.len()
+        ),
+        "Migration aborted",
+    )
+}
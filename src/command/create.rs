@@ -1,67 +1,230 @@
 use std::{
+    collections::BTreeSet,
+    env,
     fs::{self, File},
     io::Write,
-    ops::ControlFlow,
-    path::Path,
+    path::{Path, PathBuf},
 };
 
 use anyhow::{Context, Result};
 use chrono::Local;
+use regex::Regex;
+use sqlite_migrator::{loader::validate_name, meta::META_FILE_NAME, shell};
+use tracing::warn;
+
+/// Editor used when neither `VISUAL` nor `EDITOR` is set
+const DEFAULT_EDITOR: &str = if cfg!(windows) { "notepad" } else { "vi" };
+
+#[derive(Debug, Clone, Default)]
+pub struct CreateOptions {
+    /// Validate the name as a strict slug of at most this many characters
+    pub max_name_length: Option<usize>,
+    /// Regular expression the name must match
+    pub name_pattern: Option<String>,
+    /// Use this exact sequence number
+    pub id: Option<u32>,
+    /// Use the first free sequence number after this one
+    pub after: Option<u32>,
+    /// Skip down.sql and mark the migration irreversible
+    pub no_down: bool,
+    /// Open the generated up.sql in the user's editor
+    pub edit: bool,
+    /// Open the generated down.sql in the user's editor too
+    pub edit_down: bool,
+    /// SQL written to up.sql after the generated comment
+    pub up_sql: Option<String>,
+    /// SQL written to down.sql after the generated comment
+    pub down_sql: Option<String>,
+}
+
+// Reject names which can't be part of a folder name on every platform
+fn check_folder_name(name: &str) -> Result<()> {
+    if name.is_empty() {
+        anyhow::bail!("Migration name cannot be empty");
+    }
+    if let Some(c) = name.chars().find(|c| {
+        c.is_control() || matches!(c, '/' | '\\' | ':' | '*' | '?' | '"' | '<' | '>' | '|')
+    }) {
+        anyhow::bail!(
+            "Migration name {name:?} contains {c:?}, which is not allowed in folder names"
+        );
+    }
+    if name.starts_with('.') || name.ends_with('.') {
+        anyhow::bail!("Migration name {name:?} cannot start or end with a dot");
+    }
+
+    Ok(())
+}
+
+/// Check that a migration name (without its id prefix) matches the
+/// `name_pattern` regular expression
+pub fn check_name_pattern(name: &str, pattern: &str) -> Result<()> {
+    let regex = Regex::new(pattern).context(format!("Invalid name_pattern {pattern:?}"))?;
+    if !regex.is_match(name) {
+        anyhow::bail!("Migration name {name:?} doesn't match the name pattern {pattern:?}");
+    }
+
+    Ok(())
+}
+
+pub fn create(migration_dir: &Path, migration_name: &str, options: &CreateOptions) -> Result<()> {
+    let name = migration_name.replace(['-', ' '], "_");
+    let name = name.trim_end_matches('_');
+    check_folder_name(name)?;
+    if let Some(max_length) = options.max_name_length {
+        validate_name(name, max_length)?;
+    }
+    if let Some(pattern) = &options.name_pattern {
+        check_name_pattern(name, pattern)?;
+    }
 
-pub fn create(migration_dir: &Path, migration_name: &str) -> Result<()> {
     if !migration_dir.exists() {
-        fs::create_dir(migration_dir).context("Failed to create migration directory.")?;
+        fs::create_dir_all(migration_dir).context("Failed to create migration directory.")?;
     }
 
     // Determine the sequence number for the new migration folder
-    let max_sequence_number = fs::read_dir(migration_dir)
+    let folders = fs::read_dir(migration_dir)
         .context("Failed to read migration directory")?
         .filter_map(|res| res.map(|e| e.path()).ok())
         .filter_map(|entry| {
             let dir_name = entry.file_name()?.to_str()?;
-            let parts: Vec<&str> = dir_name.split('-').collect();
-            if !entry.is_dir() || parts.len() < 2 {
+            let (id, name) = dir_name.split_once('-')?;
+            if !entry.is_dir() {
                 return None;
             }
-            parts.first().and_then(|v| v.parse::<u32>().ok())
+            Some((
+                id.parse::<u32>().ok()?,
+                name.to_owned(),
+                dir_name.to_owned(),
+            ))
         })
-        .max()
-        .unwrap_or(0);
-
-    // Generate a new folder name with a 4-digit sequence number.
-    let new_sequence_number = max_sequence_number + 1;
-    let folder_name = format!(
-        "{:04}-{}",
-        new_sequence_number,
-        migration_name
-            .replace(['-', ' '], "_")
-            .trim_end_matches('_')
-    );
-
-    // Create the new folder inside the source directory.
-    let migration_folder = migration_dir.join(&folder_name);
-    fs::create_dir(&migration_folder)
-        .context("Failed to create new migration folder inside migration directory.")?;
+        .collect::<Vec<_>>();
+    if let Some((_, _, existing)) = folders.iter().find(|(_, n, _)| n == name) {
+        anyhow::bail!("A migration named {name:?} already exists: {existing}");
+    }
+    let mut sequence_numbers = folders
+        .iter()
+        .map(|(id, _, _)| *id)
+        .collect::<BTreeSet<_>>();
+    let max_sequence_number = sequence_numbers.last().copied().unwrap_or(0);
+
+    let mut new_sequence_number = match (options.id, options.after) {
+        (Some(id), _) => {
+            if id == 0 {
+                anyhow::bail!("Migration id cannot be 0");
+            }
+            if sequence_numbers.contains(&id) {
+                anyhow::bail!("Migration id {id} is already used");
+            }
+            id
+        }
+        (None, Some(after)) => {
+            if after != 0 && !sequence_numbers.contains(&after) {
+                anyhow::bail!("No migration with id {after} to create a migration after");
+            }
+            (after + 1..)
+                .find(|id| !sequence_numbers.contains(id))
+                .expect("sequence numbers are finite")
+        }
+        (None, None) => max_sequence_number + 1,
+    };
+    if new_sequence_number > max_sequence_number + 1 {
+        warn!(
+            "Migration id {new_sequence_number} leaves a gap after the current last id {max_sequence_number}"
+        );
+    }
+
+    // Create the new folder inside the source directory, with a 4-digit
+    // sequence number. When another migration took the id in the meantime,
+    // e.g. a colleague's one pulled in, the next free id is used instead.
+    let (folder_name, migration_folder) = loop {
+        let folder_name = format!("{:04}-{}", new_sequence_number, name);
+        let migration_folder = migration_dir.join(&folder_name);
+        let taken = fs::read_dir(migration_dir)
+            .context("Failed to read migration directory")?
+            .filter_map(|entry| entry.ok()?.file_name().into_string().ok())
+            .any(|n| {
+                n.split_once('-').map(|(id, _)| id.parse::<u32>().ok())
+                    == Some(Some(new_sequence_number))
+            });
+        if !taken {
+            match fs::create_dir(&migration_folder) {
+                Ok(()) => break (folder_name, migration_folder),
+                Err(e) if e.kind() == std::io::ErrorKind::AlreadyExists => {}
+                Err(e) => {
+                    return Err(e).context(
+                        "Failed to create new migration folder inside migration directory.",
+                    )
+                }
+            }
+        }
+
+        sequence_numbers.insert(new_sequence_number);
+        let next = (new_sequence_number + 1..)
+            .find(|id| !sequence_numbers.contains(id))
+            .expect("sequence numbers are finite");
+        warn!("Migration id {new_sequence_number} is already used, creating the migration with id {next}");
+        new_sequence_number = next;
+    };
 
     // Generate and write the current date as a comment in up.sql and down.sql.
     let current_date = Local::now().format("%Y-%m-%d %H:%M:%S").to_string();
-    let comment = |up_or_down: &str| {
-        format!(
+    let comment = |up_or_down: &str, sql: &Option<String>| {
+        let comment = format!(
             "-- {} migration `{folder_name}` generated at {current_date}.",
             up_or_down
-        )
+        );
+        match sql {
+            Some(sql) => format!("{comment}\n{}\n", sql.trim_end()),
+            None => comment,
+        }
     };
 
     let up_sql_path = migration_folder.join("up.sql");
     let down_sql_path = migration_folder.join("down.sql");
 
-    File::create(up_sql_path)
-        .and_then(|mut file| file.write_all(comment("Up").as_bytes()))
+    File::create(&up_sql_path)
+        .and_then(|mut file| file.write_all(comment("Up", &options.up_sql).as_bytes()))
         .context("Failed to create and write up.sql")?;
 
-    File::create(down_sql_path)
-        .and_then(|mut file| file.write_all(comment("Down").as_bytes()))
-        .context("Failed to create and write down.sql")?;
+    if options.no_down {
+        let meta_path = migration_folder.join(META_FILE_NAME);
+        File::create(meta_path)
+            .and_then(|mut file| {
+                file.write_all(b"# `down` refuses to revert this migration\nirreversible: true\n")
+            })
+            .context(format!("Failed to create and write {META_FILE_NAME}"))?;
+    } else {
+        File::create(&down_sql_path)
+            .and_then(|mut file| file.write_all(comment("Down", &options.down_sql).as_bytes()))
+            .context("Failed to create and write down.sql")?;
+    }
+
+    let mut to_edit = Vec::new();
+    if options.edit || options.edit_down {
+        to_edit.push(up_sql_path);
+    }
+    if options.edit_down && !options.no_down {
+        to_edit.push(down_sql_path);
+    }
+    if !to_edit.is_empty() {
+        edit(&to_edit)?;
+    }
 
     Ok(())
 }
+
+// Open `files` in $VISUAL or $EDITOR, waiting for the editor to exit
+fn edit(files: &[PathBuf]) -> Result<()> {
+    let editor = env::var("VISUAL")
+        .or_else(|_| env::var("EDITOR"))
+        .unwrap_or_else(|_| DEFAULT_EDITOR.to_owned());
+    let files = files
+        .iter()
+        .map(|f| format!("\"{}\"", f.display()))
+        .collect::<Vec<_>>()
+        .join(" ");
+
+    shell::run(&format!("{editor} {files}"), &[])
+        .context("Failed to open the migration in an editor")
+}
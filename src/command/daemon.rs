@@ -0,0 +1,40 @@
+use std::{str::FromStr, thread};
+
+use anyhow::{Context, Result};
+use chrono::Local;
+use cron::Schedule;
+use sqlite_migrator::migration::MigrationRun;
+use tracing::{error, info};
+
+/// Run `run` on every tick of the cron `schedule` until the process is
+/// stopped. Failed runs are logged, and reported by `run` itself, but don't
+/// stop the daemon.
+pub fn daemon(schedule: &str, mut run: impl FnMut() -> Result<Vec<MigrationRun>>) -> Result<()> {
+    let schedule = parse_schedule(schedule)?;
+
+    loop {
+        let next = schedule
+            .upcoming(Local)
+            .next()
+            .context("Schedule has no upcoming run")?;
+        info!("Next run at {}", next);
+        thread::sleep((next - Local::now()).to_std().unwrap_or_default());
+
+        match run() {
+            Ok(runs) => info!("Scheduled run applied {} migrations", runs.len()),
+            Err(err) => error!("Scheduled run failed: {:#}", err),
+        }
+    }
+}
+
+// Accept standard 5 field cron expressions as well as the 6 and 7 field ones
+// (with seconds and years) understood by the cron crate
+fn parse_schedule(schedule: &str) -> Result<Schedule> {
+    let expression = if schedule.split_whitespace().count() == 5 {
+        format!("0 {schedule}")
+    } else {
+        schedule.to_owned()
+    };
+
+    Schedule::from_str(&expression).context(format!("Invalid cron schedule {schedule:?}"))
+}
@@ -0,0 +1,394 @@
+use std::{
+    fmt,
+    path::{Path, PathBuf},
+};
+
+use anyhow::Result;
+use rusqlite::Connection;
+use sqlite_migrator::{
+    error::Error,
+    lock,
+    migration::{Migrations, SchemaVersion},
+};
+
+/// Oldest SQLite version supported, for `VACUUM INTO` backups
+const MIN_SQLITE_VERSION: i32 = 3_027_000;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Status {
+    Ok,
+    Warn,
+    Fail,
+    /// Not run because a check it depends on failed
+    Skip,
+}
+
+impl fmt::Display for Status {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Status::Ok => f.pad("ok"),
+            Status::Warn => f.pad("warn"),
+            Status::Fail => f.pad("FAIL"),
+            Status::Skip => f.pad("skip"),
+        }
+    }
+}
+
+struct Check {
+    name: &'static str,
+    status: Status,
+    detail: String,
+    hint: Option<String>,
+}
+
+impl Check {
+    fn ok(name: &'static str, detail: impl Into<String>) -> Self {
+        Self {
+            name,
+            status: Status::Ok,
+            detail: detail.into(),
+            hint: None,
+        }
+    }
+
+    fn problem(
+        name: &'static str,
+        status: Status,
+        detail: impl Into<String>,
+        hint: impl Into<String>,
+    ) -> Self {
+        Self {
+            name,
+            status,
+            detail: detail.into(),
+            hint: Some(hint.into()),
+        }
+    }
+
+    fn skip(name: &'static str) -> Self {
+        Self {
+            name,
+            status: Status::Skip,
+            detail: "depends on a failed check".to_owned(),
+            hint: None,
+        }
+    }
+}
+
+/// Diagnose common setup problems, printing the result of every check with a
+/// hint for the failed ones. `load` and `open` load the migrations and open
+/// the database the way the other commands do.
+pub fn doctor(
+    config_path: &Path,
+    config: Result<(), &anyhow::Error>,
+    source: &Result<PathBuf>,
+    db_path: &Result<PathBuf>,
+    load: impl Fn(&Path) -> Result<Migrations>,
+    open: impl Fn(&Path) -> Result<Connection>,
+) -> Result<()> {
+    let mut checks = vec![check_config(config_path, config)];
+
+    let migrations = match source {
+        Ok(source) if source.is_dir() => {
+            checks.push(Check::ok("source directory", source.display().to_string()));
+            Some(load(source))
+        }
+        Ok(source) => {
+            checks.push(Check::problem(
+                "source directory",
+                Status::Fail,
+                format!("{} does not exist", source.display()),
+                "create it with `migrator create <NAME>` or --ensure-dir",
+            ));
+            None
+        }
+        Err(err) => {
+            checks.push(Check::problem(
+                "source directory",
+                Status::Fail,
+                format!("{err:#}"),
+                format!(
+                    "pass --source, or set MIGRATION_DIR or source_path in {}",
+                    config_path.display()
+                ),
+            ));
+            None
+        }
+    };
+    let migrations = match migrations {
+        Some(loaded) => {
+            checks.extend(check_migrations(&loaded));
+            loaded.ok()
+        }
+        None => {
+            checks.extend(["migration files", "migration ids", "down migrations"].map(Check::skip));
+            None
+        }
+    };
+
+    let conn = match db_path {
+        Ok(db_path) if db_path.exists() => match open(db_path) {
+            Ok(conn) => {
+                match conn.query_row("SELECT count(*) FROM sqlite_master", [], |_| Ok(())) {
+                    Ok(()) => {
+                        checks.push(Check::ok("database", db_path.display().to_string()));
+                        Some(conn)
+                    }
+                    Err(err) => {
+                        checks.push(Check::problem(
+                            "database",
+                            Status::Fail,
+                            format!("{}: {err}", db_path.display()),
+                            "check that the path points to an SQLite database",
+                        ));
+                        None
+                    }
+                }
+            }
+            Err(err) => {
+                checks.push(Check::problem(
+                    "database",
+                    Status::Fail,
+                    format!("{err:#}"),
+                    "check the permissions of the database file and its directory",
+                ));
+                None
+            }
+        },
+        Ok(db_path) => {
+            checks.push(Check::problem(
+                "database",
+                Status::Warn,
+                format!("{} does not exist yet", db_path.display()),
+                "it is created by the first `migrator up`, pass --no-create to forbid that",
+            ));
+            None
+        }
+        Err(err) => {
+            checks.push(Check::problem(
+                "database",
+                Status::Fail,
+                format!("{err:#}"),
+                format!(
+                    "pass --database, or set DATABASE_PATH or database_path in {}",
+                    config_path.display()
+                ),
+            ));
+            None
+        }
+    };
+
+    match (&migrations, &conn) {
+        (Some(migrations), Some(conn)) => checks.push(check_version(migrations, conn)),
+        _ => checks.push(Check::skip("database version")),
+    }
+    match &conn {
+        Some(conn) => checks.push(check_lock(conn)),
+        None => checks.push(Check::skip("migration lock")),
+    }
+    checks.push(check_sqlite_version());
+
+    let name_width = checks
+        .iter()
+        .map(|c| c.name.len())
+        .max()
+        .unwrap_or_default();
+    for check in &checks {
+        println!(
+            "[{:<4}] {:<name_width$}  {}",
+            check.status, check.name, check.detail
+        );
+        if let Some(hint) = &check.hint {
+            println!("       {:<name_width$}  hint: {hint}", "");
+        }
+    }
+
+    let failed = checks.iter().filter(|c| c.status == Status::Fail).count();
+    if failed > 0 {
+        anyhow::bail!("{failed} checks failed");
+    }
+
+    Ok(())
+}
+
+fn check_config(path: &Path, config: Result<(), &anyhow::Error>) -> Check {
+    const NAME: &str = "config file";
+    match config {
+        Ok(()) => Check::ok(NAME, path.display().to_string()),
+        Err(err)
+            if err
+                .downcast_ref::<std::io::Error>()
+                .is_some_and(|e| e.kind() == std::io::ErrorKind::NotFound) =>
+        {
+            Check::ok(
+                NAME,
+                format!("no {}, using arguments and environment", path.display()),
+            )
+        }
+        Err(err) => Check::problem(
+            NAME,
+            Status::Fail,
+            format!("{err:#}"),
+            format!("fix the syntax of {}", path.display()),
+        ),
+    }
+}
+
+fn check_migrations(loaded: &Result<Migrations>) -> Vec<Check> {
+    let migrations = match loaded {
+        Ok(migrations) => migrations,
+        Err(err) => {
+            let (id_problems, file_problems) = match err.downcast_ref::<Error>() {
+                Some(Error::InvalidDirectory(problems)) => problems
+                    .iter()
+                    .map(ToString::to_string)
+                    .zip(
+                        problems
+                            .iter()
+                            .map(|p| matches!(p.error, Error::InvalidId(_))),
+                    )
+                    .partition::<Vec<_>, _>(|(_, is_id)| *is_id),
+                _ => (Vec::new(), vec![(format!("{err:#}"), false)]),
+            };
+            let join = |problems: Vec<(String, bool)>| {
+                problems
+                    .into_iter()
+                    .map(|(problem, _)| problem)
+                    .collect::<Vec<_>>()
+                    .join("; ")
+            };
+
+            return vec![
+                if file_problems.is_empty() {
+                    Check::ok("migration files", "readable")
+                } else {
+                    Check::problem(
+                        "migration files",
+                        Status::Fail,
+                        join(file_problems),
+                        "every migration folder needs a readable, non-empty up.sql",
+                    )
+                },
+                if id_problems.is_empty() {
+                    Check::ok("migration ids", "unique and consecutive")
+                } else {
+                    Check::problem(
+                        "migration ids",
+                        Status::Fail,
+                        join(id_problems),
+                        "rename the folders so that ids start at 1 and follow each other",
+                    )
+                },
+                Check::skip("down migrations"),
+            ];
+        }
+    };
+
+    let count = migrations.migrations().len();
+    let last = usize::from(migrations.max_version());
+    let missing_down = migrations
+        .iter()
+        .filter(|(_, m)| !m.get_irreversible())
+        .filter(|(_, m)| m.get_down().is_none_or(|down| down.trim().is_empty()))
+        .map(|(version, _)| format!("{version:04}"))
+        .collect::<Vec<_>>();
+    let irreversible = migrations
+        .migrations()
+        .iter()
+        .filter(|m| m.get_irreversible())
+        .count();
+
+    vec![
+        Check::ok("migration files", format!("{count} migrations")),
+        Check::ok(
+            "migration ids",
+            if last == count {
+                format!("1 to {count}, consecutive")
+            } else {
+                format!("up to {last}, with gaps")
+            },
+        ),
+        if missing_down.is_empty() && irreversible == 0 {
+            Check::ok("down migrations", "every migration can be reverted")
+        } else if missing_down.is_empty() {
+            Check::ok(
+                "down migrations",
+                format!(
+                    "every migration can be reverted, except {irreversible} marked irreversible"
+                ),
+            )
+        } else {
+            Check::problem(
+                "down migrations",
+                Status::Warn,
+                format!("no down.sql for {}", missing_down.join(", ")),
+                "add a down.sql, or accept that `down` can't revert them",
+            )
+        },
+    ]
+}
+
+fn check_version(migrations: &Migrations, conn: &Connection) -> Check {
+    const NAME: &str = "database version";
+    match migrations.current_version(conn) {
+        Ok(SchemaVersion::Outside(v)) => Check::problem(
+            NAME,
+            Status::Fail,
+            format!(
+                "{v} is beyond the latest migration {}",
+                usize::from(migrations.max_version())
+            ),
+            "the database was migrated from a newer migration directory, update your checkout",
+        ),
+        Ok(version) => Check::ok(
+            NAME,
+            format!(
+                "{} of {}",
+                usize::from(version),
+                usize::from(migrations.max_version())
+            ),
+        ),
+        Err(err) => Check::problem(
+            NAME,
+            Status::Fail,
+            format!("{err:#}"),
+            "check that the version storage matches the version_storage config key",
+        ),
+    }
+}
+
+fn check_lock(conn: &Connection) -> Check {
+    const NAME: &str = "migration lock";
+    match lock::status(conn) {
+        Ok(None) => Check::ok(NAME, "not held"),
+        Ok(Some(info)) => Check::problem(
+            NAME,
+            Status::Fail,
+            format!(
+                "held by {} since {} ({})",
+                info.holder, info.acquired_at, info.reason
+            ),
+            "if no migration is running, release it with `migrator unlock --force`",
+        ),
+        Err(err) => Check::problem(
+            NAME,
+            Status::Fail,
+            format!("{err:#}"),
+            "check the _migrator_lock table",
+        ),
+    }
+}
+
+fn check_sqlite_version() -> Check {
+    const NAME: &str = "sqlite version";
+    if rusqlite::version_number() < MIN_SQLITE_VERSION {
+        Check::problem(
+            NAME,
+            Status::Fail,
+            format!("{} is older than 3.27.0", rusqlite::version()),
+            "upgrade the SQLite library the migrator is linked with",
+        )
+    } else {
+        Check::ok(NAME, rusqlite::version())
+    }
+}
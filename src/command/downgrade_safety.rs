@@ -0,0 +1,49 @@
+use std::{fs, path::Path};
+
+use anyhow::{Context, Result};
+use rusqlite::Connection;
+use sqlite_migrator::{downgrade, migration::Migrations};
+
+/// Check that the pending migrations don't break the previous release,
+/// described by the `queries` it runs and/or its `schema` dump.
+pub fn verify_downgrade_safety(
+    migrations: &Migrations,
+    conn: &Connection,
+    queries: Option<&Path>,
+    schema: Option<&Path>,
+) -> Result<()> {
+    let migrated = migrations.migrated_schema(conn)?;
+
+    let mut breakages = Vec::new();
+    if let Some(path) = queries {
+        let sql = fs::read_to_string(path)
+            .context(format!("Failed to read queries {}", path.display()))?;
+        breakages.extend(downgrade::broken_queries(
+            &migrated,
+            &downgrade::split_queries(&sql),
+        ));
+    }
+    if let Some(path) = schema {
+        let sql = fs::read_to_string(path)
+            .context(format!("Failed to read schema {}", path.display()))?;
+        let previous = Connection::open_in_memory()?;
+        previous
+            .execute_batch(&sql)
+            .context(format!("Failed to load schema {}", path.display()))?;
+        breakages.extend(downgrade::removed_objects(&previous, &migrated)?);
+    }
+
+    if breakages.is_empty() {
+        println!("Pending migrations keep the schema readable by the previous release");
+        return Ok(());
+    }
+
+    for breakage in &breakages {
+        println!("{breakage}");
+    }
+
+    anyhow::bail!(
+        "Pending migrations break the previous release in {} places",
+        breakages.len()
+    )
+}
@@ -0,0 +1,71 @@
+use std::{fmt::Write as _, fs, path::Path};
+
+use anyhow::{Context, Result};
+use sqlite_migrator::{migration::Migrations, statements};
+
+/// Write the up SQL of the migrations after version `from`, up to `to` or the
+/// latest one, with the rows of their data files, as a single script for DBAs who apply changes by hand. Each
+/// migration is preceded by a comment stating the version the database must
+/// be at, and the script ends by setting the new version.
+pub fn export(
+    migrations: &Migrations,
+    from: usize,
+    to: Option<usize>,
+    output: Option<&Path>,
+) -> Result<()> {
+    let to = to.unwrap_or(migrations.max_version().into());
+    if to < from {
+        anyhow::bail!("Version {to} is lower than the starting version {from}");
+    }
+    let exported = migrations
+        .iter()
+        .filter(|(v, _)| (from + 1..=to).contains(v))
+        .collect::<Vec<_>>();
+    let Some((last, _)) = exported.last() else {
+        anyhow::bail!("No migration between versions {from} and {to}");
+    };
+
+    let mut script = format!(
+        "-- Migrations {} to {last} exported at {}\n-- The database must be at version {from}\nBEGIN;\n",
+        exported[0].0,
+        chrono::Local::now().format("%Y-%m-%d %H:%M:%S")
+    );
+    let mut version = from;
+    for (v, m) in &exported {
+        write!(
+            script,
+            "\n-- Migration {v:04} {}\n-- Requires version {version}, leaves the database at version {v}\n",
+            m.get_comment().unwrap_or_default(),
+        )?;
+        for statement in statements::split(&m.get_up_script()) {
+            writeln!(script, "{}", terminated(&statement.sql))?;
+        }
+        version = *v;
+    }
+    write!(
+        script,
+        "\n{}\nCOMMIT;\n",
+        migrations.get_version_storage().set_sql(*last)
+    )?;
+
+    match output {
+        Some(path) => {
+            fs::write(path, script).context(format!("Failed to write {}", path.display()))?
+        }
+        None => print!("{script}"),
+    }
+
+    Ok(())
+}
+
+// `sql` ending with a semicolon, so that it doesn't run into the next
+// statement of the script
+fn terminated(sql: &str) -> String {
+    if sql.ends_with(';') {
+        sql.to_owned()
+    } else if sql.lines().last().is_some_and(|line| line.contains("--")) {
+        format!("{sql}\n;")
+    } else {
+        format!("{sql};")
+    }
+}
@@ -0,0 +1,119 @@
+use std::{fmt::Write as _, fs, path::Path};
+
+use anyhow::{Context, Result};
+use sqlite_migrator::migration::Migrations;
+
+#[derive(clap::ValueEnum, Debug, Clone, Copy)]
+pub enum GenerateTarget {
+    /// A Rust module embedding the migrations
+    Rust,
+}
+
+/// Write the migrations as code for `target`, to `output` or stdout
+pub fn generate(
+    migrations: &Migrations,
+    source: &Path,
+    target: GenerateTarget,
+    output: Option<&Path>,
+) -> Result<()> {
+    let code = match target {
+        GenerateTarget::Rust => rust_module(migrations, source)?,
+    };
+
+    match output {
+        Some(path) => {
+            fs::write(path, code).context(format!("Failed to write {}", path.display()))?
+        }
+        None => print!("{code}"),
+    }
+
+    Ok(())
+}
+
+// Module holding the SQL of every migration in consts, and a `migrations()`
+// function building the set with `Migrations::from_slices`. Only the SQL is
+// kept, data files included as INSERT statements: hooks and metadata of
+// `migration.yaml` are left out.
+fn rust_module(migrations: &Migrations, source: &Path) -> Result<String> {
+    // `from_slices` numbers migrations by position
+    if let Some((_, (version, _))) = migrations
+        .iter()
+        .enumerate()
+        .find(|(i, (v, _))| *v != i + 1)
+    {
+        anyhow::bail!(
+            "Migration {version} leaves a gap in the ids, embedded migrations need consecutive ids"
+        );
+    }
+
+    let mut code = format!(
+        "//! Migrations of `{}`, generated by `migrator generate rust`. Regenerate\n\
+         //! this file rather than editing it.\n\
+         use sqlite_migrator::migration::Migrations;\n",
+        source.display()
+    );
+    let mut entries = String::new();
+    for (version, m) in migrations.iter() {
+        let name = m.get_comment().unwrap_or_default();
+        let ident = const_name(version, name);
+        write!(
+            code,
+            "\n/// Up SQL of `{name}`\npub const {ident}_UP: &str = {};\n",
+            raw_string(&m.get_up_script())
+        )?;
+        let down = match m.get_down() {
+            Some(down) => {
+                write!(
+                    code,
+                    "\n/// Down SQL of `{name}`\npub const {ident}_DOWN: &str = {};\n",
+                    raw_string(down)
+                )?;
+                format!("Some({ident}_DOWN)")
+            }
+            None => "None".to_owned(),
+        };
+        writeln!(entries, "        ({name:?}, {ident}_UP, {down}),")?;
+    }
+    write!(
+        code,
+        "\n/// Every migration, in version order\n\
+         pub fn migrations() -> Migrations {{\n    Migrations::from_slices(&[\n{entries}    ])\n}}\n"
+    )?;
+
+    Ok(code)
+}
+
+// `M0001_ADD_USERS` for migration 1 `0001-add_users`
+fn const_name(version: usize, name: &str) -> String {
+    let name = name
+        .trim_start_matches(|c: char| c.is_ascii_digit())
+        .chars()
+        .map(|c| {
+            if c.is_ascii_alphanumeric() {
+                c.to_ascii_uppercase()
+            } else {
+                '_'
+            }
+        })
+        .collect::<String>();
+    let name = name.trim_matches('_');
+
+    if name.is_empty() {
+        format!("M{version:04}")
+    } else {
+        format!("M{version:04}_{name}")
+    }
+}
+
+// Raw string literal of `sql`, with enough `#` to contain any `"#` sequence
+fn raw_string(sql: &str) -> String {
+    let hashes = sql
+        .split('"')
+        .skip(1)
+        .map(|after| after.chars().take_while(|c| *c == '#').count() + 1)
+        .max()
+        .unwrap_or(0);
+    let hashes = "#".repeat(hashes);
+
+    format!("r{hashes}\"{sql}\"{hashes}")
+}
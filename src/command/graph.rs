@@ -0,0 +1,150 @@
+use anyhow::Result;
+use rusqlite::Connection;
+use sqlite_migrator::{
+    migration::Migrations,
+    schema::{self, ForeignKey, Table},
+};
+
+#[derive(clap::ValueEnum, Debug, Clone, Copy, Default)]
+pub enum GraphFormat {
+    /// Graphviz dot
+    #[default]
+    Dot,
+    /// Mermaid entity relationship diagram
+    Mermaid,
+}
+
+/// Print a diagram of the tables and foreign keys of the schema the
+/// migrations produce.
+pub fn graph(migrations: &Migrations, format: GraphFormat) -> Result<()> {
+    let migrated = migrations.migrated_schema(&Connection::open_in_memory()?)?;
+    let tables = schema::tables(&migrated)?;
+
+    match format {
+        GraphFormat::Dot => print_dot(&tables),
+        GraphFormat::Mermaid => print_mermaid(&tables),
+    }
+
+    Ok(())
+}
+
+fn print_dot(tables: &[Table]) {
+    println!("digraph schema {{");
+    println!("    rankdir=LR;");
+    println!("    node [shape=record];");
+    for table in tables {
+        let columns = table
+            .columns
+            .iter()
+            .map(|c| {
+                let pk = if c.primary_key { " (PK)" } else { "" };
+                // left-aligned lines
+                format!(
+                    "{}\\l",
+                    dot_record(format!("{} {}{pk}", c.name, c.decl_type).trim_end())
+                )
+            })
+            .collect::<String>();
+        println!(
+            "    {} [label=\"{{{}|{columns}}}\"];",
+            dot_id(&table.name),
+            dot_record(&table.name),
+        );
+    }
+    for table in tables {
+        for key in &table.foreign_keys {
+            println!(
+                "    {} -> {} [label={}];",
+                dot_id(&table.name),
+                dot_id(&key.table),
+                dot_id(&key_label(key))
+            );
+        }
+    }
+    println!("}}");
+}
+
+fn print_mermaid(tables: &[Table]) {
+    println!("erDiagram");
+    for table in tables {
+        println!("    {} {{", mermaid_id(&table.name));
+        for c in &table.columns {
+            let decl_type = if c.decl_type.is_empty() {
+                "ANY"
+            } else {
+                &c.decl_type
+            };
+            let mut keys = Vec::new();
+            if c.primary_key {
+                keys.push("PK");
+            }
+            if table
+                .foreign_keys
+                .iter()
+                .any(|k| k.columns.iter().any(|(from, _)| *from == c.name))
+            {
+                keys.push("FK");
+            }
+            let line = format!(
+                "        {} {} {}",
+                mermaid_id(decl_type),
+                mermaid_id(&c.name),
+                keys.join(", ")
+            );
+            println!("{}", line.trim_end());
+        }
+        println!("    }}");
+    }
+    for table in tables {
+        for key in &table.foreign_keys {
+            println!(
+                "    {} }}o--|| {} : \"{}\"",
+                mermaid_id(&table.name),
+                mermaid_id(&key.table),
+                key_label(key).replace('"', "'")
+            );
+        }
+    }
+}
+
+// `column -> referenced column` pairs of a foreign key
+fn key_label(key: &ForeignKey) -> String {
+    key.columns
+        .iter()
+        .map(|(from, to)| match to {
+            Some(to) => format!("{from} -> {to}"),
+            None => from.clone(),
+        })
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+// Quoted dot identifier
+fn dot_id(name: &str) -> String {
+    format!("\"{}\"", name.replace('\\', "\\\\").replace('"', "\\\""))
+}
+
+// Text of a record label, escaping the characters delimiting fields
+fn dot_record(text: &str) -> String {
+    text.chars()
+        .fold(String::with_capacity(text.len()), |mut escaped, c| {
+            if matches!(c, '{' | '}' | '|' | '<' | '>' | '"' | '\\') {
+                escaped.push('\\');
+            }
+            escaped.push(c);
+            escaped
+        })
+}
+
+// Mermaid identifier, replacing the characters it doesn't accept
+fn mermaid_id(name: &str) -> String {
+    name.chars()
+        .map(|c| {
+            if c.is_ascii_alphanumeric() || matches!(c, '_' | '-' | '(' | ')' | '[' | ']') {
+                c
+            } else {
+                '_'
+            }
+        })
+        .collect()
+}
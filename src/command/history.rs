@@ -0,0 +1,131 @@
+use std::{fs::File, path::Path};
+
+use anyhow::{Context, Result};
+use rusqlite::{Connection, OpenFlags};
+use sqlite_migrator::{
+    failures,
+    history::{self, HistoryEntry},
+};
+
+/// Print the applied migrations recorded in the history table.
+pub fn history(conn: &Connection, json: bool) -> Result<()> {
+    let entries = history::entries(conn)?;
+
+    if json {
+        println!("{}", serde_json::to_string_pretty(&entries)?);
+        return Ok(());
+    }
+
+    if entries.is_empty() {
+        println!("No migration recorded in history");
+        return Ok(());
+    }
+
+    for entry in entries {
+        print_entry(&entry);
+    }
+
+    Ok(())
+}
+
+/// Print the failed migration attempts recorded in the failures table.
+pub fn history_failures(conn: &Connection, json: bool) -> Result<()> {
+    let failures = failures::failures(conn)?;
+
+    if json {
+        println!("{}", serde_json::to_string_pretty(&failures)?);
+        return Ok(());
+    }
+
+    if failures.is_empty() {
+        println!("No failed migration recorded");
+        return Ok(());
+    }
+
+    for failure in failures {
+        println!(
+            "{:04} {:<32} {:<4} {} {}@{} with {}",
+            failure.version,
+            failure.name,
+            failure.direction,
+            failure.failed_at,
+            failure.failed_by,
+            failure.hostname,
+            failure.tool_version
+        );
+        println!("     {}", failure.error);
+        if let Some(statement) = &failure.statement {
+            println!("     {}", statement.trim().replace('\n', "\n     "));
+        }
+    }
+
+    Ok(())
+}
+
+/// Print the differences between two histories, each read from either a
+/// `history --json` export or a database file. Fails if they differ.
+pub fn history_diff(left: &Path, right: &Path) -> Result<()> {
+    let left_entries = load(left)?;
+    let right_entries = load(right)?;
+    let diff = history::diff(&left_entries, &right_entries);
+
+    if diff.is_empty() {
+        println!("Histories are identical");
+        return Ok(());
+    }
+
+    for (path, entries) in [(left, &diff.only_left), (right, &diff.only_right)] {
+        if !entries.is_empty() {
+            println!("Only in {}:", path.display());
+            for entry in entries {
+                print_entry(entry);
+            }
+        }
+    }
+
+    if let Some((left_order, right_order)) = &diff.order {
+        println!("Migrations were applied in a different order:");
+        println!("  {}: {:?}", left.display(), left_order);
+        println!("  {}: {:?}", right.display(), right_order);
+    }
+
+    anyhow::bail!("Histories differ")
+}
+
+fn print_entry(entry: &HistoryEntry) {
+    let host = if entry.hostname.is_empty() {
+        String::new()
+    } else {
+        format!("@{}", entry.hostname)
+    };
+    let tool = if entry.tool_version.is_empty() {
+        String::new()
+    } else {
+        format!(" with {}", entry.tool_version)
+    };
+    println!(
+        "{:04} {:<32} {} {:>8}ms {}{host}{tool}{}",
+        entry.version,
+        entry.name,
+        entry.applied_at,
+        entry.duration_ms,
+        entry.applied_by,
+        if entry.skipped { " (skipped)" } else { "" }
+    );
+    if !entry.description.is_empty() {
+        println!("     {}", entry.description);
+    }
+}
+
+// Read a history from a JSON export, or from a database otherwise
+fn load(path: &Path) -> Result<Vec<HistoryEntry>> {
+    if path.extension().is_some_and(|ext| ext == "json") {
+        let file = File::open(path).context(format!("Could not open {}", path.display()))?;
+        return serde_json::from_reader(file)
+            .context(format!("Could not parse history export {}", path.display()));
+    }
+
+    let conn = Connection::open_with_flags(path, OpenFlags::SQLITE_OPEN_READ_ONLY)
+        .context(format!("Could not open database {}", path.display()))?;
+    history::entries(&conn)
+}
@@ -0,0 +1,106 @@
+use std::{fs, path::Path};
+
+use anyhow::{Context, Result};
+use tracing::{info, warn};
+
+use super::{create, CreateOptions};
+
+#[derive(clap::ValueEnum, Debug, Clone, Copy, Default)]
+pub enum ImportFormat {
+    /// refinery's `V<version>__<name>.sql` files
+    #[default]
+    Refinery,
+}
+
+struct Imported {
+    version: u64,
+    file_name: String,
+    name: String,
+    sql: String,
+}
+
+/// Convert the migrations of another tool found in `from` into migration
+/// folders of `migration_dir`, created after its existing migrations in the
+/// original order
+pub fn import(migration_dir: &Path, from: &Path, format: ImportFormat) -> Result<()> {
+    let mut imported = match format {
+        ImportFormat::Refinery => read_refinery(from)?,
+    };
+    imported.sort_by_key(|m| m.version);
+    if let Some(pair) = imported.windows(2).find(|w| w[0].version == w[1].version) {
+        anyhow::bail!(
+            "{} and {} have the same version {}",
+            pair[0].file_name,
+            pair[1].file_name,
+            pair[0].version
+        );
+    }
+    if imported.is_empty() {
+        anyhow::bail!("No migrations to import in {}", from.display());
+    }
+
+    for m in &imported {
+        let options = CreateOptions {
+            up_sql: Some(format!("-- Imported from {}\n{}", m.file_name, m.sql)),
+            down_sql: Some(format!(
+                "-- {} had no down migration, write the SQL reverting it here",
+                m.file_name
+            )),
+            ..Default::default()
+        };
+        create(migration_dir, &m.name, &options)
+            .with_context(|| format!("Could not import {}", m.file_name))?;
+    }
+    info!(
+        "Imported {} migrations into {}, their down.sql are stubs to fill in",
+        imported.len(),
+        migration_dir.display()
+    );
+
+    Ok(())
+}
+
+// `V<version>__<name>.sql` files; refinery's unversioned `U` migrations and
+// Rust migrations have no equivalent
+fn read_refinery(from: &Path) -> Result<Vec<Imported>> {
+    let mut imported = Vec::new();
+    for entry in fs::read_dir(from).with_context(|| format!("Could not read {}", from.display()))? {
+        let path = entry?.path();
+        let Some(file_name) = path.file_name().and_then(|n| n.to_str()) else {
+            continue;
+        };
+        let Some((prefix, name)) = file_name.split_once("__") else {
+            warn!("Skipping {file_name}, not a refinery migration");
+            continue;
+        };
+        let mut chars = prefix.chars();
+        let kind = chars.next();
+        let Ok(version) = chars.as_str().parse::<u64>() else {
+            warn!("Skipping {file_name}, not a refinery migration");
+            continue;
+        };
+        match kind {
+            Some('V' | 'v') => {}
+            Some('U' | 'u') => anyhow::bail!(
+                "{file_name}: unversioned migrations can't be imported, rename it with a V prefix"
+            ),
+            _ => {
+                warn!("Skipping {file_name}, not a refinery migration");
+                continue;
+            }
+        }
+        let Some(name) = name.strip_suffix(".sql") else {
+            anyhow::bail!("{file_name}: only SQL migrations can be imported");
+        };
+
+        imported.push(Imported {
+            version,
+            file_name: file_name.to_owned(),
+            name: name.to_owned(),
+            sql: fs::read_to_string(&path)
+                .with_context(|| format!("Could not read {}", path.display()))?,
+        });
+    }
+
+    Ok(imported)
+}
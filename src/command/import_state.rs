@@ -0,0 +1,72 @@
+use std::{fs::File, io, path::Path};
+
+use anyhow::{Context, Result};
+use chrono::NaiveDateTime;
+use rusqlite::Connection;
+use sqlite_migrator::{history::HistoryEntry, migration::Migrations};
+
+/// Table in which sqlx records the migrations it applied
+const SQLX_TABLE: &str = "_sqlx_migrations";
+
+/// Record migrations applied by an external executor, read as a JSON list of
+/// history entries (the `history --json` format) from `path`, or stdin for `-`.
+pub fn import_state(migrations: &Migrations, conn: &mut Connection, path: &Path) -> Result<()> {
+    let entries: Vec<HistoryEntry> = if path == Path::new("-") {
+        serde_json::from_reader(io::stdin().lock()).context("Could not parse state from stdin")?
+    } else {
+        let file = File::open(path).context(format!("Could not open {}", path.display()))?;
+        serde_json::from_reader(file).context(format!("Could not parse {}", path.display()))?
+    };
+
+    Ok(migrations.import_state(conn, &entries)?)
+}
+
+/// Record the migrations sqlx successfully applied to the database, read
+/// from its `_sqlx_migrations` table, so that a project can switch tools
+/// without re-baselining. sqlx checksums aren't compatible and aren't kept.
+pub fn import_sqlx_state(migrations: &Migrations, conn: &mut Connection) -> Result<()> {
+    let entries = conn
+        .prepare(&format!(
+            "SELECT version, description, installed_on, execution_time FROM {SQLX_TABLE} \
+             WHERE success ORDER BY version"
+        ))
+        .and_then(|mut stmt| {
+            stmt.query_map([], |row| {
+                let version: i64 = row.get(0)?;
+                let description: String = row.get(1)?;
+                let installed_on: String = row.get(2)?;
+                let execution_ns: i64 = row.get(3)?;
+                Ok((version, description, installed_on, execution_ns))
+            })?
+            .collect::<rusqlite::Result<Vec<_>>>()
+        })
+        .context(format!("Could not read the {SQLX_TABLE} table"))?
+        .into_iter()
+        .map(|(version, description, installed_on, execution_ns)| {
+            let version = usize::try_from(version)
+                .context(format!("Invalid sqlx migration version {version}"))?;
+            let name = migrations
+                .get(version)
+                .and_then(|m| m.get_comment())
+                .unwrap_or(&description)
+                .to_owned();
+            Ok(HistoryEntry {
+                version,
+                name,
+                applied_at: NaiveDateTime::parse_from_str(&installed_on, "%Y-%m-%d %H:%M:%S")
+                    .map(|t| t.and_utc().to_rfc3339())
+                    .unwrap_or(installed_on),
+                duration_ms: u64::try_from(execution_ns / 1_000_000).unwrap_or_default(),
+                applied_by: String::new(),
+                checksum: String::new(),
+                skipped: false,
+                schema_hash: String::new(),
+                hostname: String::new(),
+                tool_version: "sqlx".to_owned(),
+                description,
+            })
+        })
+        .collect::<Result<Vec<_>>>()?;
+
+    Ok(migrations.import_state(conn, &entries)?)
+}
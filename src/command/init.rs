@@ -0,0 +1,216 @@
+use std::{
+    fmt::Write as _,
+    fs,
+    path::{Path, PathBuf},
+    time::Duration,
+};
+
+use anyhow::{Context, Result};
+use rusqlite::Connection;
+use sqlite_migrator::{
+    history::{self, HistoryEntry},
+    migration::Migrations,
+    schema,
+};
+use tracing::info;
+
+use super::{create, CreateOptions};
+
+/// Name of the migration reverse-engineered from an existing database, or
+/// created empty
+const INITIAL_NAME: &str = "initial";
+
+/// Migration directory written to new config files when none is given
+pub const DEFAULT_SOURCE: &str = "migrations";
+
+/// Database written to new config files when none is given
+pub const DEFAULT_DATABASE: &str = "app.db";
+
+/// Set up a project: create `migration_dir` and a commented config file at
+/// `config_path` pointing to it and to `database`, unless one exists, and an
+/// empty first migration with `initial`
+pub fn scaffold(
+    config_path: &Path,
+    migration_dir: &Path,
+    database: &Path,
+    initial: bool,
+) -> Result<()> {
+    // before writing anything
+    if initial {
+        ensure_empty(migration_dir)?;
+    }
+
+    fs::create_dir_all(migration_dir).context(format!(
+        "Failed to create migration directory {}",
+        migration_dir.display()
+    ))?;
+    println!("Migration directory {}", migration_dir.display());
+
+    if config_path.exists() {
+        println!("Config file {} exists, left as is", config_path.display());
+    } else {
+        let quote = |path: &Path| serde_yaml::to_string(&path.display().to_string());
+        let config = format!(
+            "# Config of the migrator, validated by `migrator config check`.
+# Values can use environment variables: ${{VAR}} or ${{VAR:-default}}.
+
+# Directory of the migration folders
+source_path: {}
+# Database to migrate, overridden by --database or DATABASE_PATH
+database_path: {}
+
+# Require lowercase snake_case migration names
+# strict_names: true
+# Back up the database before applying migrations
+# backup: true
+# Ask for confirmation before migrating this database
+# protected: true
+# Checksum algorithm of the history table: sha256 or xxh3
+# checksum_algorithm: sha256
+# Transaction of the migrations: deferred, immediate or exclusive
+# transaction_mode: immediate
+# Roll back any migration running longer than this
+# timeout_secs: 600
+# Shell commands run after every successful `up`
+# after_successful_up:
+#   - ./scripts/dump-schema.sh
+",
+            quote(migration_dir)?.trim_end(),
+            quote(database)?.trim_end(),
+        );
+        fs::write(config_path, config)
+            .context(format!("Failed to write {}", config_path.display()))?;
+        println!("Config file {}", config_path.display());
+    }
+
+    if initial {
+        create(
+            migration_dir,
+            INITIAL_NAME,
+            &CreateOptions {
+                id: Some(1),
+                ..Default::default()
+            },
+        )?;
+        println!(
+            "Initial migration {}",
+            migration_dir.join("0001-initial").display()
+        );
+    }
+
+    Ok(())
+}
+
+/// Fail unless `migration_dir` is missing or empty, as the initial migration
+/// must be the first one
+pub fn ensure_empty(migration_dir: &Path) -> Result<()> {
+    if migration_dir.exists()
+        && fs::read_dir(migration_dir)
+            .context("Failed to read migration directory")?
+            .next()
+            .is_some()
+    {
+        anyhow::bail!(
+            "Migration directory {} is not empty, the initial migration must be the first one",
+            migration_dir.display()
+        );
+    }
+
+    Ok(())
+}
+
+/// Write the schema of the existing database `conn` (tables, indexes, views
+/// and triggers) as the first migration of the empty `migration_dir`, with a
+/// down migration dropping it all. Returns the folder of the migration.
+pub fn init_from_db(migration_dir: &Path, conn: &Connection) -> Result<PathBuf> {
+    ensure_empty(migration_dir)?;
+
+    let (up, down, objects) = schema_sql(conn)?;
+    create(
+        migration_dir,
+        INITIAL_NAME,
+        &CreateOptions {
+            id: Some(1),
+            up_sql: Some(up),
+            down_sql: Some(down),
+            ..Default::default()
+        },
+    )?;
+    info!(
+        "Wrote {objects} schema objects to the initial migration in {}",
+        migration_dir.display()
+    );
+
+    Ok(migration_dir.join(format!("0001-{INITIAL_NAME}")))
+}
+
+/// Up SQL creating the schema of `conn` (tables, indexes, views and
+/// triggers), down SQL dropping it all, and the number of schema objects
+pub(super) fn schema_sql(conn: &Connection) -> Result<(String, String, usize)> {
+    // shadow tables of virtual tables are created along with them. They are
+    // told by the suffixes the FTS3, FTS4, FTS5 and R*Tree modules give them,
+    // as `pragma_table_list` needs SQLite 3.37.
+    let mut stmt = conn.prepare(
+        "SELECT type, name, sql FROM sqlite_master AS o
+         WHERE sql IS NOT NULL AND name NOT LIKE 'sqlite_%' AND tbl_name NOT LIKE '\\_migrator\\_%' ESCAPE '\\'
+         AND NOT EXISTS (
+             SELECT 1 FROM sqlite_master AS v
+             WHERE o.type = 'table' AND v.type = 'table' AND v.sql LIKE 'CREATE VIRTUAL TABLE%'
+             AND substr(o.name, 1, length(v.name) + 1) = v.name || '_'
+             AND substr(o.name, length(v.name) + 2) IN (
+                 'data', 'idx', 'content', 'docsize', 'config', 'segments', 'segdir', 'stat',
+                 'node', 'rowid', 'parent'
+             )
+         )
+         ORDER BY o.rowid",
+    )?;
+    let objects = stmt
+        .query_map([], |row| {
+            Ok((
+                row.get::<_, String>(0)?,
+                row.get::<_, String>(1)?,
+                row.get::<_, String>(2)?,
+            ))
+        })?
+        .collect::<rusqlite::Result<Vec<_>>>()
+        .context("Could not read the schema of the database")?;
+    if objects.is_empty() {
+        anyhow::bail!("The database has no schema to start from");
+    }
+
+    let mut up = String::new();
+    let mut down = String::new();
+    for (_, _, sql) in &objects {
+        writeln!(up, "{sql};")?;
+    }
+    for (kind, name, _) in objects.iter().rev() {
+        writeln!(
+            down,
+            "DROP {} IF EXISTS \"{}\";",
+            kind.to_uppercase(),
+            name.replace('"', "\"\"")
+        )?;
+    }
+
+    Ok((up, down, objects.len()))
+}
+
+/// Record the initial migration as applied to the database it was
+/// reverse-engineered from, so that `up` starts from the next one
+pub fn baseline(migrations: &Migrations, conn: &mut Connection) -> Result<()> {
+    let (version, m) = migrations
+        .iter()
+        .next()
+        .context("The initial migration was not loaded")?;
+    let mut entry = HistoryEntry::new(
+        version,
+        m.get_comment().unwrap_or_default(),
+        &m.checksum(),
+        Duration::ZERO,
+    );
+    entry.description = m.get_description().unwrap_or_default().to_owned();
+
+    migrations.import_state(conn, &[entry])?;
+    // the database is the reference the later schema checks compare with
+    history::set_schema_hash(conn, version, &schema::hash(conn)?)
+}
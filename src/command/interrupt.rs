@@ -0,0 +1,74 @@
+//! Ctrl-C handling: the migration running is interrupted and rolled back, and
+//! the migration lock released, rather than leaving users unsure of what was
+//! committed.
+use std::sync::{
+    atomic::{AtomicBool, Ordering},
+    Arc, Mutex, OnceLock,
+};
+
+use anyhow::Result;
+use rusqlite::InterruptHandle;
+
+/// Exit code after a Ctrl-C, the one shells report for SIGINT
+pub const INTERRUPTED_EXIT_CODE: i32 = 130;
+
+static FLAG: OnceLock<Arc<AtomicBool>> = OnceLock::new();
+static MIGRATING: Mutex<Option<InterruptHandle>> = Mutex::new(None);
+
+/// Flag set on Ctrl-C, to pass to `Migrations::interrupt_flag`
+pub fn interrupt_flag() -> Arc<AtomicBool> {
+    FLAG.get_or_init(Arc::default).clone()
+}
+
+/// Install the Ctrl-C handler. While the migration lock is held, the first
+/// Ctrl-C interrupts the running statement and stops the migration, which
+/// returns through the lock release; otherwise, or on a second Ctrl-C, the
+/// process exits at once.
+pub fn install_interrupt_handler() -> Result<()> {
+    ctrlc::set_handler(|| {
+        let again = interrupt_flag().swap(true, Ordering::SeqCst);
+        let migrating = MIGRATING.lock().ok().and_then(|handle| {
+            handle.as_ref().map(|h| {
+                h.interrupt();
+            })
+        });
+        if again || migrating.is_none() {
+            std::process::exit(INTERRUPTED_EXIT_CODE);
+        }
+        eprintln!("Interrupted, rolling back the migration and releasing the migration lock...");
+    })?;
+
+    Ok(())
+}
+
+/// Run `f`, which holds the migration lock on the connection of `handle`,
+/// letting Ctrl-C interrupt that connection meanwhile instead of exiting
+/// with the lock held
+pub fn interruptible<T>(handle: InterruptHandle, f: impl FnOnce() -> T) -> T {
+    set_migrating(Some(handle));
+    let res = f();
+    set_migrating(None);
+    res
+}
+
+/// Exit with [`INTERRUPTED_EXIT_CODE`] if Ctrl-C was hit during the migration,
+/// reporting what happened to it, else return `res`
+pub fn exit_if_interrupted<T>(res: Result<T>) -> Result<T> {
+    if !interrupt_flag().load(Ordering::SeqCst) {
+        return res;
+    }
+
+    match res {
+        Err(err) => eprintln!(
+            "Error: {err:#}\nThe migration transaction was rolled back and the migration lock released."
+        ),
+        Ok(_) => eprintln!("Interrupted after the migration was committed."),
+    }
+    std::process::exit(INTERRUPTED_EXIT_CODE);
+}
+
+fn set_migrating(handle: Option<InterruptHandle>) {
+    if let Ok(mut migrating) = MIGRATING.lock() {
+        *migrating = handle;
+    }
+}
@@ -0,0 +1,47 @@
+use anyhow::Result;
+use sqlite_migrator::{loader::validate_name, migration::Migrations};
+
+use super::create::check_name_pattern;
+
+/// Validate migration names as strict slugs of at most `max_name_length`
+/// characters when it is set, against `name_pattern` when it is set, and
+/// that down migrations reverse up migrations with `round_trip`. Loading the
+/// migrations already checked the directory layout.
+pub fn lint(
+    migrations: &Migrations,
+    max_name_length: Option<usize>,
+    name_pattern: Option<&str>,
+    round_trip: bool,
+) -> Result<()> {
+    let mut problems = Vec::new();
+    for m in migrations.migrations() {
+        let folder_name = m.get_comment().unwrap_or_default();
+        let name = folder_name
+            .split_once('-')
+            .map(|(_, name)| name)
+            .unwrap_or_default();
+        if let Some(max_length) = max_name_length {
+            if let Err(err) = validate_name(name, max_length) {
+                problems.push(format!("{folder_name}: {err}"));
+            }
+        }
+        if let Some(pattern) = name_pattern {
+            if let Err(err) = check_name_pattern(name, pattern) {
+                problems.push(format!("{folder_name}: {err}"));
+            }
+        }
+    }
+
+    if round_trip {
+        if let Err(err) = migrations.validate_round_trip() {
+            problems.push(err.to_string());
+        }
+    }
+
+    if !problems.is_empty() {
+        anyhow::bail!("Lint failed:\n{}", problems.join("\n"));
+    }
+
+    println!("{} migrations OK", migrations.migrations().len());
+    Ok(())
+}
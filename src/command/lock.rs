@@ -0,0 +1,38 @@
+use anyhow::Result;
+use rusqlite::Connection;
+use sqlite_migrator::lock;
+use tracing::warn;
+
+/// Take the migration lock and keep it after exiting, blocking migration
+/// runs during a manual intervention until `unlock`
+pub fn lock(conn: &Connection, reason: &str) -> Result<()> {
+    lock::acquire(conn, reason)?;
+    println!("Migration lock taken ({reason}), release it with `migrator unlock --force`");
+
+    Ok(())
+}
+
+/// Release the migration lock left by a crashed run or by `lock`. Refuses
+/// without `force`, as a migration may still be running.
+pub fn unlock(conn: &Connection, force: bool) -> Result<()> {
+    let Some(info) = lock::status(conn)? else {
+        println!("Migration lock not held");
+        return Ok(());
+    };
+
+    let held = format!(
+        "held by {} since {} ({})",
+        info.holder, info.acquired_at, info.reason
+    );
+    if !force {
+        anyhow::bail!(
+            "Migration lock {held}. Check that no migration is running, then pass --force to release it"
+        );
+    }
+
+    warn!("Releasing the migration lock {held}: a migration still running is no longer protected from concurrent runs");
+    lock::release(conn)?;
+    println!("Migration lock released");
+
+    Ok(())
+}
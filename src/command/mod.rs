@@ -1,3 +1,73 @@
+mod backup;
+mod compare;
+mod config;
+mod consent;
 mod create;
+mod daemon;
+mod doctor;
+mod downgrade_safety;
+mod export;
+mod generate;
+mod graph;
+mod history;
+mod import;
+mod import_state;
+mod init;
+mod interrupt;
+mod lint;
+mod lock;
+mod notify;
+mod pending;
+mod plan;
+mod prompt;
+mod renumber;
+mod repair;
+mod schema;
+mod sign;
+mod snapshot;
+mod squash;
+mod status;
+mod summary;
+mod tenant;
+mod test;
+mod up_glob;
+mod verify;
+mod version;
 
-pub use create::create;
+pub use backup::backup;
+pub use compare::compare;
+pub use config::{config_check, expand_env, valid_keys, ConfigSchema};
+pub use consent::confirm_migration;
+pub use create::{create, CreateOptions};
+pub use daemon::daemon;
+pub use doctor::doctor;
+pub use downgrade_safety::verify_downgrade_safety;
+pub use export::export;
+pub use generate::{generate, GenerateTarget};
+pub use graph::{graph, GraphFormat};
+pub use history::{history, history_diff, history_failures};
+pub use import::{import, ImportFormat};
+pub use import_state::{import_sqlx_state, import_state};
+pub use init::{baseline, ensure_empty, init_from_db, scaffold, DEFAULT_DATABASE, DEFAULT_SOURCE};
+pub use interrupt::{
+    exit_if_interrupted, install_interrupt_handler, interrupt_flag, interruptible,
+};
+pub use lint::lint;
+pub use lock::{lock, unlock};
+pub use notify::{notify_run, RunReport};
+pub use pending::pending;
+pub use plan::{plan, PlanFormat};
+pub use prompt::PromptCategory;
+pub use renumber::renumber;
+pub use repair::repair;
+pub use schema::print_schema;
+pub use sign::{sign, verify_signature};
+pub use snapshot::snapshot;
+pub use squash::squash;
+pub use status::{status, status_check};
+pub use summary::print_summary;
+pub use tenant::{tenant_add, tenant_list, tenant_remove};
+pub use test::test;
+pub use up_glob::{up_glob, up_tenants};
+pub use verify::verify;
+pub use version::version;
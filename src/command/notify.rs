@@ -0,0 +1,96 @@
+use std::{path::Path, time::Duration};
+
+use anyhow::{Context, Result};
+use sqlite_migrator::{migration::MigrationRun, shell};
+use tracing::{debug, warn};
+
+// POST `payload` as JSON to `url`
+fn post_webhook(url: &str, payload: &serde_json::Value) -> Result<()> {
+    debug!("Posting to webhook {}", url);
+    ureq::post(url)
+        .send_json(payload)
+        .context(format!("Could not post to webhook {url}"))?;
+
+    Ok(())
+}
+
+/// Outcome of an `up` or `down` run, reported by [`notify_run`]
+pub struct RunReport<'a> {
+    pub command: &'static str,
+    pub database: &'a Path,
+    /// Version of the database before the run, unknown when it failed
+    /// before reading it
+    pub from_version: Option<usize>,
+    /// Version of the database after the run, unknown when it failed
+    pub to_version: Option<usize>,
+    pub duration: Duration,
+    pub result: &'a Result<Vec<MigrationRun>>,
+}
+
+impl RunReport<'_> {
+    fn payload(&self) -> serde_json::Value {
+        let mut payload = serde_json::json!({
+            "command": self.command,
+            "status": if self.result.is_ok() { "success" } else { "failure" },
+            "database": self.database.display().to_string(),
+            "from_version": self.from_version,
+            "to_version": self.to_version,
+            "duration_ms": self.duration.as_millis(),
+        });
+        match self.result {
+            Ok(runs) => {
+                payload["migrations"] = runs
+                    .iter()
+                    .map(|r| {
+                        serde_json::json!({
+                            "version": r.version.get(),
+                            "name": r.name,
+                            "duration_ms": r.duration.as_millis(),
+                        })
+                    })
+                    .collect();
+            }
+            Err(err) => payload["error"] = format!("{err:#}").into(),
+        }
+
+        payload
+    }
+}
+
+/// Post the report of a run to `webhook` and pass it to `command`, as the
+/// `MIGRATOR_REPORT` JSON and a few `MIGRATOR_*` environment variables.
+/// Failing to notify is logged, without failing the run.
+pub fn notify_run(webhook: Option<&str>, command: Option<&str>, report: &RunReport) {
+    let payload = report.payload();
+
+    if let Some(url) = webhook {
+        if let Err(err) = post_webhook(url, &payload) {
+            warn!("{:#}", err);
+        }
+    }
+
+    if let Some(command) = command {
+        let version = |v: Option<usize>| v.map(|v| v.to_string()).unwrap_or_default();
+        let envs = [
+            ("MIGRATOR_REPORT", payload.to_string()),
+            ("MIGRATOR_COMMAND", report.command.to_owned()),
+            (
+                "MIGRATOR_STATUS",
+                payload["status"].as_str().unwrap_or_default().to_owned(),
+            ),
+            (
+                "MIGRATOR_DATABASE_PATH",
+                report.database.display().to_string(),
+            ),
+            ("MIGRATOR_FROM_VERSION", version(report.from_version)),
+            ("MIGRATOR_TO_VERSION", version(report.to_version)),
+            (
+                "MIGRATOR_DURATION_MS",
+                report.duration.as_millis().to_string(),
+            ),
+        ];
+        if let Err(err) = shell::run(command, &envs) {
+            warn!("Notification command failed: {:#}", err);
+        }
+    }
+}
@@ -0,0 +1,16 @@
+use anyhow::Result;
+use rusqlite::Connection;
+use sqlite_migrator::migration::Migrations;
+
+/// Print the migrations not applied yet, one per line as tab-separated
+/// `version` and `name` fields. Nothing is printed when the database is up to
+/// date.
+pub fn pending(migrations: &Migrations, conn: &Connection) -> Result<()> {
+    let current: usize = migrations.current_version(conn)?.into();
+
+    for (version, m) in migrations.iter().filter(|(v, _)| *v > current) {
+        println!("{version}\t{}", m.get_comment().unwrap_or_default());
+    }
+
+    Ok(())
+}
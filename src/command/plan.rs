@@ -0,0 +1,81 @@
+use anyhow::Result;
+use rusqlite::Connection;
+use sqlite_migrator::migration::{Direction, Migrations};
+
+#[derive(clap::ValueEnum, Debug, Clone, Copy, Default)]
+pub enum PlanFormat {
+    /// Human readable list of pending migrations
+    #[default]
+    Text,
+    /// JSON steps with the SQL to run, for external executors
+    Exec,
+}
+
+/// Print the migrations `up` would apply.
+///
+/// The exec format is a JSON object listing one step per migration, with the
+/// SQL to run and the metadata to report back through `import-state`. A step
+/// with an `only_if` guard is skipped, hooks included, when the guard is false
+/// once the steps before it ran, and one with `requires_backup` must only run
+/// after a backup, as `up` does.
+pub fn plan(migrations: &Migrations, conn: &Connection, format: PlanFormat) -> Result<()> {
+    let current: usize = migrations.current_version(conn)?.into();
+    let pending = migrations
+        .iter()
+        .filter(|(v, _)| *v > current)
+        .collect::<Vec<_>>();
+
+    match format {
+        PlanFormat::Text => {
+            if pending.is_empty() {
+                println!("Nothing to migrate, database is at version {current}");
+            }
+            for (version, m) in pending {
+                println!("{version:04} {}", m.get_comment().unwrap_or_default());
+                if let Some(guard) = m.get_only_if() {
+                    println!("     only if {guard}");
+                }
+                if m.get_requires_backup() {
+                    println!("     requires a backup");
+                }
+                for hook in m.hooks(Direction::Up) {
+                    println!("     hook {hook}");
+                }
+            }
+        }
+        PlanFormat::Exec => {
+            let steps = pending
+                .into_iter()
+                .map(|(version, m)| {
+                    serde_json::json!({
+                        "version": version,
+                        "name": m.get_comment().unwrap_or_default(),
+                        "checksum": m.checksum(),
+                        "sql": m.get_up_script(),
+                        "only_if": m.get_only_if(),
+                        "requires_backup": m.get_requires_backup(),
+                        "post_sql": migrations.get_version_storage().set_sql(version),
+                        "hooks": m
+                            .hooks(Direction::Up)
+                            .iter()
+                            .map(|h| serde_json::json!({
+                                "stage": h.stage.to_string(),
+                                "kind": if h.command.is_some() { "shell" } else { "rust" },
+                                "command": h.command,
+                                "direction": Direction::Up.to_string(),
+                            }))
+                            .collect::<Vec<_>>(),
+                    })
+                })
+                .collect::<Vec<_>>();
+            let plan = serde_json::json!({
+                "from_version": current,
+                "to_version": usize::from(migrations.max_version()),
+                "steps": steps,
+            });
+            println!("{}", serde_json::to_string_pretty(&plan)?);
+        }
+    }
+
+    Ok(())
+}
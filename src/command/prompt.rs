@@ -0,0 +1,62 @@
+use std::io::{self, BufRead, IsTerminal, Write};
+
+use anyhow::Result;
+use clap::ValueEnum;
+
+/// Class of confirmation prompt, which automation can consent to separately
+/// with `--assume-yes-for`
+#[derive(clap::ValueEnum, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PromptCategory {
+    /// Applying migrations that drop or delete data
+    DestructiveSql,
+    /// Rolling back migrations whose down SQL drops or deletes data
+    IrreversibleDown,
+    /// Migrating a database marked `protected` in the config file
+    ProtectedEnvironment,
+}
+
+/// Ask a yes/no question on the terminal, defaulting to no. The question is
+/// written to stderr, leaving stdout to the output of the command.
+pub fn confirm(question: &str) -> Result<bool> {
+    eprint!("{question} [y/N] ");
+    io::stderr().flush()?;
+
+    let mut answer = String::new();
+    io::stdin().lock().read_line(&mut answer)?;
+
+    // a Ctrl-C while waiting for the answer declines
+    if super::interrupt_flag().load(std::sync::atomic::Ordering::SeqCst) {
+        return Ok(false);
+    }
+
+    Ok(matches!(answer.trim(), "y" | "Y" | "yes" | "YES"))
+}
+
+/// Ask a question of the given category, unless `assumed` consents to it.
+/// Fails with `aborted` when the answer is no, and without asking when stdin
+/// isn't a terminal, e.g. in CI or scheduled runs, naming the
+/// `--assume-yes-for` category to pass instead.
+pub fn confirm_for(
+    category: PromptCategory,
+    assumed: &[PromptCategory],
+    question: &str,
+    aborted: &str,
+) -> Result<()> {
+    if assumed.contains(&category) {
+        return Ok(());
+    }
+    if !io::stdin().is_terminal() {
+        let name = category
+            .to_possible_value()
+            .map(|value| value.get_name().to_owned())
+            .unwrap_or_default();
+        anyhow::bail!(
+            "{aborted}: {question} No terminal to ask on, pass --assume-yes-for {name} to consent"
+        );
+    }
+    if confirm(question)? {
+        return Ok(());
+    }
+
+    anyhow::bail!("{aborted}")
+}
@@ -0,0 +1,140 @@
+use std::{fs, path::Path};
+
+use anyhow::{Context, Result};
+use rusqlite::Connection;
+use sqlite_migrator::history;
+use tracing::warn;
+
+/// Give consecutive ids, starting at `from`, to the migration folders whose
+/// id is `from` or higher, in id and then name order. Folders sharing an id
+/// after a branch merge are thus moved after one another, along with the
+/// migrations following them, and gaps left by removed migrations are
+/// closed.
+///
+/// The folders listed in `order`, by name or by id, come first in that order
+/// instead.
+///
+/// With `conn`, the history entries of the renamed folders are moved to their
+/// new id in the same go, so that applied migrations keep their entry.
+pub fn renumber(
+    migration_dir: &Path,
+    from: usize,
+    order: &[String],
+    conn: Option<&mut Connection>,
+) -> Result<()> {
+    // the loader rejects migration ids of 0
+    anyhow::ensure!(
+        from >= 1,
+        "--from must be 1 or higher, migration ids start at 1"
+    );
+
+    let mut folders = fs::read_dir(migration_dir)
+        .context("Failed to read migration directory")?
+        .filter_map(|entry| {
+            let name = entry.ok()?.file_name().into_string().ok()?;
+            let digits = name.chars().take_while(char::is_ascii_digit).count();
+            let rest = name[digits..].strip_prefix('-')?.to_owned();
+            let id = name[..digits].parse::<usize>().ok()?;
+            (id >= from).then_some((id, name, digits, rest))
+        })
+        .collect::<Vec<_>>();
+    folders.sort();
+
+    let mut ordered = Vec::with_capacity(folders.len());
+    for wanted in order {
+        let matching = folders
+            .iter()
+            .enumerate()
+            .filter(|(_, (id, name, _, _))| {
+                name == wanted || wanted.parse::<usize>().is_ok_and(|w| w == *id)
+            })
+            .map(|(i, _)| i)
+            .collect::<Vec<_>>();
+        match matching[..] {
+            [i] => ordered.push(folders.remove(i)),
+            [] => anyhow::bail!(
+                "No migration {wanted:?} with an id from {from} to renumber, or it is listed twice"
+            ),
+            _ => anyhow::bail!(
+                "Several migrations have the id {wanted}, list them by folder name instead"
+            ),
+        }
+    }
+    ordered.append(&mut folders);
+    let folders = ordered;
+
+    let renames = folders
+        .into_iter()
+        .zip(from..)
+        .map(|((_, name, digits, rest), id)| (name, format!("{id:0digits$}-{rest}"), id))
+        .filter(|(old, new, _)| old != new)
+        .collect::<Vec<_>>();
+    if renames.is_empty() {
+        println!("Migration ids from {from} are already in order");
+        return Ok(());
+    }
+
+    // the history is only committed once every folder was renamed
+    let tx = conn.map(Connection::transaction).transpose()?;
+    if let Some(tx) = &tx {
+        let moved = history::renumber(
+            tx,
+            &renames
+                .iter()
+                .map(|(old, new, id)| (old.as_str(), *id, new.as_str()))
+                .collect::<Vec<_>>(),
+        )?;
+        println!("Moved {moved} history entries");
+    }
+    rename_all(migration_dir, &renames)?;
+    if let Some(tx) = tx {
+        if let Err(e) = tx.commit() {
+            roll_back(migration_dir, &renames, renames.len());
+            return Err(e).context("Failed to update the history, the folders were not renamed");
+        }
+    }
+    for (old, new, _) in &renames {
+        println!("{old} -> {new}");
+    }
+
+    Ok(())
+}
+
+// Rename the folders through temporary names, as a new name can be the old
+// name of another folder. On failure, the folders renamed so far are given
+// back their old names, since the loader skips the hidden temporary ones.
+fn rename_all(migration_dir: &Path, renames: &[(String, String, usize)]) -> Result<()> {
+    let temporary = |old: &str| migration_dir.join(format!(".renumber-{old}"));
+
+    for (i, (old, _, _)) in renames.iter().enumerate() {
+        if let Err(e) = fs::rename(migration_dir.join(old), temporary(old)) {
+            roll_back(migration_dir, &renames[..i], 0);
+            return Err(e).context(format!("Failed to rename {old}"));
+        }
+    }
+    for (i, (old, new, _)) in renames.iter().enumerate() {
+        if let Err(e) = fs::rename(temporary(old), migration_dir.join(new)) {
+            roll_back(migration_dir, renames, i);
+            return Err(e).context(format!("Failed to rename {old} to {new}"));
+        }
+    }
+
+    Ok(())
+}
+
+// Undo `renames`, the first `moved` of which already have their new name and
+// the others their temporary name
+fn roll_back(migration_dir: &Path, renames: &[(String, String, usize)], moved: usize) {
+    let temporary = |old: &str| migration_dir.join(format!(".renumber-{old}"));
+
+    for (old, new, _) in &renames[..moved] {
+        if let Err(e) = fs::rename(migration_dir.join(new), temporary(old)) {
+            warn!("Failed to rename {new} back to {old}: {e}");
+        }
+    }
+    for (old, _, _) in renames {
+        if let Err(e) = fs::rename(temporary(old), migration_dir.join(old)) {
+            warn!("Failed to rename .renumber-{old} back to {old}: {e}");
+        }
+    }
+}
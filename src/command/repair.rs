@@ -0,0 +1,28 @@
+use anyhow::Result;
+use rusqlite::Connection;
+use sqlite_migrator::migration::Migrations;
+
+use super::prompt::confirm;
+
+/// Update the names and checksums stored in the history table to match the
+/// migration directory, after confirmation unless `yes` is set.
+pub fn repair(migrations: &Migrations, conn: &mut Connection, yes: bool) -> Result<()> {
+    let discrepancies = migrations.verify(conn)?;
+    if discrepancies.is_empty() {
+        println!("Nothing to repair");
+        return Ok(());
+    }
+
+    for discrepancy in &discrepancies {
+        println!("{discrepancy}");
+    }
+
+    if !yes && !confirm("Update the history to match the migration directory?")? {
+        anyhow::bail!("Repair aborted");
+    }
+
+    let repaired = migrations.repair(conn)?;
+    println!("Repaired {} history entries", repaired.len());
+
+    Ok(())
+}
@@ -0,0 +1,12 @@
+use anyhow::Result;
+use rusqlite::Connection;
+use sqlite_migrator::schema;
+
+/// Print the schema of the database as SQL statements.
+pub fn print_schema(conn: &Connection) -> Result<()> {
+    for statement in schema::dump(conn)? {
+        println!("{statement};");
+    }
+
+    Ok(())
+}
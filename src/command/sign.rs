@@ -0,0 +1,67 @@
+use std::{fs, path::Path};
+
+use anyhow::{Context, Result};
+use ed25519_dalek::{
+    pkcs8::{DecodePrivateKey, DecodePublicKey},
+    SigningKey, VerifyingKey,
+};
+use sqlite_migrator::{migration::Migrations, signature};
+use tracing::info;
+
+/// Sign the migration directory `source` with the PEM encoded Ed25519 private key at
+/// `key_path`, e.g. from `openssl genpkey -algorithm ed25519`, writing the
+/// signature to `output` or the signature file of `source`. `migrations`,
+/// loaded from `source`, are only counted
+pub fn sign(
+    migrations: &Migrations,
+    source: &Path,
+    key_path: &Path,
+    output: Option<&Path>,
+) -> Result<()> {
+    let pem =
+        fs::read_to_string(key_path).context(format!("Could not read {}", key_path.display()))?;
+    let key = SigningKey::from_pkcs8_pem(&pem).map_err(|e| {
+        anyhow::anyhow!(
+            "{} is not a PEM encoded Ed25519 private key: {e}",
+            key_path.display()
+        )
+    })?;
+
+    let path = output.map_or_else(|| source.join(signature::SIGNATURE_FILE), Path::to_owned);
+    fs::write(&path, signature::sign(source, &key)? + "\n")
+        .context(format!("Failed to write {}", path.display()))?;
+    info!(
+        "Signed {} migrations, signature written to {}",
+        migrations.migrations().len(),
+        path.display()
+    );
+
+    Ok(())
+}
+
+/// Refuse an unsigned or tampered migration directory `source`: the
+/// signature at `signature_path`, or in the signature file of `source`, must
+/// be made by the private key of the PEM encoded public key at `key_path`
+pub fn verify_signature(
+    source: &Path,
+    key_path: &Path,
+    signature_path: Option<&Path>,
+) -> Result<()> {
+    let pem =
+        fs::read_to_string(key_path).context(format!("Could not read {}", key_path.display()))?;
+    let key = VerifyingKey::from_public_key_pem(&pem).map_err(|e| {
+        anyhow::anyhow!(
+            "{} is not a PEM encoded Ed25519 public key: {e}",
+            key_path.display()
+        )
+    })?;
+
+    let path =
+        signature_path.map_or_else(|| source.join(signature::SIGNATURE_FILE), Path::to_owned);
+    let signature = fs::read_to_string(&path).context(format!(
+        "The migrations are not signed, {} could not be read",
+        path.display()
+    ))?;
+
+    signature::verify(source, &signature, &key)
+}
@@ -0,0 +1,42 @@
+use std::{fs, path::Path};
+
+use anyhow::{Context, Result};
+use rusqlite::Connection;
+use sqlite_migrator::{migration::Migrations, schema};
+
+/// Print the normalized schema the migrations produce, or with `check`, fail
+/// if it differs from the snapshot stored in that file.
+pub fn snapshot(migrations: &Migrations, check: Option<&Path>) -> Result<()> {
+    let migrated = migrations.migrated_schema(&Connection::open_in_memory()?)?;
+    let snapshot = schema::snapshot(&migrated)?;
+
+    let Some(path) = check else {
+        print!("{snapshot}");
+        return Ok(());
+    };
+
+    let expected =
+        fs::read_to_string(path).context(format!("Failed to read snapshot {}", path.display()))?;
+    if expected == snapshot {
+        println!("Migrated schema matches {}", path.display());
+        return Ok(());
+    }
+
+    for line in expected
+        .lines()
+        .filter(|l| !snapshot.lines().any(|s| s == *l))
+    {
+        println!("- {line}");
+    }
+    for line in snapshot
+        .lines()
+        .filter(|l| !expected.lines().any(|e| e == *l))
+    {
+        println!("+ {line}");
+    }
+
+    anyhow::bail!(
+        "Migrated schema doesn't match {0}, regenerate it with `migrator snapshot > {0}` if the change is intended",
+        path.display()
+    )
+}
@@ -0,0 +1,140 @@
+use std::{fs, path::Path};
+
+use anyhow::{Context, Result};
+use chrono::Local;
+use rusqlite::Connection;
+use sqlite_migrator::{loader::ARCHIVE_DIR, meta::META_FILE_NAME, migration::Migrations};
+use tracing::warn;
+
+use super::init::schema_sql;
+
+/// Name of the migration replacing the squashed ones
+const BASELINE_NAME: &str = "baseline";
+
+/// Replace the migrations up to `to` with a baseline migration creating the
+/// schema they lead to, with id `to` so that databases already past it are
+/// left alone. The replaced migration folders are moved to
+/// `archive/<date>/` for reference.
+pub fn squash(migration_dir: &Path, migrations: &Migrations, to: usize) -> Result<()> {
+    if migrations.get(to).is_none() {
+        anyhow::bail!("No migration has the version {to}");
+    }
+    let baseline = migrations.baseline_version();
+    if to <= baseline {
+        anyhow::bail!("The migrations up to {baseline} are already squashed");
+    }
+
+    let mut conn = Connection::open_in_memory()?;
+    // only the SQL, the commands and hooks are for real databases
+    migrations
+        .replay(&mut conn, to)
+        .context(format!("Could not apply the migrations up to {to}"))?;
+    let (up, down, objects) = schema_sql(&conn)?;
+
+    let folders = fs::read_dir(migration_dir)
+        .context("Failed to read migration directory")?
+        .filter_map(|entry| {
+            let name = entry.ok()?.file_name().into_string().ok()?;
+            let digits = name.chars().take_while(char::is_ascii_digit).count();
+            name[digits..].strip_prefix('-')?;
+            let id = name[..digits].parse::<usize>().ok()?;
+            (id <= to).then_some(name)
+        })
+        .collect::<Vec<_>>();
+
+    let archive = migration_dir
+        .join(ARCHIVE_DIR)
+        .join(Local::now().format("%Y-%m-%d").to_string());
+    if let Some(existing) = folders.iter().find(|f| archive.join(f).exists()) {
+        anyhow::bail!("{existing} is already archived in {}", archive.display());
+    }
+
+    // written to a hidden folder first, which the loader skips, and moved in
+    // place once the replaced migrations are archived, so that a failure
+    // leaves the directory as it was
+    let folder = format!("{to:04}-{BASELINE_NAME}");
+    let temporary = migration_dir.join(format!(".squash-{folder}"));
+    if let Err(e) = write_baseline(&temporary, &folder, &up, &down) {
+        remove_temporary(&temporary);
+        return Err(e);
+    }
+
+    let moved = archive_folders(migration_dir, &archive, &folders).and_then(|()| {
+        fs::rename(&temporary, migration_dir.join(&folder))
+            .context(format!("Failed to move the baseline to {folder}"))
+    });
+    if let Err(e) = moved {
+        restore(migration_dir, &archive, &folders);
+        remove_temporary(&temporary);
+        return Err(e);
+    }
+    println!(
+        "Archived {} migrations to {}",
+        folders.len(),
+        archive.display()
+    );
+    println!("Wrote {objects} schema objects to {folder}");
+
+    Ok(())
+}
+
+// Write the baseline migration `folder` to the new directory `dir`, rather
+// than with `create`, which warns about the gap before its id
+fn write_baseline(dir: &Path, folder: &str, up: &str, down: &str) -> Result<()> {
+    fs::create_dir(dir).context(format!("Failed to create {}", dir.display()))?;
+    let generated = format!(
+        "migration `{folder}` generated at {}",
+        Local::now().format("%Y-%m-%d %H:%M:%S")
+    );
+    for (file, contents) in [
+        ("up.sql", format!("-- Up {generated}.\n{up}")),
+        ("down.sql", format!("-- Down {generated}.\n{down}")),
+        (
+            META_FILE_NAME,
+            "# Replaces the archived migrations before it\nbaseline: true\n".to_owned(),
+        ),
+    ] {
+        fs::write(dir.join(file), contents).context(format!("Failed to write {file}"))?;
+    }
+
+    Ok(())
+}
+
+// Move the `folders` of the migration directory to `archive`, moving them
+// back on failure
+fn archive_folders(migration_dir: &Path, archive: &Path, folders: &[String]) -> Result<()> {
+    fs::create_dir_all(archive).context(format!("Failed to create {}", archive.display()))?;
+    for (i, folder) in folders.iter().enumerate() {
+        if let Err(e) = fs::rename(migration_dir.join(folder), archive.join(folder)) {
+            restore(migration_dir, archive, &folders[..i]);
+            return Err(e).context(format!("Failed to archive {folder}"));
+        }
+    }
+
+    Ok(())
+}
+
+// Move archived `folders` back to the migration directory, skipping the ones
+// already there
+fn restore(migration_dir: &Path, archive: &Path, folders: &[String]) {
+    for folder in folders {
+        let archived = archive.join(folder);
+        if !archived.exists() {
+            continue;
+        }
+        if let Err(e) = fs::rename(&archived, migration_dir.join(folder)) {
+            warn!(
+                "Failed to move {folder} back from {}: {e}",
+                archive.display()
+            );
+        }
+    }
+}
+
+fn remove_temporary(dir: &Path) {
+    if dir.exists() {
+        if let Err(e) = fs::remove_dir_all(dir) {
+            warn!("Failed to remove {}: {e}", dir.display());
+        }
+    }
+}
@@ -0,0 +1,102 @@
+use anyhow::Result;
+use rusqlite::Connection;
+use sqlite_migrator::{
+    history::{self, HistoryEntry},
+    migration::Migrations,
+    schema,
+};
+
+/// Print every migration with whether it has been applied to the database.
+///
+/// The porcelain format prints one migration per line as tab-separated
+/// `version`, `state` and `name` fields and is guaranteed to stay stable.
+pub fn status(migrations: &Migrations, conn: &Connection, porcelain: bool) -> Result<()> {
+    let current_version = migrations.current_version(conn)?;
+    let current: usize = current_version.into();
+    let entries = history::entries(conn)?;
+
+    if !porcelain {
+        println!("Current version: {current_version}");
+        println!("Latest version: {}", migrations.max_version());
+        match schema_drifted(conn, &entries, current)? {
+            Some(true) => println!(
+                "Schema: drifted, it doesn't match the schema recorded when migrating to version {current}"
+            ),
+            Some(false) => println!("Schema: matches version {current}"),
+            None => {}
+        }
+        println!();
+    }
+
+    for (version, m) in migrations.iter() {
+        let state = if version > current {
+            "pending"
+        } else if entries.iter().any(|e| e.version == version && e.skipped) {
+            "skipped"
+        } else {
+            "applied"
+        };
+        let name = m.get_comment().unwrap_or_default();
+
+        if porcelain {
+            println!("{version}\t{state}\t{name}");
+        } else {
+            println!("  [{state}] {version:04} {name}");
+            // recorded descriptions remain when the files are changed or absent
+            let description = m.get_description().or_else(|| {
+                entries
+                    .iter()
+                    .find(|e| e.version == version && !e.description.is_empty())
+                    .map(|e| e.description.as_str())
+            });
+            if let Some(description) = description {
+                println!("            {description}");
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// Print a single summary line, failing if migrations are pending, applied
+/// migrations drifted from the migration directory or the schema was changed
+/// by hand.
+pub fn status_check(migrations: &Migrations, conn: &Connection) -> Result<()> {
+    let current: usize = migrations.current_version(conn)?.into();
+    let pending = migrations.iter().filter(|(v, _)| *v > current).count();
+    let drifted = migrations.verify(conn)?.len();
+    let schema_drifted = schema_drifted(conn, &history::entries(conn)?, current)?;
+
+    if pending == 0 && drifted == 0 && schema_drifted != Some(true) {
+        println!("Database is up to date at version {current}");
+        return Ok(());
+    }
+
+    if pending == 0 && drifted == 0 {
+        anyhow::bail!(
+            "Database at version {current} has a schema that doesn't match the one recorded when migrating to it"
+        );
+    }
+    anyhow::bail!(
+        "Database at version {current} is not up to date: {pending} pending, {drifted} drifted migrations"
+    )
+}
+
+// Whether the live schema differs from the one recorded when a run left the
+// database at `current`, `None` when no schema hash was recorded for it
+fn schema_drifted(
+    conn: &Connection,
+    entries: &[HistoryEntry],
+    current: usize,
+) -> Result<Option<bool>> {
+    let Some(expected) = entries
+        .iter()
+        .find(|e| e.version == current)
+        .map(|e| &e.schema_hash)
+        .filter(|hash| !hash.is_empty())
+    else {
+        return Ok(None);
+    };
+
+    Ok(Some(schema::hash(conn)? != *expected))
+}
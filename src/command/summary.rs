@@ -0,0 +1,66 @@
+use std::time::Duration;
+
+use sqlite_migrator::migration::MigrationRun;
+
+/// Print a table of the migrations that were run and how long each took.
+/// With `stats`, also print how many statements each ran and how many rows
+/// they changed.
+pub fn print_summary(runs: &[MigrationRun], stats: bool) {
+    if runs.is_empty() {
+        return;
+    }
+
+    let name_width = runs
+        .iter()
+        .map(|r| r.name.as_deref().unwrap_or_default().len())
+        .max()
+        .unwrap_or_default()
+        .max("Name".len());
+    // timestamp ids, e.g. of Diesel migrations, are wider than the header
+    let version_width = runs
+        .iter()
+        .map(|r| r.version.to_string().len())
+        .max()
+        .unwrap_or_default()
+        .max("Version".len() + 2);
+
+    print!(
+        "{:<version_width$} {:<4} {:<name_width$}",
+        "Version", "Dir", "Name"
+    );
+    if stats {
+        print!(" {:>10} {:>10}", "Statements", "Rows");
+    }
+    println!(" {:>10}", "Duration");
+    for run in runs {
+        print!(
+            "{:<version_width$} {:<4} {:<name_width$}",
+            run.version.get(),
+            run.direction,
+            run.name.as_deref().unwrap_or_default()
+        );
+        if stats {
+            print!(" {:>10} {:>10}", run.statements, run.rows_changed);
+        }
+        println!(
+            " {:>10}{}",
+            format_duration(run.duration),
+            if run.skipped { " (skipped)" } else { "" }
+        );
+    }
+
+    let total = runs.iter().map(|r| r.duration).sum();
+    print!("{:<version_width$} {:<4} {:<name_width$}", "Total", "", "");
+    if stats {
+        print!(
+            " {:>10} {:>10}",
+            runs.iter().map(|r| r.statements).sum::<usize>(),
+            runs.iter().map(|r| r.rows_changed).sum::<u64>()
+        );
+    }
+    println!(" {:>10}", format_duration(total));
+}
+
+fn format_duration(duration: Duration) -> String {
+    format!("{:.2}ms", duration.as_secs_f64() * 1000.0)
+}
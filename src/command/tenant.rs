@@ -0,0 +1,44 @@
+use std::path::Path;
+
+use anyhow::Result;
+use sqlite_migrator::tenants::Registry;
+
+/// Register the database of a tenant in the registry at `registry`.
+pub fn tenant_add(registry: &Path, name: &str, database: &Path) -> Result<()> {
+    let mut tenants = Registry::load(registry)?;
+    tenants.add(name, database)?;
+    tenants.save(registry)?;
+
+    println!("Added tenant {name} ({})", database.display());
+    Ok(())
+}
+
+/// Deactivate a tenant, which is kept in the registry but no longer migrated.
+pub fn tenant_remove(registry: &Path, name: &str) -> Result<()> {
+    let mut tenants = Registry::load(registry)?;
+    tenants.deactivate(name)?;
+    tenants.save(registry)?;
+
+    println!("Deactivated tenant {name}");
+    Ok(())
+}
+
+pub fn tenant_list(registry: &Path) -> Result<()> {
+    let tenants = Registry::load(registry)?;
+
+    if tenants.tenants.is_empty() {
+        println!("No tenant registered in {}", registry.display());
+        return Ok(());
+    }
+
+    for tenant in &tenants.tenants {
+        println!(
+            "{}\t{}\t{}",
+            tenant.name,
+            if tenant.active { "active" } else { "inactive" },
+            tenant.database.display()
+        );
+    }
+
+    Ok(())
+}
@@ -0,0 +1,50 @@
+use std::time::Instant;
+
+use anyhow::Result;
+use rusqlite::Connection;
+use sqlite_migrator::migration::Migrations;
+
+/// Test every migration on its own: in a fresh in-memory database migrated to
+/// the version preceding it, apply it, revert it and apply it again,
+/// checking the schema after each step. Only the SQL is run, without hooks
+/// or shell commands. Prints the outcome and duration of
+/// each test, failing if any of them failed.
+pub fn test(migrations: &Migrations) -> Result<()> {
+    let name_width = migrations
+        .migrations()
+        .iter()
+        .map(|m| m.get_comment().unwrap_or_default().len())
+        .max()
+        .unwrap_or_default();
+
+    let mut failed = 0;
+    for (version, m) in migrations.iter() {
+        let start = Instant::now();
+        let result = (|| -> Result<()> {
+            let mut conn = Connection::open_in_memory()?;
+            conn.pragma_update(None, "foreign_keys", "ON")?;
+            let previous = migrations.version_before(version, 1).unwrap_or_default();
+            migrations.replay(&mut conn, previous)?;
+            Ok(migrations.round_trip(&mut conn, version)?)
+        })();
+        let duration = format!("{:.2}ms", start.elapsed().as_secs_f64() * 1000.0);
+
+        let name = m.get_comment().unwrap_or_default();
+        match result {
+            Ok(()) => println!("PASS {version:04} {name:<name_width$} {duration:>10}"),
+            Err(err) => {
+                failed += 1;
+                println!("FAIL {version:04} {name:<name_width$} {duration:>10}");
+                println!("     {err:#}");
+            }
+        }
+    }
+
+    let total = migrations.migrations().len();
+    if failed > 0 {
+        anyhow::bail!("{failed} of {total} migrations failed");
+    }
+    println!("{total} migrations passed");
+
+    Ok(())
+}
@@ -0,0 +1,79 @@
+use std::path::{Path, PathBuf};
+
+use anyhow::{anyhow, bail, Context, Result};
+use sqlite_migrator::{migration::MigrationRun, tenants::Registry};
+use tracing::error;
+
+use super::print_summary;
+
+/// Run `up` against every database matching `pattern`, printing the summary
+/// of each run with its `stats`.
+pub fn up_glob(
+    pattern: &str,
+    continue_on_error: bool,
+    stats: bool,
+    up: impl FnMut(&Path) -> Result<Vec<MigrationRun>>,
+) -> Result<()> {
+    let databases = glob::glob(pattern)
+        .context(format!("Invalid database glob {pattern:?}"))?
+        .collect::<Result<Vec<PathBuf>, _>>()?;
+    if databases.is_empty() {
+        bail!("No database matches {pattern:?}");
+    }
+
+    up_databases(&databases, continue_on_error, stats, up)
+}
+
+/// Run `up` against the database of every active tenant of the registry,
+/// printing the summary of each run with its `stats`.
+pub fn up_tenants(
+    registry: &Path,
+    continue_on_error: bool,
+    stats: bool,
+    up: impl FnMut(&Path) -> Result<Vec<MigrationRun>>,
+) -> Result<()> {
+    let databases = Registry::load(registry)?
+        .active()
+        .map(|t| t.database.clone())
+        .collect::<Vec<_>>();
+    if databases.is_empty() {
+        bail!("No active tenant in {}", registry.display());
+    }
+
+    up_databases(&databases, continue_on_error, stats, up)
+}
+
+// Print one result line per database, followed by the summary of its run,
+// stopping at the first failure unless `continue_on_error`
+fn up_databases(
+    databases: &[PathBuf],
+    continue_on_error: bool,
+    stats: bool,
+    mut up: impl FnMut(&Path) -> Result<Vec<MigrationRun>>,
+) -> Result<()> {
+    let mut failed = 0;
+    for database in databases {
+        match up(database) {
+            Ok(runs) => {
+                println!("{}\tok\t{} applied", database.display(), runs.len());
+                print_summary(&runs, stats);
+            }
+            Err(err) => {
+                error!("{}: {:#}", database.display(), err);
+                println!("{}\tfailed\t{:#}", database.display(), err);
+                failed += 1;
+                if !continue_on_error {
+                    return Err(err.context(format!("Migrating {}", database.display())));
+                }
+            }
+        }
+    }
+
+    if failed > 0 {
+        return Err(anyhow!(
+            "{failed} of {} databases failed to migrate",
+            databases.len()
+        ));
+    }
+    Ok(())
+}
@@ -0,0 +1,22 @@
+use anyhow::Result;
+use rusqlite::Connection;
+use sqlite_migrator::migration::Migrations;
+
+/// Report applied migrations that don't match the files on disk.
+pub fn verify(migrations: &Migrations, conn: &Connection) -> Result<()> {
+    let discrepancies = migrations.verify(conn)?;
+
+    if discrepancies.is_empty() {
+        println!("All applied migrations match the migration directory");
+        return Ok(());
+    }
+
+    for discrepancy in &discrepancies {
+        println!("{discrepancy}");
+    }
+
+    anyhow::bail!(
+        "{} applied migrations don't match the migration directory",
+        discrepancies.len()
+    )
+}
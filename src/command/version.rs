@@ -0,0 +1,22 @@
+use anyhow::Result;
+use rusqlite::Connection;
+use sqlite_migrator::migration::{Migrations, SchemaVersion};
+
+/// Print the database version, the latest version defined and whether the
+/// database is inside the range of defined migrations.
+pub fn version(migrations: &Migrations, conn: &Connection) -> Result<()> {
+    let current_version = migrations.current_version(conn)?;
+    let current: usize = current_version.into();
+
+    println!("Current version: {current}");
+    println!("Latest version: {}", usize::from(migrations.max_version()));
+    match current_version {
+        SchemaVersion::NoneSet => println!("State: no version set"),
+        SchemaVersion::Inside(_) => println!("State: inside the defined migrations"),
+        SchemaVersion::Outside(_) => {
+            println!("State: outside the defined migrations, the database is ahead of the migration directory")
+        }
+    }
+
+    Ok(())
+}
@@ -0,0 +1,292 @@
+//! Comparison of two databases.
+use std::{collections::BTreeMap, fmt};
+
+use anyhow::{Context, Result};
+use rusqlite::{types::Value, Connection};
+
+use crate::schema;
+
+/// Difference between the schemas of two databases
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Difference {
+    /// A table, index, view or trigger only found in the first database
+    Removed { kind: String, name: String },
+    /// A table, index, view or trigger only found in the second database
+    Added { kind: String, name: String },
+    /// An object whose normalized SQL differs, with the SQL of each database,
+    /// for tables whose columns are the same
+    Changed {
+        kind: String,
+        name: String,
+        first: String,
+        second: String,
+    },
+    /// A column only found in the first database
+    ColumnRemoved { table: String, column: String },
+    /// A column only found in the second database
+    ColumnAdded { table: String, column: String },
+    /// A column whose type, constraints or default differ, with its
+    /// definition in each database
+    ColumnChanged {
+        table: String,
+        column: String,
+        first: String,
+        second: String,
+    },
+}
+
+impl fmt::Display for Difference {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Difference::Removed { kind, name } => write!(f, "- {kind} {name}"),
+            Difference::Added { kind, name } => write!(f, "+ {kind} {name}"),
+            Difference::Changed {
+                kind,
+                name,
+                first,
+                second,
+            } => write!(f, "~ {kind} {name}: {first} -> {second}"),
+            Difference::ColumnRemoved { table, column } => write!(f, "- column {table}.{column}"),
+            Difference::ColumnAdded { table, column } => write!(f, "+ column {table}.{column}"),
+            Difference::ColumnChanged {
+                table,
+                column,
+                first,
+                second,
+            } => write!(f, "~ column {table}.{column}: {first} -> {second}"),
+        }
+    }
+}
+
+/// Compare the schemas of two databases: their tables, indexes, views and
+/// triggers by normalized SQL (see [`schema::normalize`]), and the columns
+/// of the tables found in both by `pragma_table_info`. The internal tables of
+/// SQLite and of the migrator are left out.
+pub fn schema_diff(a: &Connection, b: &Connection) -> Result<Vec<Difference>> {
+    let objects_a = schema_objects(a)?;
+    let mut objects_b = schema_objects(b)?;
+
+    let mut differences = Vec::new();
+    for ((kind, name), sql_a) in objects_a {
+        let Some(sql_b) = objects_b.remove(&(kind.clone(), name.clone())) else {
+            differences.push(Difference::Removed { kind, name });
+            continue;
+        };
+        if sql_a == sql_b {
+            continue;
+        }
+
+        let columns = if kind == "table" {
+            column_differences(a, b, &name)?
+        } else {
+            Vec::new()
+        };
+        if columns.is_empty() {
+            differences.push(Difference::Changed {
+                kind,
+                name,
+                first: sql_a,
+                second: sql_b,
+            });
+        } else {
+            differences.extend(columns);
+        }
+    }
+    differences.extend(
+        objects_b
+            .into_keys()
+            .map(|(kind, name)| Difference::Added { kind, name }),
+    );
+
+    Ok(differences)
+}
+
+// Normalized SQL of the objects of the schema, by type and name
+fn schema_objects(conn: &Connection) -> Result<BTreeMap<(String, String), String>> {
+    let mut stmt = conn.prepare(
+        "SELECT type, name, sql FROM sqlite_master
+         WHERE sql IS NOT NULL AND name NOT LIKE 'sqlite_%' AND tbl_name NOT LIKE '\\_migrator\\_%' ESCAPE '\\'",
+    )?;
+    let objects = stmt
+        .query_map([], |row| {
+            Ok((
+                (row.get(0)?, row.get(1)?),
+                schema::normalize(&row.get::<_, String>(2)?),
+            ))
+        })?
+        .collect::<Result<_, _>>()?;
+
+    Ok(objects)
+}
+
+fn column_differences(a: &Connection, b: &Connection, table: &str) -> Result<Vec<Difference>> {
+    let columns_a = column_definitions(a, table)?;
+    let columns_b = column_definitions(b, table)?;
+
+    let mut differences = Vec::new();
+    for (column, first) in &columns_a {
+        match columns_b.iter().find(|(c, _)| c == column) {
+            None => differences.push(Difference::ColumnRemoved {
+                table: table.to_owned(),
+                column: column.clone(),
+            }),
+            Some((_, second)) if second != first => differences.push(Difference::ColumnChanged {
+                table: table.to_owned(),
+                column: column.clone(),
+                first: first.clone(),
+                second: second.clone(),
+            }),
+            Some(_) => {}
+        }
+    }
+    for (column, _) in &columns_b {
+        if !columns_a.iter().any(|(c, _)| c == column) {
+            differences.push(Difference::ColumnAdded {
+                table: table.to_owned(),
+                column: column.clone(),
+            });
+        }
+    }
+
+    Ok(differences)
+}
+
+// Columns of the table with their definition, e.g. `INTEGER NOT NULL
+// DEFAULT 0`, in order
+fn column_definitions(conn: &Connection, table: &str) -> Result<Vec<(String, String)>> {
+    let mut stmt = conn.prepare(
+        "SELECT name, type, \"notnull\", dflt_value, pk FROM pragma_table_info(?1) ORDER BY cid",
+    )?;
+    let columns = stmt
+        .query_map([table], |row| {
+            let mut definition = row.get::<_, String>(1)?;
+            if row.get(2)? {
+                definition.push_str(" NOT NULL");
+            }
+            if let Some(default) = row.get::<_, Option<String>>(3)? {
+                definition.push_str(&format!(" DEFAULT {default}"));
+            }
+            if row.get::<_, i64>(4)? > 0 {
+                definition.push_str(" PRIMARY KEY");
+            }
+            Ok((row.get(0)?, definition.trim_start().to_owned()))
+        })?
+        .collect::<Result<_, _>>()?;
+
+    Ok(columns)
+}
+
+/// Row-level differences of a table between two databases, rows being
+/// matched by primary key (or rowid when the table has none)
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct TableDiff {
+    pub table: String,
+    pub columns: Vec<String>,
+    /// Rows only found in the second database
+    pub inserted: Vec<Vec<Value>>,
+    /// Rows found in both databases with different values, as (first, second)
+    pub updated: Vec<(Vec<Value>, Vec<Value>)>,
+    /// Rows only found in the first database
+    pub deleted: Vec<Vec<Value>>,
+}
+
+impl TableDiff {
+    pub fn is_empty(&self) -> bool {
+        self.inserted.is_empty() && self.updated.is_empty() && self.deleted.is_empty()
+    }
+}
+
+/// Compare the rows of `table` between two databases. Every row is loaded in
+/// memory, so this is meant for small reference or configuration tables.
+pub fn data_diff(a: &Connection, b: &Connection, table: &str) -> Result<TableDiff> {
+    let columns = table_columns(a, table)?;
+    if columns != table_columns(b, table)? {
+        anyhow::bail!("Table {table} has different columns in the compared databases");
+    }
+
+    let rows_a = table_rows(a, table)?;
+    let mut rows_b = table_rows(b, table)?;
+
+    let mut diff = TableDiff {
+        table: table.to_owned(),
+        columns,
+        ..Default::default()
+    };
+    for (key, row_a) in rows_a {
+        match rows_b.remove(&key) {
+            None => diff.deleted.push(row_a),
+            Some(row_b) if row_b != row_a => diff.updated.push((row_a, row_b)),
+            Some(_) => {}
+        }
+    }
+    diff.inserted = rows_b.into_values().collect();
+
+    Ok(diff)
+}
+
+/// Render a SQL value the way it would be written in a query
+pub fn format_value(value: &Value) -> String {
+    match value {
+        Value::Null => "NULL".to_owned(),
+        Value::Integer(i) => i.to_string(),
+        Value::Real(f) => f.to_string(),
+        Value::Text(s) => format!("'{}'", s.replace('\'', "''")),
+        Value::Blob(b) => format!(
+            "X'{}'",
+            b.iter().map(|b| format!("{b:02X}")).collect::<String>()
+        ),
+    }
+}
+
+fn table_columns(conn: &Connection, table: &str) -> Result<Vec<String>> {
+    let mut stmt = conn.prepare("SELECT name FROM pragma_table_info(?1) ORDER BY cid")?;
+    let columns = stmt
+        .query_map([table], |row| row.get(0))?
+        .collect::<Result<Vec<String>, _>>()?;
+
+    if columns.is_empty() {
+        anyhow::bail!("Table {table} does not exist");
+    }
+
+    Ok(columns)
+}
+
+// Rows of the table keyed by their primary key columns, or rowid
+fn table_rows(conn: &Connection, table: &str) -> Result<BTreeMap<String, Vec<Value>>> {
+    let mut stmt =
+        conn.prepare("SELECT name FROM pragma_table_info(?1) WHERE pk > 0 ORDER BY pk")?;
+    let mut key_columns = stmt
+        .query_map([table], |row| row.get(0))?
+        .collect::<Result<Vec<String>, _>>()?;
+    if key_columns.is_empty() {
+        key_columns.push("rowid".to_owned());
+    }
+
+    let key = key_columns
+        .iter()
+        .map(|c| format!("\"{}\"", c.replace('"', "\"\"")))
+        .collect::<Vec<_>>()
+        .join(", ");
+    let query = format!("SELECT {key}, * FROM \"{}\"", table.replace('"', "\"\""));
+    let mut stmt = conn
+        .prepare(&query)
+        .context(anyhow::format_err!("query: {}", query))?;
+    let key_len = key_columns.len();
+    let column_count = stmt.column_count();
+
+    let rows = stmt
+        .query_map([], |row| {
+            let values = (0..column_count)
+                .map(|i| row.get::<_, Value>(i))
+                .collect::<Result<Vec<_>, _>>()?;
+            let key = values[..key_len]
+                .iter()
+                .map(format_value)
+                .collect::<Vec<_>>()
+                .join(", ");
+            Ok((key, values[key_len..].to_vec()))
+        })?
+        .collect::<Result<BTreeMap<_, _>, _>>()?;
+
+    Ok(rows)
+}
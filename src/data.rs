@@ -0,0 +1,173 @@
+//! Data files seeding tables after the up SQL of a migration, found as
+//! `data/<table>.csv` or `data/<table>.json` in migration folders.
+#[cfg(feature = "loader")]
+use std::path::Path;
+
+use rusqlite::{types::Value, Connection};
+
+use crate::error::{Error, Result};
+
+/// Folder of the data files of a migration
+pub const DATA_DIR: &str = "data";
+
+/// Rows to insert into a table
+#[derive(Debug, Clone, PartialEq)]
+pub struct TableData {
+    pub table: String,
+    pub columns: Vec<String>,
+    pub rows: Vec<Vec<Value>>,
+}
+
+impl TableData {
+    /// Rows of a CSV file whose header names the columns. Values are
+    /// inserted as text, converted by the column affinity, and empty fields
+    /// are NULL.
+    #[cfg(feature = "loader")]
+    pub fn from_csv(table: &str, path: &Path) -> Result<Self> {
+        let invalid = |e: csv::Error| Error::InvalidData(format!("{}: {e}", path.display()));
+        let mut reader = csv::Reader::from_path(path).map_err(invalid)?;
+        let columns = reader
+            .headers()
+            .map_err(invalid)?
+            .iter()
+            .map(str::to_owned)
+            .collect();
+        let rows = reader
+            .records()
+            .map(|record| {
+                Ok(record
+                    .map_err(invalid)?
+                    .iter()
+                    .map(|field| match field {
+                        "" => Value::Null,
+                        field => Value::Text(field.to_owned()),
+                    })
+                    .collect())
+            })
+            .collect::<Result<_>>()?;
+
+        Ok(Self {
+            table: table.to_owned(),
+            columns,
+            rows,
+        })
+    }
+
+    /// Rows of a JSON file holding a list of objects, the columns being
+    /// every key found. Missing keys are NULL, and nested arrays and objects
+    /// are inserted as JSON text.
+    #[cfg(feature = "loader")]
+    pub fn from_json(table: &str, path: &Path) -> Result<Self> {
+        let invalid =
+            |message: String| Error::InvalidData(format!("{}: {message}", path.display()));
+        let file = std::fs::File::open(path).map_err(|source| Error::FileLoad {
+            path: path.to_owned(),
+            source,
+        })?;
+        let objects: Vec<serde_json::Map<String, serde_json::Value>> =
+            serde_json::from_reader(std::io::BufReader::new(file))
+                .map_err(|e| invalid(format!("expected a list of objects: {e}")))?;
+
+        let mut columns: Vec<String> = Vec::new();
+        for key in objects.iter().flat_map(|o| o.keys()) {
+            if !columns.contains(key) {
+                columns.push(key.clone());
+            }
+        }
+        let rows = objects
+            .iter()
+            .map(|object| {
+                columns
+                    .iter()
+                    .map(|column| match object.get(column) {
+                        None | Some(serde_json::Value::Null) => Value::Null,
+                        Some(serde_json::Value::Bool(b)) => Value::Integer(i64::from(*b)),
+                        Some(serde_json::Value::Number(n)) => n
+                            .as_i64()
+                            .map(Value::Integer)
+                            .or_else(|| n.as_f64().map(Value::Real))
+                            .unwrap_or_else(|| Value::Text(n.to_string())),
+                        Some(serde_json::Value::String(s)) => Value::Text(s.clone()),
+                        Some(nested) => Value::Text(nested.to_string()),
+                    })
+                    .collect()
+            })
+            .collect();
+
+        Ok(Self {
+            table: table.to_owned(),
+            columns,
+            rows,
+        })
+    }
+
+    /// Insert the rows with a prepared statement, returning how many were
+    /// inserted
+    pub fn insert(&self, conn: &Connection, version: usize) -> Result<u64> {
+        if self.rows.is_empty() {
+            return Ok(0);
+        }
+
+        let sql = format!(
+            "INSERT INTO {} VALUES ({})",
+            self.target(),
+            vec!["?"; self.columns.len()].join(", ")
+        );
+        let data_error = |row: usize| {
+            move |source| Error::DataError {
+                version,
+                table: self.table.clone(),
+                row,
+                source,
+            }
+        };
+
+        let mut stmt = conn.prepare(&sql).map_err(data_error(0))?;
+        for (i, row) in self.rows.iter().enumerate() {
+            stmt.execute(rusqlite::params_from_iter(row))
+                .map_err(data_error(i + 1))?;
+        }
+
+        Ok(self.rows.len() as u64)
+    }
+
+    /// INSERT statements of the rows with their values inline, for scripts
+    /// applying the migration without its data files
+    pub fn insert_sql(&self) -> String {
+        self.rows
+            .iter()
+            .map(|row| {
+                let values = row.iter().map(literal).collect::<Vec<_>>().join(", ");
+                format!("INSERT INTO {} VALUES ({values});\n", self.target())
+            })
+            .collect()
+    }
+
+    // `"table" ("column", ...)`
+    fn target(&self) -> String {
+        let quote = |name: &str| format!("\"{}\"", name.replace('"', "\"\""));
+        format!(
+            "{} ({})",
+            quote(&self.table),
+            self.columns
+                .iter()
+                .map(|c| quote(c))
+                .collect::<Vec<_>>()
+                .join(", ")
+        )
+    }
+}
+
+// SQL literal of `value`
+fn literal(value: &Value) -> String {
+    match value {
+        Value::Null => "NULL".to_owned(),
+        Value::Integer(i) => i.to_string(),
+        Value::Real(f) => format!("{f:?}"),
+        Value::Text(s) => format!("'{}'", s.replace('\'', "''")),
+        Value::Blob(bytes) => format!(
+            "X'{}'",
+            bytes.iter().map(|b| format!("{b:02X}")).collect::<String>()
+        ),
+    }
+}
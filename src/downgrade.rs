@@ -0,0 +1,138 @@
+//! Checks that pending migrations keep the schema readable by the previous
+//! application release, for rolling deployments where both run at once.
+use anyhow::{Context, Result};
+use rusqlite::Connection;
+
+use crate::migration::{execute_statements, Migrations};
+
+/// Something the previous release uses that the pending migrations remove.
+#[derive(Debug)]
+pub enum Breakage {
+    /// A query of the previous release no longer compiles.
+    Query { query: String, error: String },
+    /// A table or view of the previous schema is gone.
+    Table(String),
+    /// A column of the previous schema is gone.
+    Column { table: String, column: String },
+}
+
+impl std::fmt::Display for Breakage {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Breakage::Query { query, error } => write!(f, "query {query:?} fails: {error}"),
+            Breakage::Table(table) => write!(f, "table {table} is removed"),
+            Breakage::Column { table, column } => {
+                write!(f, "column {table}.{column} is removed")
+            }
+        }
+    }
+}
+
+impl Migrations {
+    /// Copy the schema of `conn` into an in-memory database and apply the
+    /// pending migrations to it, without touching `conn`.
+    ///
+    /// Only the SQL is run: shell commands are left out and `only_if` guards,
+    /// which depend on data the copy doesn't have, are assumed true.
+    pub fn migrated_schema(&self, conn: &Connection) -> Result<Connection> {
+        let copy = schema_copy(conn)?;
+        let current: usize = self.current_version(conn)?.into();
+
+        for (version, m) in self.iter().filter(|(v, _)| *v > current) {
+            execute_statements(&copy, version, m.get_up()).context(format!(
+                "Failed to apply migration {:04} {}",
+                version,
+                m.get_comment().unwrap_or_default()
+            ))?;
+        }
+
+        Ok(copy)
+    }
+}
+
+/// In-memory database with the tables, indexes, views and triggers of `conn`.
+pub fn schema_copy(conn: &Connection) -> Result<Connection> {
+    let copy = Connection::open_in_memory()?;
+
+    let mut stmt = conn.prepare(
+        "SELECT sql FROM sqlite_master
+         WHERE sql IS NOT NULL AND name NOT LIKE 'sqlite_%'
+         ORDER BY CASE type WHEN 'table' THEN 0 WHEN 'index' THEN 1 WHEN 'view' THEN 2 ELSE 3 END, rowid",
+    )?;
+    let statements = stmt
+        .query_map([], |row| row.get::<_, String>(0))?
+        .collect::<Result<Vec<_>, _>>()?;
+    for sql in statements {
+        copy.execute_batch(&sql)
+            .context(format!("Failed to copy schema statement {sql:?}"))?;
+    }
+
+    Ok(copy)
+}
+
+/// Queries which no longer compile against `conn`.
+pub fn broken_queries(conn: &Connection, queries: &[String]) -> Vec<Breakage> {
+    queries
+        .iter()
+        .filter_map(|query| {
+            conn.prepare(query).err().map(|err| Breakage::Query {
+                query: query.clone(),
+                error: err.to_string(),
+            })
+        })
+        .collect()
+}
+
+/// Tables, views and columns of `old` missing from `new`.
+pub fn removed_objects(old: &Connection, new: &Connection) -> Result<Vec<Breakage>> {
+    let mut breakages = Vec::new();
+
+    let new_tables = tables(new)?;
+    for table in tables(old)? {
+        if !new_tables.contains(&table) {
+            breakages.push(Breakage::Table(table));
+            continue;
+        }
+
+        let new_columns = columns(new, &table)?;
+        for column in columns(old, &table)? {
+            if !new_columns.contains(&column) {
+                breakages.push(Breakage::Column {
+                    table: table.clone(),
+                    column,
+                });
+            }
+        }
+    }
+
+    Ok(breakages)
+}
+
+/// Split a file of queries separated by semicolons.
+pub fn split_queries(sql: &str) -> Vec<String> {
+    sql.split(';')
+        .map(str::trim)
+        .filter(|q| !q.is_empty())
+        .map(str::to_owned)
+        .collect()
+}
+
+fn tables(conn: &Connection) -> Result<Vec<String>> {
+    let mut stmt = conn.prepare(
+        "SELECT name FROM sqlite_master
+         WHERE type IN ('table', 'view') AND name NOT LIKE 'sqlite_%' AND name NOT LIKE '\\_migrator\\_%' ESCAPE '\\'
+         ORDER BY name",
+    )?;
+    let names = stmt
+        .query_map([], |row| row.get(0))?
+        .collect::<Result<Vec<_>, _>>()?;
+    Ok(names)
+}
+
+fn columns(conn: &Connection, table: &str) -> Result<Vec<String>> {
+    let mut stmt = conn.prepare("SELECT name FROM pragma_table_info(?1)")?;
+    let names = stmt
+        .query_map([table], |row| row.get(0))?
+        .collect::<Result<Vec<_>, _>>()?;
+    Ok(names)
+}
@@ -0,0 +1,188 @@
+//! Error type of the library, so that callers can match on the kind of
+//! failure.
+use std::{fmt, path::PathBuf};
+
+use crate::migration::SchemaVersion;
+
+pub type Result<T, E = Error> = std::result::Result<T, E>;
+
+#[derive(Debug, thiserror::Error)]
+#[non_exhaustive]
+pub enum Error {
+    /// A migration file or directory could not be read
+    #[error("Could not read {path:?}")]
+    FileLoad {
+        path: PathBuf,
+        #[source]
+        source: std::io::Error,
+    },
+    /// The migration directory contains no migration
+    #[error("Directory does not contain any migration files")]
+    NoMigrationFiles,
+    /// A migration folder name doesn't start with a valid id, or the ids
+    /// aren't consecutive and unique
+    #[error("{0}")]
+    InvalidId(String),
+    /// Several migrations have the same id, typically created on two branches
+    /// merged since
+    #[error(
+        "Migration id {id} is used by several migrations, probably created on separate branches: {}",
+        .paths.iter().map(|p| p.display().to_string()).collect::<Vec<_>>().join(", ")
+    )]
+    DuplicateId { id: usize, paths: Vec<PathBuf> },
+    /// Entries of the migration directory are invalid, every problem found is
+    /// listed with the path of its entry
+    #[error("{}", format_problems(.0))]
+    InvalidDirectory(Vec<DirectoryProblem>),
+    /// A migration has no up SQL
+    #[error("up.sql is missing or empty")]
+    EmptyMigration,
+    /// A data file of a migration can't be parsed
+    #[error("{0}")]
+    InvalidData(String),
+    /// A migration name doesn't follow the strict naming rules
+    #[error("{0}")]
+    InvalidName(String),
+    /// The migration set is empty
+    #[error("migration definition: no migration defined")]
+    NoMigrationDefined,
+    /// The database version is beyond the migrations defined
+    #[error("migration definition: database too far ahead")]
+    DatabaseTooFarAhead,
+    /// The requested version is beyond the migrations defined
+    #[error("specified schema version {requested}: higher than max supported version {max}")]
+    SpecifiedSchemaVersion {
+        requested: SchemaVersion,
+        max: SchemaVersion,
+    },
+    /// A migration to revert has no down SQL
+    #[error("migration definition: down not defined for migration {version}")]
+    MissingDown { version: usize },
+    /// A migration failed after the ones preceding it were committed, see
+    /// [`Migrations::partial_commit`](crate::migration::Migrations::partial_commit)
+    #[error("migration failed, the database was committed at version {version}")]
+    PartiallyApplied {
+        version: usize,
+        #[source]
+        source: Box<Error>,
+    },
+    /// A migration ran longer than its timeout and was interrupted, the
+    /// source being the error of the statement running
+    #[error("migration {version:04} exceeded its timeout of {}s", .timeout.as_secs_f64())]
+    Timeout {
+        version: usize,
+        timeout: std::time::Duration,
+        #[source]
+        source: Box<Error>,
+    },
+    /// The run was interrupted, see
+    /// [`Migrations::interrupt_flag`](crate::migration::Migrations::interrupt_flag)
+    #[error("migration {version:04} interrupted")]
+    Interrupted { version: usize },
+    /// A migration to revert is marked irreversible
+    #[error("irreversible migration {version:04}")]
+    Irreversible { version: usize },
+    /// A statement of a migration failed
+    #[error("migration {version}, query at line {line}: {query}")]
+    SqlError {
+        version: usize,
+        line: usize,
+        query: String,
+        #[source]
+        source: rusqlite::Error,
+    },
+    /// A row of a data file of a migration could not be inserted, row 0
+    /// meaning the insert statement itself failed
+    #[error("migration {version}, data row {row} of table {table}")]
+    DataError {
+        version: usize,
+        table: String,
+        row: usize,
+        #[source]
+        source: rusqlite::Error,
+    },
+    /// A migration left rows violating foreign keys
+    #[error(
+        "foreign key error: table: {table:?}, rowid: {rowid:?}, parent: {parent:?}, fkid: {fkid:?}"
+    )]
+    ForeignKeyViolation {
+        table: String,
+        rowid: i64,
+        parent: String,
+        fkid: i64,
+    },
+    /// An assertion query of a migration returned false
+    #[error("migration {version}, assertion failed at line {line}: {query}")]
+    AssertionFailed {
+        version: usize,
+        line: usize,
+        query: String,
+    },
+    /// Reverting a migration, or applying it again after reverting it, didn't
+    /// leave the schema it should, see
+    /// [`Migrations::validate_round_trip`](crate::migration::Migrations::validate_round_trip)
+    #[error(
+        "migration {version:04} doesn't round-trip, the schema after {step} differs: {difference}"
+    )]
+    RoundTrip {
+        version: usize,
+        step: &'static str,
+        difference: String,
+    },
+    /// The database is at a version replaced by a baseline migration, which
+    /// would be applied on top of the schema it already has
+    #[error("the database is at version {version}, before the baseline migration {baseline:04}; migrate it to the baseline with the archived migrations first")]
+    BeforeBaseline { version: usize, baseline: usize },
+    /// Imported history entries don't fit the database or migration set
+    #[error("{0}")]
+    InvalidImport(String),
+    /// The database stayed locked by another connection after every retry
+    #[error("database is locked by another connection, gave up after {attempts} attempts")]
+    Busy {
+        attempts: u32,
+        #[source]
+        source: rusqlite::Error,
+    },
+    /// Any other SQLite error
+    #[error(transparent)]
+    Sqlite(#[from] rusqlite::Error),
+    /// Errors of hooks, shell commands and the history and lock tables
+    #[error(transparent)]
+    Other(#[from] anyhow::Error),
+}
+
+/// A problem with an entry of the migration directory
+#[derive(Debug)]
+pub struct DirectoryProblem {
+    pub path: PathBuf,
+    pub error: Error,
+}
+
+impl fmt::Display for DirectoryProblem {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}: {}", self.path.display(), self.error)?;
+        // sources aren't part of the error message, and tell e.g. why a file
+        // couldn't be read
+        let mut source = std::error::Error::source(&self.error);
+        while let Some(err) = source {
+            write!(f, ": {err}")?;
+            source = err.source();
+        }
+        Ok(())
+    }
+}
+
+fn format_problems(problems: &[DirectoryProblem]) -> String {
+    match problems {
+        [problem] => problem.to_string(),
+        problems => format!(
+            "{} problems in the migration directory:\n  {}",
+            problems.len(),
+            problems
+                .iter()
+                .map(ToString::to_string)
+                .collect::<Vec<_>>()
+                .join("\n  ")
+        ),
+    }
+}
@@ -0,0 +1,141 @@
+//! Failed migration attempts, recorded with their error once the migration
+//! transaction is rolled back so that they survive it.
+use anyhow::{Context, Result};
+use rusqlite::{Connection, OptionalExtension};
+
+use crate::{error::Error, history};
+
+pub const FAILURES_TABLE: &str = "_migrator_failures";
+
+/// A migration which failed to apply or revert
+#[derive(Debug, Clone, PartialEq, Eq, serde::Serialize)]
+pub struct Failure {
+    pub version: usize,
+    pub name: String,
+    /// `up` or `down`
+    pub direction: String,
+    /// Error message, with its causes
+    pub error: String,
+    /// Statement which failed, when the error comes from one
+    pub statement: Option<String>,
+    /// RFC 3339 timestamp
+    pub failed_at: String,
+    pub failed_by: String,
+    pub hostname: String,
+    /// Migrator build which ran the migration, see
+    /// [`history::TOOL_VERSION`]
+    pub tool_version: String,
+}
+
+impl Failure {
+    /// Failure of a migration run now by the current user, from this host
+    pub fn new(version: usize, name: &str, direction: &str, error: &Error) -> Self {
+        let mut message = error.to_string();
+        let mut source = std::error::Error::source(error);
+        while let Some(cause) = source {
+            message.push_str(&format!(": {cause}"));
+            source = cause.source();
+        }
+
+        Self {
+            version,
+            name: name.to_owned(),
+            direction: direction.to_owned(),
+            error: message,
+            statement: statement(error).map(str::to_owned),
+            failed_at: history::now(),
+            failed_by: history::current_user(),
+            hostname: history::current_host(),
+            tool_version: history::TOOL_VERSION.to_owned(),
+        }
+    }
+}
+
+// Statement of the migration reported by `error`
+fn statement(error: &Error) -> Option<&str> {
+    match error {
+        Error::SqlError { query, .. } | Error::AssertionFailed { query, .. } => Some(query),
+        Error::Timeout { source, .. } | Error::PartiallyApplied { source, .. } => statement(source),
+        _ => None,
+    }
+}
+
+/// Record a failed attempt, creating the table if needed
+pub fn record(conn: &Connection, failure: &Failure) -> Result<()> {
+    conn.execute_batch(&format!(
+        "CREATE TABLE IF NOT EXISTS {FAILURES_TABLE} (
+            id INTEGER PRIMARY KEY,
+            version INTEGER NOT NULL,
+            name TEXT NOT NULL,
+            direction TEXT NOT NULL,
+            error TEXT NOT NULL,
+            statement TEXT,
+            failed_at TEXT NOT NULL,
+            failed_by TEXT NOT NULL,
+            hostname TEXT NOT NULL,
+            tool_version TEXT NOT NULL
+        )"
+    ))
+    .context("Could not create the failures table")?;
+    conn.execute(
+        &format!(
+            "INSERT INTO {FAILURES_TABLE}
+             (version, name, direction, error, statement, failed_at, failed_by, hostname,
+              tool_version)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9)"
+        ),
+        rusqlite::params![
+            failure.version,
+            failure.name,
+            failure.direction,
+            failure.error,
+            failure.statement,
+            failure.failed_at,
+            failure.failed_by,
+            failure.hostname,
+            failure.tool_version,
+        ],
+    )
+    .context("Could not record the failed migration")?;
+
+    Ok(())
+}
+
+/// Every recorded failure, oldest first. Empty if no migration ever failed
+/// on the database.
+pub fn failures(conn: &Connection) -> Result<Vec<Failure>> {
+    let exists = conn
+        .query_row(
+            "SELECT 1 FROM sqlite_master WHERE type = 'table' AND name = ?1",
+            [FAILURES_TABLE],
+            |_| Ok(()),
+        )
+        .optional()?
+        .is_some();
+    if !exists {
+        return Ok(Vec::new());
+    }
+
+    let mut stmt = conn.prepare(&format!(
+        "SELECT version, name, direction, error, statement, failed_at, failed_by, hostname,
+                tool_version
+         FROM {FAILURES_TABLE} ORDER BY id"
+    ))?;
+    let failures = stmt
+        .query_map([], |row| {
+            Ok(Failure {
+                version: row.get(0)?,
+                name: row.get(1)?,
+                direction: row.get(2)?,
+                error: row.get(3)?,
+                statement: row.get(4)?,
+                failed_at: row.get(5)?,
+                failed_by: row.get(6)?,
+                hostname: row.get(7)?,
+                tool_version: row.get(8)?,
+            })
+        })?
+        .collect::<Result<Vec<_>, _>>()?;
+
+    Ok(failures)
+}
@@ -0,0 +1,161 @@
+//! Development fixtures: SQL and data files filling local databases with
+//! realistic data once they reach the latest version, tracked apart from the
+//! schema version so that real migrations never depend on them.
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
+
+use anyhow::{Context, Result};
+use rusqlite::{Connection, OptionalExtension, Transaction, TransactionBehavior};
+use tracing::{info, warn};
+
+use crate::{checksum, data::TableData, history, migration::execute_statements};
+
+pub const FIXTURES_TABLE: &str = "_migrator_fixtures";
+
+/// Directory of the fixtures, next to the migration directory
+pub const DEFAULT_DIR: &str = "fixtures";
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum FixtureContent {
+    /// SQL statements, from a `.sql` file
+    Sql(String),
+    /// Rows of a `<table>.csv` or `<table>.json` file
+    Data(TableData),
+}
+
+/// A file of the fixtures tree
+#[derive(Debug, Clone, PartialEq)]
+pub struct Fixture {
+    /// Path relative to the fixtures directory, identifying the fixture
+    pub name: String,
+    /// Checksum of the file, to tell when it changed after being applied
+    pub checksum: String,
+    pub content: FixtureContent,
+}
+
+/// Fixtures of the `dir` tree, in path order. Files other than `.sql`,
+/// `.csv` and `.json` are ignored.
+pub fn load(dir: &Path) -> Result<Vec<Fixture>> {
+    let mut files = Vec::new();
+    collect_files(dir, &mut files)?;
+    files.sort();
+
+    files
+        .into_iter()
+        .filter_map(|path| {
+            let extension = path.extension()?.to_str()?.to_owned();
+            let table = path.file_stem()?.to_str()?.to_owned();
+            let content = match extension.as_str() {
+                "sql" => fs::read_to_string(&path)
+                    .map(FixtureContent::Sql)
+                    .map_err(Into::into),
+                "csv" => TableData::from_csv(&table, &path)
+                    .map(FixtureContent::Data)
+                    .map_err(Into::into),
+                "json" => TableData::from_json(&table, &path)
+                    .map(FixtureContent::Data)
+                    .map_err(Into::into),
+                _ => return None,
+            };
+            Some(fixture(dir, &path, content))
+        })
+        .collect()
+}
+
+fn fixture(dir: &Path, path: &Path, content: Result<FixtureContent>) -> Result<Fixture> {
+    let content = content.context(format!("Could not load fixture {}", path.display()))?;
+    let bytes = fs::read(path).context(format!("Could not read {}", path.display()))?;
+
+    Ok(Fixture {
+        name: path
+            .strip_prefix(dir)
+            .unwrap_or(path)
+            .to_string_lossy()
+            .replace('\\', "/"),
+        checksum: checksum::checksum(&String::from_utf8_lossy(&bytes)),
+        content,
+    })
+}
+
+fn collect_files(dir: &Path, files: &mut Vec<PathBuf>) -> Result<()> {
+    for entry in fs::read_dir(dir).context(format!("Could not read {}", dir.display()))? {
+        let path = entry?.path();
+        if path
+            .file_name()
+            .is_some_and(|n| n.to_string_lossy().starts_with('.'))
+        {
+            continue;
+        }
+        if path.is_dir() {
+            collect_files(&path, files)?;
+        } else {
+            files.push(path);
+        }
+    }
+
+    Ok(())
+}
+
+fn ensure_table(conn: &Connection) -> Result<()> {
+    conn.execute_batch(&format!(
+        "CREATE TABLE IF NOT EXISTS {FIXTURES_TABLE} (
+            name TEXT PRIMARY KEY,
+            checksum TEXT NOT NULL,
+            applied_at TEXT NOT NULL
+        )"
+    ))
+    .context("Could not create the fixtures table")
+}
+
+/// Apply the fixtures not applied to the database yet, in one transaction,
+/// returning their names. A fixture changed since it was applied isn't
+/// rerun, as its rows are already there.
+pub fn apply(conn: &mut Connection, fixtures: &[Fixture]) -> Result<Vec<String>> {
+    let tx = Transaction::new(conn, TransactionBehavior::Immediate)?;
+    ensure_table(&tx)?;
+
+    let mut applied = Vec::new();
+    for fixture in fixtures {
+        let stored: Option<String> = tx
+            .query_row(
+                &format!("SELECT checksum FROM {FIXTURES_TABLE} WHERE name = ?1"),
+                [&fixture.name],
+                |row| row.get(0),
+            )
+            .optional()?;
+        match stored {
+            Some(stored) if stored != fixture.checksum => {
+                warn!(
+                    "Fixture {} changed since it was applied, not rerun",
+                    fixture.name
+                );
+                continue;
+            }
+            Some(_) => continue,
+            None => {}
+        }
+
+        match &fixture.content {
+            FixtureContent::Sql(sql) => execute_statements(&tx, 0, sql).map(|_| ()),
+            FixtureContent::Data(data) => data.insert(&tx, 0).map(|_| ()),
+        }
+        .context(format!("Fixture {} failed", fixture.name))?;
+        tx.execute(
+            &format!(
+                "INSERT INTO {FIXTURES_TABLE} (name, checksum, applied_at) VALUES (?1, ?2, ?3)"
+            ),
+            [
+                fixture.name.clone(),
+                fixture.checksum.clone(),
+                history::now(),
+            ],
+        )?;
+        info!("Applied fixture {}", fixture.name);
+        applied.push(fixture.name.clone());
+    }
+    tx.commit()?;
+
+    Ok(applied)
+}
@@ -0,0 +1,439 @@
+//! History table recording when, how fast and by whom each applied migration
+//! was run.
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use anyhow::{Context, Result};
+use rusqlite::{Connection, OptionalExtension};
+
+pub const HISTORY_TABLE: &str = "_migrator_history";
+
+/// Name and version of the migrator build recording history entries
+pub const TOOL_VERSION: &str = concat!(env!("CARGO_PKG_NAME"), " ", env!("CARGO_PKG_VERSION"));
+
+// Columns of the history table. Columns added after the table was first
+// created in a database are appended with `ALTER TABLE`, so new columns must
+// come last and have a default value.
+const COLUMNS: &[(&str, &str)] = &[
+    ("version", "INTEGER PRIMARY KEY"),
+    ("name", "TEXT NOT NULL DEFAULT ''"),
+    ("applied_at", "TEXT NOT NULL DEFAULT ''"),
+    ("duration_ms", "INTEGER NOT NULL DEFAULT 0"),
+    ("applied_by", "TEXT NOT NULL DEFAULT ''"),
+    ("checksum", "TEXT NOT NULL DEFAULT ''"),
+    ("skipped", "INTEGER NOT NULL DEFAULT 0"),
+    ("schema_hash", "TEXT NOT NULL DEFAULT ''"),
+    ("hostname", "TEXT NOT NULL DEFAULT ''"),
+    ("tool_version", "TEXT NOT NULL DEFAULT ''"),
+    ("description", "TEXT NOT NULL DEFAULT ''"),
+];
+
+/// An applied migration, as recorded in the history table
+#[derive(Debug, Clone, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+pub struct HistoryEntry {
+    pub version: usize,
+    pub name: String,
+    /// RFC 3339 timestamp
+    #[serde(default)]
+    pub applied_at: String,
+    #[serde(default)]
+    pub duration_ms: u64,
+    #[serde(default)]
+    pub applied_by: String,
+    /// Checksum of the up SQL, empty for migrations recorded before checksums
+    /// were stored
+    #[serde(default)]
+    pub checksum: String,
+    /// The migration's `only_if` guard was false, so it was recorded without
+    /// running
+    #[serde(default)]
+    pub skipped: bool,
+    /// Hash of the schema right after the run which left the database at
+    /// this version, see [`schema::hash`](crate::schema::hash). Empty when
+    /// no run ended at this version.
+    #[serde(default)]
+    pub schema_hash: String,
+    /// Host the migration was applied from
+    #[serde(default)]
+    pub hostname: String,
+    /// Migrator build which applied the migration, see [`TOOL_VERSION`]
+    #[serde(default)]
+    pub tool_version: String,
+    /// Description of the migration, so that it can be shown without the
+    /// migration directory
+    #[serde(default)]
+    pub description: String,
+}
+
+impl HistoryEntry {
+    /// Entry of a migration applied now by the current user, from this host
+    /// and with this build
+    pub fn new(version: usize, name: &str, checksum: &str, duration: Duration) -> Self {
+        #[allow(clippy::cast_possible_truncation)]
+        Self {
+            version,
+            name: name.to_owned(),
+            applied_at: now(),
+            duration_ms: duration.as_millis() as u64,
+            applied_by: current_user(),
+            checksum: checksum.to_owned(),
+            skipped: false,
+            schema_hash: String::new(),
+            hostname: current_host(),
+            tool_version: TOOL_VERSION.to_owned(),
+            description: String::new(),
+        }
+    }
+}
+
+fn table_exists(conn: &Connection) -> Result<bool> {
+    Ok(conn
+        .query_row(
+            "SELECT 1 FROM sqlite_master WHERE type = 'table' AND name = ?1",
+            [HISTORY_TABLE],
+            |_| Ok(()),
+        )
+        .optional()?
+        .is_some())
+}
+
+fn existing_columns(conn: &Connection) -> Result<Vec<String>> {
+    let mut stmt = conn.prepare(&format!(
+        "SELECT name FROM pragma_table_info('{HISTORY_TABLE}')"
+    ))?;
+    let existing = stmt
+        .query_map([], |row| row.get::<_, String>(0))?
+        .collect::<Result<Vec<_>, _>>()?;
+
+    Ok(existing)
+}
+
+// Columns of the history table in `COLUMNS` order, with the default value of
+// the columns a table created by an older version lacks, so that it can be
+// read without adding them
+fn select_columns(conn: &Connection) -> Result<String> {
+    let existing = existing_columns(conn)?;
+    Ok(COLUMNS
+        .iter()
+        .map(|(name, def)| {
+            if existing.iter().any(|e| e == name) {
+                (*name).to_owned()
+            } else {
+                def.split_once("DEFAULT ")
+                    .map_or("NULL", |(_, default)| default)
+                    .to_owned()
+            }
+        })
+        .collect::<Vec<_>>()
+        .join(", "))
+}
+
+/// Create the history table, or add the columns it is missing
+pub fn ensure_table(conn: &Connection) -> Result<()> {
+    if !table_exists(conn)? {
+        let columns = COLUMNS
+            .iter()
+            .map(|(name, def)| format!("{name} {def}"))
+            .collect::<Vec<_>>()
+            .join(", ");
+        let query = format!("CREATE TABLE {HISTORY_TABLE} ({columns})");
+        return conn
+            .execute_batch(&query)
+            .context(anyhow::format_err!("query: {}", query));
+    }
+
+    let existing = existing_columns(conn)?;
+    for (name, def) in COLUMNS
+        .iter()
+        .filter(|(name, _)| !existing.iter().any(|e| e == name))
+    {
+        let query = format!("ALTER TABLE {HISTORY_TABLE} ADD COLUMN {name} {def}");
+        conn.execute_batch(&query)
+            .context(anyhow::format_err!("query: {}", query))?;
+    }
+
+    Ok(())
+}
+
+/// Record a migration as applied
+pub fn record(conn: &Connection, entry: &HistoryEntry) -> Result<()> {
+    conn.execute(
+        &format!(
+            "INSERT OR REPLACE INTO {HISTORY_TABLE}
+             (version, name, applied_at, duration_ms, applied_by, checksum, skipped, schema_hash,
+              hostname, tool_version, description)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11)"
+        ),
+        rusqlite::params![
+            entry.version,
+            entry.name,
+            entry.applied_at,
+            entry.duration_ms,
+            entry.applied_by,
+            entry.checksum,
+            entry.skipped,
+            entry.schema_hash,
+            entry.hostname,
+            entry.tool_version,
+            entry.description,
+        ],
+    )
+    .context("Could not record migration in history")?;
+
+    Ok(())
+}
+
+/// Replace the stored name and checksum of an applied migration
+pub fn update(conn: &Connection, version: usize, name: &str, checksum: &str) -> Result<()> {
+    conn.execute(
+        &format!("UPDATE {HISTORY_TABLE} SET name = ?2, checksum = ?3 WHERE version = ?1"),
+        rusqlite::params![version, name, checksum],
+    )
+    .context("Could not update migration in history")?;
+
+    Ok(())
+}
+
+/// Move the entries of applied migrations to a new version and name, given
+/// as `(old name, new version, new name)`, e.g. after their folders were
+/// renumbered. Returns the number of entries moved.
+pub fn renumber(conn: &Connection, renames: &[(&str, usize, &str)]) -> Result<usize> {
+    if !table_exists(conn)? {
+        return Ok(0);
+    }
+
+    // through negative versions, as a new version can be the old version of
+    // another entry
+    let mut moved = 0;
+    for (old, version, name) in renames {
+        moved += conn
+            .execute(
+                &format!("UPDATE {HISTORY_TABLE} SET version = -?2, name = ?3 WHERE name = ?1"),
+                rusqlite::params![old, version, name],
+            )
+            .context(format!("Could not move {old} in history"))?;
+    }
+    conn.execute(
+        &format!("UPDATE {HISTORY_TABLE} SET version = -version WHERE version < 0"),
+        [],
+    )
+    .context("Could not move migrations in history, another entry has their new version")?;
+
+    Ok(moved)
+}
+
+/// Record the hash of the schema once a run left the database at `version`
+pub fn set_schema_hash(conn: &Connection, version: usize, hash: &str) -> Result<()> {
+    conn.execute(
+        &format!("UPDATE {HISTORY_TABLE} SET schema_hash = ?2 WHERE version = ?1"),
+        rusqlite::params![version, hash],
+    )
+    .context("Could not record schema hash in history")?;
+
+    Ok(())
+}
+
+/// Whether the migration was recorded as skipped by its `only_if` guard
+pub fn is_skipped(conn: &Connection, version: usize) -> Result<bool> {
+    if !table_exists(conn)? {
+        return Ok(false);
+    }
+
+    Ok(conn
+        .query_row(
+            &format!("SELECT skipped FROM {HISTORY_TABLE} WHERE version = ?1"),
+            [version],
+            |row| row.get(0),
+        )
+        .optional()?
+        .unwrap_or(false))
+}
+
+/// Remove a reverted migration from the history
+pub fn remove(conn: &Connection, version: usize) -> Result<()> {
+    conn.execute(
+        &format!("DELETE FROM {HISTORY_TABLE} WHERE version = ?1"),
+        [version],
+    )
+    .context("Could not remove migration from history")?;
+
+    Ok(())
+}
+
+/// Every recorded migration, in the order they were applied. Empty if the
+/// database has no history table.
+pub fn entries(conn: &Connection) -> Result<Vec<HistoryEntry>> {
+    if !table_exists(conn)? {
+        return Ok(Vec::new());
+    }
+
+    let mut stmt = conn.prepare(&format!(
+        "SELECT {} FROM {HISTORY_TABLE} ORDER BY applied_at, version",
+        select_columns(conn)?
+    ))?;
+    let entries = stmt
+        .query_map([], |row| {
+            Ok(HistoryEntry {
+                version: row.get(0)?,
+                name: row.get(1)?,
+                applied_at: row.get(2)?,
+                duration_ms: row.get(3)?,
+                applied_by: row.get(4)?,
+                checksum: row.get(5)?,
+                skipped: row.get(6)?,
+                schema_hash: row.get(7)?,
+                hostname: row.get(8)?,
+                tool_version: row.get(9)?,
+                description: row.get(10)?,
+            })
+        })?
+        .collect::<Result<Vec<_>, _>>()?;
+
+    Ok(entries)
+}
+
+/// Difference between the histories of two databases
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct HistoryDiff {
+    /// Entries only found in the left history
+    pub only_left: Vec<HistoryEntry>,
+    /// Entries only found in the right history
+    pub only_right: Vec<HistoryEntry>,
+    /// Versions found in both histories, in the order they were applied on
+    /// each side, when that order differs
+    pub order: Option<(Vec<usize>, Vec<usize>)>,
+}
+
+impl HistoryDiff {
+    pub fn is_empty(&self) -> bool {
+        self.only_left.is_empty() && self.only_right.is_empty() && self.order.is_none()
+    }
+}
+
+/// Compare two histories, as returned by [`entries`]
+pub fn diff(left: &[HistoryEntry], right: &[HistoryEntry]) -> HistoryDiff {
+    let has = |entries: &[HistoryEntry], version| entries.iter().any(|e| e.version == version);
+
+    let only_left = left
+        .iter()
+        .filter(|e| !has(right, e.version))
+        .cloned()
+        .collect();
+    let only_right = right
+        .iter()
+        .filter(|e| !has(left, e.version))
+        .cloned()
+        .collect();
+
+    let left_order = left
+        .iter()
+        .map(|e| e.version)
+        .filter(|v| has(right, *v))
+        .collect::<Vec<_>>();
+    let right_order = right
+        .iter()
+        .map(|e| e.version)
+        .filter(|v| has(left, *v))
+        .collect::<Vec<_>>();
+    let order = (left_order != right_order).then_some((left_order, right_order));
+
+    HistoryDiff {
+        only_left,
+        only_right,
+        order,
+    }
+}
+
+/// Current UTC time as an RFC 3339 timestamp with nanoseconds, e.g.
+/// `2024-03-01T12:00:00.000000000+00:00`
+pub(crate) fn now() -> String {
+    timestamp(
+        SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default(),
+    )
+}
+
+// RFC 3339 UTC timestamp of the time `since_epoch` after 1970-01-01
+fn timestamp(since_epoch: Duration) -> String {
+    let secs = since_epoch.as_secs();
+    let (hour, minute, second) = (secs % 86_400 / 3600, secs % 3600 / 60, secs % 60);
+
+    // date of the day since 1970-01-01, following Howard Hinnant's
+    // `civil_from_days` with eras of 400 years starting on March 1st
+    let days = secs / 86_400 + 719_468;
+    let (era, day_of_era) = (days / 146_097, days % 146_097);
+    let year_of_era =
+        (day_of_era - day_of_era / 1460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let month_from_march = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * month_from_march + 2) / 5 + 1;
+    let month = if month_from_march < 10 {
+        month_from_march + 3
+    } else {
+        month_from_march - 9
+    };
+    let year = era * 400 + year_of_era + u64::from(month <= 2);
+
+    format!(
+        "{year:04}-{month:02}-{day:02}T{hour:02}:{minute:02}:{second:02}.{:09}+00:00",
+        since_epoch.subsec_nanos()
+    )
+}
+
+/// Name of the OS user running the migrator
+pub(crate) fn current_user() -> String {
+    std::env::var("USER")
+        .or_else(|_| std::env::var("USERNAME"))
+        .unwrap_or_default()
+}
+
+pub(crate) fn current_host() -> String {
+    #[cfg(unix)]
+    {
+        let mut name = [0u8; 256];
+        // SAFETY: the buffer is valid for its length, and truncated names
+        // are cut at the first NUL below
+        if unsafe { libc::gethostname(name.as_mut_ptr().cast(), name.len()) } == 0 {
+            let len = name.iter().position(|b| *b == 0).unwrap_or(name.len());
+            return String::from_utf8_lossy(&name[..len]).into_owned();
+        }
+    }
+
+    std::env::var("COMPUTERNAME")
+        .or_else(|_| std::env::var("HOSTNAME"))
+        .unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn at(secs: u64) -> String {
+        timestamp(Duration::from_secs(secs))
+    }
+
+    #[test]
+    fn timestamp_at_the_epoch() {
+        assert_eq!(at(0), "1970-01-01T00:00:00.000000000+00:00");
+    }
+
+    #[test]
+    fn timestamp_on_leap_days() {
+        assert_eq!(at(951_827_696), "2000-02-29T12:34:56.000000000+00:00");
+        assert_eq!(at(946_684_799), "1999-12-31T23:59:59.000000000+00:00");
+    }
+
+    #[test]
+    fn timestamp_in_a_century_non_leap_year() {
+        assert_eq!(at(4_107_542_399), "2100-02-28T23:59:59.000000000+00:00");
+        assert_eq!(at(4_107_542_400), "2100-03-01T00:00:00.000000000+00:00");
+    }
+
+    #[test]
+    fn timestamp_recent() {
+        assert_eq!(
+            timestamp(Duration::new(1_700_000_000, 123_456_789)),
+            "2023-11-14T22:13:20.123456789+00:00"
+        );
+    }
+}
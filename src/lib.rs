@@ -0,0 +1,32 @@
+#[cfg(feature = "loader")]
+pub mod build;
+pub mod busy;
+pub mod checksum;
+pub mod compare;
+pub mod data;
+pub mod downgrade;
+pub mod error;
+pub mod failures;
+#[cfg(feature = "loader")]
+pub mod fixtures;
+pub mod history;
+#[cfg(feature = "loader")]
+pub mod loader;
+pub mod lock;
+#[cfg(feature = "loader")]
+pub mod meta;
+pub mod migration;
+pub mod netfs;
+pub mod preflight;
+pub mod repeatable;
+pub mod schema;
+pub mod shell;
+#[cfg(feature = "signing")]
+pub mod signature;
+pub mod statements;
+pub mod tenants;
+pub mod testing;
+pub mod timeout;
+pub mod vars;
+pub mod verify;
+pub mod version;
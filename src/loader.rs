@@ -1,12 +1,19 @@
-use anyhow::{format_err, Result};
+use flate2::read::GzDecoder;
 use std::{
-    fs::{self, DirEntry, File},
+    collections::{BTreeMap, BTreeSet},
+    fs::{self, DirEntry},
     io::Read,
     num::NonZeroUsize,
-    path::Path,
+    path::{Path, PathBuf},
 };
 
-use crate::migration::M;
+use crate::{
+    data::{TableData, DATA_DIR},
+    error::{DirectoryProblem, Error, Result},
+    meta::{MigrationMeta, META_FILE_NAME},
+    migration::M,
+    repeatable::Repeatable,
+};
 
 #[derive(Debug, Clone)]
 pub struct MigrationFile {
@@ -14,6 +21,74 @@ pub struct MigrationFile {
     pub name: String,
     pub up: String,
     pub down: Option<String>,
+    /// Text of the description header of `up`, see [`DESCRIPTION_HEADER`]
+    pub description: Option<String>,
+    /// Assertion queries checked after running `up`
+    pub assert: Option<String>,
+    /// SQL of `before.sql`, run outside the migration transaction before it
+    pub before_sql: Option<String>,
+    /// SQL of `after.sql`, run outside the migration transaction after it
+    pub after_sql: Option<String>,
+    /// Rows of the files of its `data` folder, inserted after running `up`
+    pub data: Vec<TableData>,
+    pub meta: MigrationMeta,
+}
+
+/// Line of `up.sql` starting its assertion queries, an alternative to a
+/// separate `assert.sql` file
+pub const ASSERT_SECTION: &str = "-- migrator:assert";
+
+/// SQL file of a migration folder run on the connection before the migration
+/// transaction, for statements such as `PRAGMA foreign_keys = OFF` which
+/// have no effect inside it
+pub const BEFORE_FILE: &str = "before.sql";
+
+/// SQL file of a migration folder run on the connection after the migration
+/// transaction is committed, e.g. `ANALYZE` or a WAL checkpoint
+pub const AFTER_FILE: &str = "after.sql";
+
+/// Prefix of a line of the leading comments of `up.sql` describing the
+/// migration, e.g. `-- description: Add the users table`
+pub const DESCRIPTION_HEADER: &str = "-- description:";
+
+/// Folder of the migration directory holding the migrations replaced by a
+/// baseline, in `archive/<date>/` folders, which the loader skips
+pub const ARCHIVE_DIR: &str = "archive";
+
+/// Maximum length of a migration name when strict names are enforced
+pub const DEFAULT_MAX_NAME_LENGTH: usize = 64;
+
+/// Digits of the `YYYY-MM-DD-HHMMSS` timestamps of Diesel migration folders
+const DIESEL_TIMESTAMP_DIGITS: usize = 14;
+
+/// Check that a migration name (without its id prefix) is a lowercase
+/// snake_case ASCII slug of at most `max_length` characters.
+pub fn validate_name(name: &str, max_length: usize) -> Result<()> {
+    if name.is_empty() {
+        return Err(Error::InvalidName(
+            "Migration name cannot be empty".to_owned(),
+        ));
+    }
+
+    if name.len() > max_length {
+        return Err(Error::InvalidName(format!(
+            "Migration name {name:?} is longer than {max_length} characters"
+        )));
+    }
+
+    if !name.starts_with(|c: char| c.is_ascii_lowercase())
+        || !name
+            .chars()
+            .all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '_')
+        || name.ends_with('_')
+        || name.contains("__")
+    {
+        return Err(Error::InvalidName(format!(
+            "Migration name {name:?} is not lowercase snake_case ASCII (e.g. `add_users_table`)"
+        )));
+    }
+
+    Ok(())
 }
 
 fn get_name(value: &DirEntry) -> Result<String> {
@@ -21,10 +96,10 @@ fn get_name(value: &DirEntry) -> Result<String> {
         .path()
         .file_name()
         .and_then(|name| name.to_str())
-        .ok_or(format_err!(
+        .ok_or(Error::InvalidId(format!(
             "Could not extract file name from {:?}",
             value.path()
-        ))?
+        )))?
         .to_owned())
     // .ok_or(Error::FileLoad(format!(
     //     "Could not extract file name from {:?}",
@@ -32,96 +107,446 @@ fn get_name(value: &DirEntry) -> Result<String> {
     // )))
 }
 
-fn get_migrations(name: &str, value: &DirEntry) -> Result<(String, Option<String>)> {
+fn get_migrations(value: &DirEntry) -> Result<(String, Option<String>, Option<String>)> {
     let mut up = String::new();
     let mut down = None;
+    let mut assert = None;
 
-    for entry in std::fs::read_dir(value.path())? {
-        let entry = entry?;
+    for entry in read_dir(&value.path())? {
         let file_name = entry.file_name().into_string().unwrap();
 
         if file_name.ends_with("up.sql") {
-            let mut file = File::open(entry.path())?;
-            file.read_to_string(&mut up)?;
+            up = read_file(&entry.path())?;
+        } else if file_name.ends_with("up.sql.gz") {
+            up = read_gz(&entry.path())?;
         } else if file_name.ends_with("down.sql") {
             let down_file = fs::read_to_string(entry.path()).ok();
             if let Some(down_file) = down_file {
                 down = Some(down_file);
             }
+        } else if file_name.ends_with("down.sql.gz") {
+            down = Some(read_gz(&entry.path())?);
+        } else if file_name == "assert.sql" {
+            assert = Some(read_file(&entry.path())?);
+        }
+    }
+
+    // assertions can also follow the up SQL in the same file
+    if let Some((sql, section)) = split_assert_section(&up) {
+        assert = Some(match assert {
+            Some(file) => format!("{file}\n{section}"),
+            None => section,
+        });
+        up = sql;
+    }
+
+    Ok((up, down, assert))
+}
+
+// Content of the optional `file_name` of a migration folder
+fn get_optional_file(value: &DirEntry, file_name: &str) -> Result<Option<String>> {
+    let path = value.path().join(file_name);
+    path.is_file().then(|| read_file(&path)).transpose()
+}
+
+fn split_assert_section(up: &str) -> Option<(String, String)> {
+    let mut offset = 0;
+    for line in up.split_inclusive('\n') {
+        if line.trim() == ASSERT_SECTION {
+            return Some((
+                up[..offset].to_owned(),
+                up[offset + line.len()..].to_owned(),
+            ));
         }
+        offset += line.len();
+    }
+
+    None
+}
+
+// Description header among the comments `up` starts with
+fn get_description(up: &str) -> Option<String> {
+    up.lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty())
+        .take_while(|line| line.starts_with("--"))
+        .find_map(|line| {
+            let prefix = line.get(..DESCRIPTION_HEADER.len())?;
+            prefix
+                .eq_ignore_ascii_case(DESCRIPTION_HEADER)
+                .then(|| line[DESCRIPTION_HEADER.len()..].trim().to_owned())
+        })
+        .filter(|description| !description.is_empty())
+}
+
+fn file_load(path: &Path) -> impl FnOnce(std::io::Error) -> Error + '_ {
+    |source| Error::FileLoad {
+        path: path.to_owned(),
+        source,
+    }
+}
+
+fn read_dir(path: &Path) -> Result<Vec<DirEntry>> {
+    fs::read_dir(path)
+        .and_then(|entries| entries.collect())
+        .map_err(file_load(path))
+}
+
+fn read_file(path: &Path) -> Result<String> {
+    fs::read_to_string(path).map_err(file_load(path))
+}
+
+// Read a gzip-compressed SQL file
+fn read_gz(path: &Path) -> Result<String> {
+    let mut sql = String::new();
+    fs::File::open(path)
+        .and_then(|file| GzDecoder::new(file).read_to_string(&mut sql))
+        .map_err(file_load(path))?;
+
+    Ok(sql)
+}
+
+fn get_meta(value: &DirEntry) -> Result<MigrationMeta> {
+    let path = value.path().join(META_FILE_NAME);
+    if !path.exists() {
+        return Ok(MigrationMeta::default());
+    }
+
+    Ok(MigrationMeta::from_file(&path)?)
+}
+
+// `data/<table>.csv` and `data/<table>.json` files, in file name order
+fn get_data(value: &DirEntry) -> Result<Vec<TableData>> {
+    let dir = value.path().join(DATA_DIR);
+    if !dir.is_dir() {
+        return Ok(Vec::new());
     }
 
-    Ok((up, down))
+    let mut entries = read_dir(&dir)?;
+    entries.sort_by_key(|e| e.file_name());
+    entries
+        .iter()
+        .filter_map(|entry| {
+            let path = entry.path();
+            let table = path.file_stem()?.to_str()?.to_owned();
+            match path.extension()?.to_str()? {
+                "csv" => Some(TableData::from_csv(&table, &path)),
+                "json" => Some(TableData::from_json(&table, &path)),
+                _ => None,
+            }
+        })
+        .collect()
+}
+
+// Digits of the timestamp prefixing Diesel's `<timestamp>_<name>` folders,
+// e.g. `2017-08-08-150914_create_users`
+fn diesel_timestamp(file_name: &str) -> Option<String> {
+    let (timestamp, _) = file_name.split_once('_')?;
+    let digits = timestamp
+        .chars()
+        .filter(char::is_ascii_digit)
+        .collect::<String>();
+
+    (digits.len() == DIESEL_TIMESTAMP_DIGITS
+        && timestamp.chars().all(|c| c.is_ascii_digit() || c == '-'))
+    .then_some(digits)
 }
 
 fn get_id(file_name: &str) -> Result<NonZeroUsize> {
-    file_name
-        .split_once('-')
-        .ok_or(format_err!(
+    let diesel_timestamp = diesel_timestamp(file_name);
+    diesel_timestamp
+        .as_deref()
+        .map(|digits| (digits, ""))
+        .or_else(|| file_name.split_once('-'))
+        .ok_or(Error::InvalidId(format!(
             "Could not extract migration id from file name {file_name}"
-        ))?
+        )))?
         .0
         .parse::<usize>()
         .map_err(|e| {
-            format_err!("Could not parse migration id from file name {file_name} as usize: {e}")
+            Error::InvalidId(format!(
+                "Could not parse migration id from file name {file_name} as usize: {e}"
+            ))
         })
         .and_then(|v| {
-            NonZeroUsize::new(v).ok_or(format_err!(
+            NonZeroUsize::new(v).ok_or(Error::InvalidId(format!(
                 "{file_name} has an incorrect migration id: migration id cannot be 0"
-            ))
+            )))
+        })
+}
+
+// Stem of a golang-migrate `<id>_<name>.up.sql` or `<id>_<name>.down.sql`
+// file, or of a sqlx `<id>_<name>.sql` up-only file, and whether it is the up
+// one
+fn flat_file(entry: &DirEntry, flyway: bool) -> Option<(String, bool)> {
+    if !entry.file_type().is_ok_and(|t| t.is_file()) {
+        return None;
+    }
+    let file_name = entry.file_name().into_string().ok()?;
+    if flyway {
+        return flyway_file(&file_name);
+    }
+    let (stem, up) = match file_name.strip_suffix(".up.sql") {
+        Some(stem) => (stem, true),
+        None => match file_name.strip_suffix(".down.sql") {
+            Some(stem) => (stem, false),
+            None => (file_name.strip_suffix(".sql")?, true),
+        },
+    };
+    let (id, _) = stem.split_once('_')?;
+
+    (!id.is_empty() && id.chars().all(|c| c.is_ascii_digit())).then(|| (stem.to_owned(), up))
+}
+
+// Stem of a Flyway `V<id>__<name>.sql` migration or `U<id>__<name>.sql` undo
+// migration, with the `V` prefix for both, and whether it is the `V` one
+fn flyway_file(file_name: &str) -> Option<(String, bool)> {
+    let stem = file_name.strip_suffix(".sql")?;
+    let (up, stem) = match stem.split_at_checked(1)? {
+        ("V", stem) => (true, stem),
+        ("U", stem) => (false, stem),
+        _ => return None,
+    };
+    let (id, _) = stem.split_once("__")?;
+
+    (!id.is_empty() && id.chars().all(|c| c.is_ascii_digit())).then(|| (format!("V{stem}"), up))
+}
+
+// Flyway `R__<name>.sql` repeatable migration
+fn is_repeatable(entry: &DirEntry) -> bool {
+    entry.file_type().is_ok_and(|t| t.is_file())
+        && entry
+            .file_name()
+            .to_str()
+            .is_some_and(|n| n.starts_with("R__") && n.ends_with(".sql"))
+}
+
+fn get_flat_id(stem: &str) -> Result<NonZeroUsize> {
+    // Flyway stems keep their `V` prefix
+    let (id, _) = stem
+        .strip_prefix('V')
+        .unwrap_or(stem)
+        .split_once('_')
+        .unwrap_or((stem, ""));
+    id.parse::<usize>()
+        .ok()
+        .and_then(NonZeroUsize::new)
+        .ok_or(Error::InvalidId(format!(
+            "{stem} has an incorrect migration id: migration id cannot be 0"
+        )))
+}
+
+// Pair the flat files of golang-migrate and sqlx directories by their stem, e.g.
+// `000123_add_users.up.sql` with `000123_add_users.down.sql`
+fn flat_migrations(files: Vec<(DirEntry, String, bool)>) -> Vec<(PathBuf, Result<MigrationFile>)> {
+    let mut pairs: BTreeMap<String, (Option<PathBuf>, Option<PathBuf>)> = BTreeMap::new();
+    for (entry, stem, up) in files {
+        let pair = pairs.entry(stem).or_default();
+        if up {
+            pair.0 = Some(entry.path());
+        } else {
+            pair.1 = Some(entry.path());
+        }
+    }
+
+    pairs
+        .into_iter()
+        .map(|(stem, (up, down))| {
+            let path = up.clone().or_else(|| down.clone()).unwrap_or_default();
+            let migration_file = (|| {
+                let id = get_flat_id(&stem)?;
+                let up = up
+                    .as_deref()
+                    .map(read_file)
+                    .transpose()?
+                    .unwrap_or_default();
+                let down = down.as_deref().map(read_file).transpose()?;
+                if up.trim().is_empty() {
+                    return Err(Error::EmptyMigration);
+                }
+                let (up, assert) = match split_assert_section(&up) {
+                    Some((sql, section)) => (sql, Some(section)),
+                    None => (up, None),
+                };
+
+                Ok(MigrationFile {
+                    id,
+                    name: stem,
+                    description: get_description(&up),
+                    up,
+                    down,
+                    assert,
+                    before_sql: None,
+                    after_sql: None,
+                    data: Vec::new(),
+                    meta: MigrationMeta::default(),
+                })
+            })();
+
+            (path, migration_file)
         })
+        .collect()
 }
 
-impl<'a> TryFrom<&'a DirEntry> for MigrationFile {
-    type Error = anyhow::Error;
+impl TryFrom<&DirEntry> for MigrationFile {
+    type Error = Error;
 
     fn try_from(value: &DirEntry) -> std::result::Result<Self, Self::Error> {
         let name = get_name(value)?;
-        let (up, down) = get_migrations(&name, value)?;
+        let (up, down, assert) = get_migrations(value)?;
+        let meta = get_meta(value)?;
+        let data = get_data(value)?;
+        let before_sql = get_optional_file(value, BEFORE_FILE)?;
+        let after_sql = get_optional_file(value, AFTER_FILE)?;
         let id = get_id(&name)?;
 
+        if up.trim().is_empty() {
+            return Err(Error::EmptyMigration);
+        }
+
         Ok(MigrationFile {
             id,
             name,
+            description: get_description(&up),
             up: up.to_string(),
             down: down.map(|f| f.to_string()),
+            assert,
+            before_sql,
+            after_sql,
+            data,
+            meta,
         })
     }
 }
 
+/// Load the migrations of `dir`, in id order. Every invalid entry is
+/// reported at once in an [`Error::InvalidDirectory`], rather than only the
+/// first one.
 pub fn from_directory(dir: &Path) -> Result<Vec<Option<M>>> {
-    let mut entries = fs::read_dir(dir)?.collect::<Result<Vec<_>, std::io::Error>>()?;
+    Ok(load(dir, true, false)?.0.into_iter().map(Some).collect())
+}
+
+/// Load the migrations of `dir` like [`from_directory`], without requiring
+/// ids to be consecutive
+pub fn from_directory_allowing_gaps(dir: &Path) -> Result<Vec<M>> {
+    Ok(load(dir, false, false)?.0)
+}
+
+/// Load the `V<id>__<name>.sql` migrations of `dir`, with their
+/// `U<id>__<name>.sql` undo migrations as down, and its `R__<name>.sql`
+/// repeatable migrations in name order, following Flyway's naming. Ids can
+/// have gaps.
+pub fn from_flyway_directory(dir: &Path) -> Result<(Vec<M>, Vec<Repeatable>)> {
+    load(dir, false, true)
+}
+
+fn load(dir: &Path, strict_sequence: bool, flyway: bool) -> Result<(Vec<M>, Vec<Repeatable>)> {
+    let mut entries = read_dir(dir)?;
+    // hidden entries, such as the `.keep` file of Diesel directories
+    entries.retain(|e| !e.file_name().to_string_lossy().starts_with('.'));
+    entries.retain(|e| e.file_name() != ARCHIVE_DIR);
     entries.sort_by_key(|e| e.file_name());
-    let entries = entries;
+    let (repeatable, entries): (Vec<_>, Vec<_>) = entries
+        .into_iter()
+        .partition(|e| flyway && is_repeatable(e));
+    let repeatable = repeatable
+        .into_iter()
+        .map(|e| {
+            Ok(Repeatable {
+                name: e.file_name().to_string_lossy().into_owned(),
+                sql: read_file(&e.path())?,
+            })
+        })
+        .collect::<Result<Vec<_>>>()?;
+    let (flat_files, entries): (Vec<_>, Vec<_>) = entries
+        .into_iter()
+        .map(|e| (flat_file(&e, flyway), e))
+        .partition(|(flat, _)| flat.is_some());
 
-    let mut migrations: Vec<Option<M>> = vec![None; entries.len()];
+    let mut candidates = entries
+        .into_iter()
+        .map(|(_, e)| (e.path(), MigrationFile::try_from(&e)))
+        .collect::<Vec<_>>();
+    candidates.extend(flat_migrations(
+        flat_files
+            .into_iter()
+            .filter_map(|(flat, e)| flat.map(|(stem, up)| (e, stem, up)))
+            .collect(),
+    ));
 
-    for dir in entries {
-        let migration_file = MigrationFile::try_from(&dir)?;
+    let mut problems = Vec::new();
+    let mut migrations: BTreeMap<usize, (PathBuf, M)> = BTreeMap::new();
+    let mut duplicates: BTreeMap<usize, Vec<PathBuf>> = BTreeMap::new();
+    // ids of invalid entries, which aren't reported as missing on top
+    let mut invalid_ids = BTreeSet::new();
 
-        let id = usize::from(migration_file.id) - 1;
-        if migrations.len() <= id {
-            anyhow::bail!("Migration ids must be consecutive numbers");
-        }
+    for (path, migration_file) in candidates {
+        let migration_file = match migration_file {
+            Ok(migration_file) => migration_file,
+            Err(error) => {
+                if let Some(id) = path
+                    .file_name()
+                    .and_then(|n| n.to_str())
+                    .and_then(|n| get_id(n).or_else(|_| get_flat_id(n)).ok())
+                {
+                    invalid_ids.insert(usize::from(id));
+                }
+                problems.push(DirectoryProblem { path, error });
+                continue;
+            }
+        };
 
-        if migrations[id].is_some() {
-            anyhow::bail!(
-                "Multiple migrations detected for migration id: {}",
-                migration_file.id
-            );
+        let id = usize::from(migration_file.id);
+        if let Some((first, _)) = migrations.get(&id) {
+            duplicates
+                .entry(id)
+                .or_insert_with(|| vec![first.clone()])
+                .push(path);
+            continue;
         }
 
-        migrations[id] = Some((&migration_file).into());
+        migrations.insert(id, (path, (&migration_file).into()));
+    }
+
+    problems.extend(duplicates.into_iter().map(|(id, paths)| DirectoryProblem {
+        path: dir.to_owned(),
+        error: Error::DuplicateId { id, paths },
+    }));
+
+    if migrations.is_empty() && problems.is_empty() {
+        return Err(Error::NoMigrationFiles);
     }
 
-    if migrations.iter().all(|m| m.is_none()) {
-        anyhow::bail!("Directory does not contain any migration files".to_string(),);
+    let last_id = if strict_sequence {
+        migrations.keys().next_back().copied().unwrap_or_default()
+    } else {
+        0
+    };
+    // ids start at the last baseline, the migrations before it are archived
+    let first_id = migrations
+        .iter()
+        .filter(|(_, (_, m))| m.get_baseline())
+        .map(|(id, _)| *id)
+        .next_back()
+        .unwrap_or(1);
+    for id in
+        (first_id..last_id).filter(|id| !migrations.contains_key(id) && !invalid_ids.contains(id))
+    {
+        problems.push(DirectoryProblem {
+            path: dir.to_owned(),
+            error: Error::InvalidId(format!(
+                "Migration ids must be consecutive numbers, id {id} is missing"
+            )),
+        });
     }
 
-    if migrations.iter().any(|m| m.is_none()) {
-        anyhow::bail!("Migration ids must be consecutive numbers".to_string(),);
+    if !problems.is_empty() {
+        return Err(Error::InvalidDirectory(problems));
     }
 
     // The values are returned in the order of the keys, i.e. of IDs
-    Ok(migrations)
+    Ok((
+        migrations.into_values().map(|(_, m)| m).collect(),
+        repeatable,
+    ))
 }
@@ -0,0 +1,117 @@
+//! Migration lock stored in the database, preventing concurrent migration
+//! runs.
+use anyhow::{Context, Result};
+use rusqlite::{Connection, OptionalExtension};
+use tracing::warn;
+
+use crate::{busy, history};
+
+pub const LOCK_TABLE: &str = "_migrator_lock";
+
+/// Holder of the migration lock
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LockInfo {
+    pub holder: String,
+    /// RFC 3339 timestamp
+    pub acquired_at: String,
+    pub reason: String,
+}
+
+fn ensure_table(conn: &Connection) -> Result<()> {
+    conn.execute_batch(&format!(
+        "CREATE TABLE IF NOT EXISTS {LOCK_TABLE} (
+            id INTEGER PRIMARY KEY CHECK (id = 1),
+            holder TEXT NOT NULL,
+            acquired_at TEXT NOT NULL,
+            reason TEXT NOT NULL
+        )"
+    ))
+    .context("Could not create the migration lock table")
+}
+
+/// Take the migration lock, failing if it is already held
+pub fn acquire(conn: &Connection, reason: &str) -> Result<()> {
+    ensure_table(conn)?;
+
+    let inserted = conn.execute(
+        &format!(
+            "INSERT OR IGNORE INTO {LOCK_TABLE} (id, holder, acquired_at, reason)
+             VALUES (1, ?1, ?2, ?3)"
+        ),
+        [holder(), history::now(), reason.to_owned()],
+    )?;
+
+    if inserted == 0 {
+        let info = status(conn)?.context("Migration lock disappeared while acquiring it")?;
+        anyhow::bail!(
+            "migration lock held by {} since {} ({})",
+            info.holder,
+            info.acquired_at,
+            info.reason
+        );
+    }
+
+    Ok(())
+}
+
+/// Release the migration lock, whoever holds it
+pub fn release(conn: &Connection) -> Result<()> {
+    ensure_table(conn)?;
+    conn.execute(&format!("DELETE FROM {LOCK_TABLE}"), [])
+        .context("Could not release the migration lock")?;
+
+    Ok(())
+}
+
+/// Current holder of the migration lock, if any
+pub fn status(conn: &Connection) -> Result<Option<LockInfo>> {
+    let exists = conn
+        .query_row(
+            "SELECT 1 FROM sqlite_master WHERE type = 'table' AND name = ?1",
+            [LOCK_TABLE],
+            |_| Ok(()),
+        )
+        .optional()?
+        .is_some();
+    if !exists {
+        return Ok(None);
+    }
+
+    Ok(conn
+        .query_row(
+            &format!("SELECT holder, acquired_at, reason FROM {LOCK_TABLE} WHERE id = 1"),
+            [],
+            |row| {
+                Ok(LockInfo {
+                    holder: row.get(0)?,
+                    acquired_at: row.get(1)?,
+                    reason: row.get(2)?,
+                })
+            },
+        )
+        .optional()?)
+}
+
+/// Run `f` while holding the migration lock, releasing it afterwards even if
+/// `f` fails, in which case its error is returned rather than a failure to
+/// release the lock. Taking the lock is retried while the database is locked by
+/// another connection.
+pub fn with_lock<T>(
+    conn: &mut Connection,
+    reason: &str,
+    retry: busy::Retry,
+    f: impl FnOnce(&mut Connection) -> Result<T>,
+) -> Result<T> {
+    retry.run(busy::is_busy_chain, || acquire(conn, reason))?;
+    let res = f(conn);
+    match (release(conn), &res) {
+        (Err(err), Err(_)) => warn!("{:#}", err),
+        (Err(err), Ok(_)) => return Err(err),
+        (Ok(()), _) => {}
+    }
+    res
+}
+
+fn holder() -> String {
+    format!("{} (pid {})", history::current_user(), std::process::id())
+}
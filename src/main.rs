@@ -1,18 +1,21 @@
 pub mod command;
-pub mod loader;
-pub mod migration;
 
 use std::{
-    fs::{self, File},
-    io,
+    collections::HashMap,
+    fs::File,
     path::{Path, PathBuf},
+    time::{Duration, Instant},
 };
 
 use anyhow::{anyhow, Context, Result};
 use clap::Parser;
-use rusqlite::Connection;
+use rusqlite::{Connection, OpenFlags};
 
-use crate::migration::Migrations;
+use sqlite_migrator::{
+    checksum, fixtures, loader, lock,
+    migration::{MigrationRun, Migrations, TransactionMode},
+    netfs, preflight, shell, tenants, version,
+};
 
 /// Run SQLite migration files from a given directory.
 #[derive(clap::Parser, Debug, Clone)]
@@ -25,16 +28,162 @@ struct MigrateCli {
     source: Option<PathBuf>,
     #[arg(short, long, env = "DATABASE_PATH", value_hint = clap::ValueHint::FilePath)]
     database: Option<PathBuf>,
+    /// Read the config from this file instead of `.migrate-config.yaml` in
+    /// the working directory
+    #[arg(short, long, env = "MIGRATOR_CONFIG", value_hint = clap::ValueHint::FilePath)]
+    config: Option<PathBuf>,
+    /// Use a throwaway in-memory database, same as `--database :memory:`
+    #[arg(long, conflicts_with = "database")]
+    scratch: bool,
+    #[command(flatten)]
+    open_flags: OpenFlagsArgs,
+    /// Create the migration directory if it doesn't exist
+    #[arg(long)]
+    ensure_dir: bool,
+    /// Format of the logs written to stderr
+    #[arg(long, value_enum, default_value_t)]
+    log_format: LogFormat,
+    /// Answer yes to the prompts of these categories
+    #[arg(long, value_enum, value_delimiter = ',', value_name = "CATEGORY")]
+    assume_yes_for: Vec<command::PromptCategory>,
+    /// Retry taking the migration lock and starting the migration transaction
+    /// this many times while the database is locked
+    #[arg(long, value_name = "N")]
+    busy_retries: Option<u32>,
+}
+
+#[derive(clap::ValueEnum, Debug, Clone, Copy, Default)]
+enum LogFormat {
+    /// Human readable lines
+    #[default]
+    Text,
+    /// One JSON object per event, for log pipelines
+    Json,
+}
+
+/// Flags used when opening the database, see rusqlite's `OpenFlags`
+#[derive(clap::Args, serde::Deserialize, serde::Serialize, Debug, Clone, Copy, Default)]
+#[serde(default)]
+struct OpenFlagsArgs {
+    /// Fail if the database file does not exist instead of creating it
+    #[arg(long)]
+    no_create: bool,
+    /// Open the database in read-only mode
+    #[arg(long)]
+    read_only: bool,
+    /// Open the database in serialized threading mode
+    #[arg(long)]
+    full_mutex: bool,
+    /// Open the database with a private cache
+    #[arg(long)]
+    private_cache: bool,
+    /// Allow writing to a database on a network filesystem
+    #[arg(long)]
+    unsafe_network_fs: bool,
+    /// Wait up to this many milliseconds for locks held by other connections
+    #[arg(long, value_name = "MS")]
+    busy_timeout: Option<u64>,
+}
+
+impl OpenFlagsArgs {
+    fn merge(self, other: Self) -> Self {
+        Self {
+            no_create: self.no_create || other.no_create,
+            read_only: self.read_only || other.read_only,
+            full_mutex: self.full_mutex || other.full_mutex,
+            private_cache: self.private_cache || other.private_cache,
+            unsafe_network_fs: self.unsafe_network_fs || other.unsafe_network_fs,
+            busy_timeout: other.busy_timeout.or(self.busy_timeout),
+        }
+    }
+
+    fn to_flags(self) -> OpenFlags {
+        let mut flags = OpenFlags::default();
+        if self.read_only {
+            flags.remove(OpenFlags::SQLITE_OPEN_READ_WRITE | OpenFlags::SQLITE_OPEN_CREATE);
+            flags.insert(OpenFlags::SQLITE_OPEN_READ_ONLY);
+        }
+        if self.no_create {
+            flags.remove(OpenFlags::SQLITE_OPEN_CREATE);
+        }
+        if self.full_mutex {
+            flags.remove(OpenFlags::SQLITE_OPEN_NO_MUTEX);
+            flags.insert(OpenFlags::SQLITE_OPEN_FULL_MUTEX);
+        }
+        if self.private_cache {
+            flags.insert(OpenFlags::SQLITE_OPEN_PRIVATE_CACHE);
+        }
+        flags
+    }
 }
 
 #[derive(clap::Subcommand, Debug, Clone)]
 enum Commands {
     /// Create a new migration
     Create(CreateArgs),
+    /// Set up a project, or bring an existing database under migration
+    /// control
+    Init(InitArgs),
     /// Run migration UP to most recent or N
-    Up(UpArgs),
+    Up(UpCommandArgs),
     /// Run migration DOWN to oldest or N
     Down(DownArgs),
+    /// Stay resident and run UP on a cron schedule
+    Daemon(DaemonArgs),
+    /// Show which migrations are applied and which are pending
+    #[command(visible_alias = "list")]
+    Status(StatusArgs),
+    /// Check the migration directory for problems
+    Lint(LintArgs),
+    /// Apply, revert and reapply each migration on its own in an in-memory
+    /// database, reporting which pass
+    Test,
+    /// List applied migrations with when, how fast and by whom they ran
+    History(HistoryArgs),
+    /// Check that applied migrations still match the migration directory
+    Verify,
+    /// Update stored checksums and names to match the migration directory
+    Repair(RepairArgs),
+    /// Compare two databases
+    Compare(CompareArgs),
+    /// Show the migrations UP would apply
+    Plan(PlanArgs),
+    /// List the migrations not applied yet
+    Pending,
+    /// Record migrations applied by an external executor
+    ImportState(ImportStateArgs),
+    /// Check that pending migrations keep the previous release working
+    VerifyDowngradeSafety(VerifyDowngradeSafetyArgs),
+    /// Manage the registry of tenant databases
+    Tenant(TenantArgs),
+    /// Print the current and latest schema versions
+    Version,
+    /// Diagnose common setup problems
+    Doctor,
+    /// Inspect the config file
+    Config(ConfigArgs),
+    /// Print a normalized snapshot of the schema the migrations produce
+    Snapshot(SnapshotArgs),
+    /// Print a diagram of the tables and foreign keys the migrations produce
+    Graph(GraphArgs),
+    /// Bundle up migrations into a single SQL script to apply by hand
+    Export(ExportArgs),
+    /// Convert the migrations of another tool into migration folders
+    Import(ImportArgs),
+    /// Generate code embedding the migrations
+    Generate(GenerateArgs),
+    /// Sign the migrations, for `up --require-signature`
+    Sign(SignArgs),
+    /// Take the migration lock, blocking migration runs until `unlock`
+    Lock(LockArgs),
+    /// Release the migration lock left by a crashed run or by `lock`
+    Unlock(UnlockArgs),
+    /// Give consecutive ids to the migrations, e.g. after merging branches
+    /// that both created a migration with the same id
+    Renumber(RenumberArgs),
+    /// Replace the first migrations with a baseline creating their schema,
+    /// archiving them
+    Squash(SquashArgs),
     // Migrate to specific version (automatically Up or Down)
     // Goto()
     // Drop()
@@ -46,103 +195,1467 @@ struct CreateArgs {
     /// Apply for N up migrations
     #[arg(required = true)]
     migration_name: String,
+    /// Require a lowercase snake_case ASCII name of bounded length
+    #[arg(long)]
+    strict_names: bool,
+    /// Create the migration with this exact id
+    #[arg(long, conflicts_with = "after")]
+    id: Option<u32>,
+    /// Create the migration with the first free id after this one
+    #[arg(long)]
+    after: Option<u32>,
+    /// Don't generate down.sql, marking the migration irreversible
+    #[arg(long)]
+    no_down: bool,
+    /// Open the generated up.sql in $VISUAL or $EDITOR
+    #[arg(long)]
+    edit: bool,
+    /// Open the generated down.sql in the editor too
+    #[arg(long, conflicts_with = "no_down")]
+    edit_down: bool,
+    /// SQL of the up migration
+    #[arg(long, value_name = "SQL")]
+    up_sql: Option<String>,
+    /// SQL of the down migration
+    #[arg(long, value_name = "SQL", conflicts_with = "no_down")]
+    down_sql: Option<String>,
+    /// Read the SQL of the up migration from stdin
+    #[arg(long, conflicts_with = "up_sql")]
+    from_stdin: bool,
+}
+
+#[derive(clap::Args, Debug, Clone)]
+#[command(author, version, about, long_about = None)]
+struct LintArgs {
+    /// Require lowercase snake_case ASCII names of bounded length
+    #[arg(long)]
+    strict_names: bool,
+    /// Apply, revert and reapply each migration in an in-memory database,
+    /// checking that down.sql restores the schema
+    #[arg(long)]
+    round_trip: bool,
 }
 
 #[derive(clap::Args, Debug, Clone)]
 #[command(author, version, about, long_about = None)]
 struct UpArgs {
     /// Apply for N up migrations
-    #[arg(short)]
+    #[arg(short, conflicts_with = "to")]
     n: Option<usize>,
+    /// Migrate to this schema version, not lower than the current one
+    #[arg(long, value_name = "VERSION")]
+    to: Option<usize>,
+    /// Migrate to the last migration of this release, tagged in its
+    /// migration.yaml
+    #[arg(long, value_name = "TAG", conflicts_with_all = ["n", "to"])]
+    to_tag: Option<String>,
+    /// Run PRAGMA integrity_check after migrating and fail on corruption
+    #[arg(long, conflicts_with = "quick_check")]
+    integrity_check: bool,
+    /// Run the faster PRAGMA quick_check after migrating and fail on corruption
+    #[arg(long)]
+    quick_check: bool,
+    /// Back up the database before applying migrations
+    #[arg(long)]
+    backup: bool,
+    /// Run VACUUM after a successful migration
+    #[arg(long)]
+    vacuum: bool,
+    /// Run ANALYZE after a successful migration
+    #[arg(long)]
+    analyze: bool,
+    /// Report statement and changed row counts of each migration
+    #[arg(long)]
+    stats: bool,
+    /// Skip the writability, free space and checksum checks run before migrating
+    #[arg(long)]
+    no_preflight: bool,
+    /// On failure, commit the migrations that succeeded before it instead of
+    /// rolling back all of them
+    #[arg(long)]
+    partial_commit: bool,
+    /// Environment of the database, `dev` and `test` apply the fixtures
+    #[arg(long, value_name = "ENV")]
+    env: Option<String>,
+    /// Apply the fixtures once the database is at the latest version
+    #[arg(long)]
+    with_fixtures: bool,
+    /// Refuse migrations not signed with the private key of this PEM encoded
+    /// Ed25519 public key
+    #[arg(long, value_name = "PUBKEY", value_hint = clap::ValueHint::FilePath)]
+    require_signature: Option<PathBuf>,
+    /// Signature checked by --require-signature, `.signature` in the
+    /// migration directory when not set
+    #[arg(long, requires = "require_signature", value_hint = clap::ValueHint::FilePath)]
+    signature: Option<PathBuf>,
+}
+
+#[derive(clap::Args, Debug, Clone)]
+#[command(author, version, about, long_about = None)]
+struct UpCommandArgs {
+    #[command(flatten)]
+    up: UpArgs,
+    /// Migrate every database matching the glob instead of --database
+    #[arg(long, value_name = "PATTERN", group = "databases")]
+    database_glob: Option<String>,
+    /// Migrate the database of every active tenant of the registry
+    #[arg(long, group = "databases")]
+    all_tenants: bool,
+    /// Keep migrating the other databases when one fails
+    #[arg(long, requires = "databases")]
+    continue_on_error: bool,
 }
 
 #[derive(clap::Args, Debug, Clone)]
 #[command(author, version, about, long_about = None)]
 struct DownArgs {
     /// Apply for N down migrations
-    #[arg(short)]
+    #[arg(short, conflicts_with = "to")]
     n: Option<usize>,
+    /// Roll back to this schema version, lower than the current one
+    #[arg(long, value_name = "VERSION")]
+    to: Option<usize>,
+    /// Roll back to the last migration of this release, tagged in its
+    /// migration.yaml
+    #[arg(long, value_name = "TAG", conflicts_with_all = ["n", "to"])]
+    to_tag: Option<String>,
+    /// Run PRAGMA integrity_check after migrating and fail on corruption
+    #[arg(long, conflicts_with = "quick_check")]
+    integrity_check: bool,
+    /// Run the faster PRAGMA quick_check after migrating and fail on corruption
+    #[arg(long)]
+    quick_check: bool,
+    /// Report statement and changed row counts of each migration
+    #[arg(long)]
+    stats: bool,
+    /// Skip the writability, free space and checksum checks run before migrating
+    #[arg(long)]
+    no_preflight: bool,
+    /// On failure, commit the migrations that succeeded before it instead of
+    /// rolling back all of them
+    #[arg(long)]
+    partial_commit: bool,
+    /// Check foreign keys when committing the rollback rather than after
+    /// each statement
+    #[arg(long)]
+    defer_foreign_keys: bool,
+}
+
+#[derive(clap::Args, Debug, Clone)]
+#[command(author, version, about, long_about = None)]
+struct DaemonArgs {
+    /// Cron expression of the runs, e.g. "0 3 * * *"
+    #[arg(long)]
+    schedule: String,
+    /// URL receiving a JSON report of each run, instead of the
+    /// `notify_webhook` of the config file
+    #[arg(long)]
+    webhook: Option<String>,
+    #[command(flatten)]
+    up: UpArgs,
+}
+
+#[derive(clap::Args, Debug, Clone)]
+#[command(author, version, about, long_about = None)]
+struct StatusArgs {
+    /// Stable tab-separated output for scripts
+    #[arg(long)]
+    porcelain: bool,
+    /// Only print a summary, failing if migrations are pending or drifted
+    #[arg(long, conflicts_with = "porcelain")]
+    check: bool,
+}
+
+#[derive(clap::Args, Debug, Clone)]
+#[command(author, version, about, long_about = None)]
+struct HistoryArgs {
+    #[command(subcommand)]
+    action: Option<HistoryCommands>,
+    /// Print the history as JSON
+    #[arg(long)]
+    json: bool,
+    /// List the failed migration attempts instead
+    #[arg(long)]
+    failures: bool,
+}
+
+#[derive(clap::Subcommand, Debug, Clone)]
+enum HistoryCommands {
+    /// Compare two histories, each a `history --json` export or a database
+    Diff {
+        #[arg(value_hint = clap::ValueHint::FilePath)]
+        left: PathBuf,
+        #[arg(value_hint = clap::ValueHint::FilePath)]
+        right: PathBuf,
+    },
+}
+
+#[derive(clap::Args, Debug, Clone)]
+#[command(author, version, about, long_about = None)]
+struct RepairArgs {
+    /// Don't ask for confirmation
+    #[arg(short, long)]
+    yes: bool,
+}
+
+#[derive(clap::Args, Debug, Clone)]
+#[command(author, version, about, long_about = None)]
+struct CompareArgs {
+    #[arg(value_hint = clap::ValueHint::FilePath)]
+    first: PathBuf,
+    #[arg(value_hint = clap::ValueHint::FilePath)]
+    second: PathBuf,
+    /// Tables whose rows are compared, in addition to the configured ones
+    #[arg(long, value_delimiter = ',')]
+    data_tables: Vec<String>,
+}
+
+#[derive(clap::Args, Debug, Clone)]
+#[command(author, version, about, long_about = None)]
+#[group(required = true, multiple = true)]
+struct VerifyDowngradeSafetyArgs {
+    /// SQL file of the queries run by the previous release
+    #[arg(long, value_hint = clap::ValueHint::FilePath)]
+    queries: Option<PathBuf>,
+    /// Schema dump of the previous release
+    #[arg(long, value_hint = clap::ValueHint::FilePath)]
+    schema: Option<PathBuf>,
+}
+
+#[derive(clap::Args, Debug, Clone)]
+#[command(author, version, about, long_about = None)]
+struct TenantArgs {
+    #[command(subcommand)]
+    action: TenantCommands,
+}
+
+#[derive(clap::Subcommand, Debug, Clone)]
+enum TenantCommands {
+    /// Register a tenant database, or reactivate a deactivated tenant
+    Add {
+        name: String,
+        #[arg(value_hint = clap::ValueHint::FilePath)]
+        database: PathBuf,
+    },
+    /// Deactivate a tenant so it is no longer migrated
+    Remove { name: String },
+    /// List the registered tenants
+    List,
+}
+
+#[derive(clap::Args, Debug, Clone)]
+#[command(author, version, about, long_about = None)]
+struct ConfigArgs {
+    #[command(subcommand)]
+    action: ConfigCommands,
+}
+
+#[derive(clap::Subcommand, Debug, Clone)]
+enum ConfigCommands {
+    /// Report unknown keys, wrong types and missing required keys, and print
+    /// the effective configuration after environment and command line
+    /// overrides
+    Check,
+}
+
+#[derive(clap::Args, Debug, Clone)]
+#[command(author, version, about, long_about = None)]
+struct PlanArgs {
+    /// Output format
+    #[arg(long, value_enum, default_value_t)]
+    format: command::PlanFormat,
+}
+
+#[derive(clap::Args, Debug, Clone)]
+#[command(author, version, about, long_about = None)]
+struct SnapshotArgs {
+    /// Fail if the schema doesn't match this snapshot instead of printing it
+    #[arg(long, value_name = "SNAPSHOT", value_hint = clap::ValueHint::FilePath)]
+    check: Option<PathBuf>,
+}
+
+#[derive(clap::Args, Debug, Clone)]
+#[command(author, version, about, long_about = None)]
+struct GraphArgs {
+    /// Output format
+    #[arg(long, value_enum, default_value_t)]
+    format: command::GraphFormat,
+}
+
+#[derive(clap::Args, Debug, Clone)]
+#[command(author, version, about, long_about = None)]
+struct ExportArgs {
+    /// Version the database is at, only the migrations after it are exported
+    #[arg(long, value_name = "VERSION", default_value_t = 0)]
+    from: usize,
+    /// Last version exported, the latest when not set
+    #[arg(long, value_name = "VERSION")]
+    to: Option<usize>,
+    /// Write the script to this file instead of stdout
+    #[arg(short, long, value_hint = clap::ValueHint::FilePath)]
+    output: Option<PathBuf>,
+}
+
+#[derive(clap::Args, Debug, Clone)]
+#[command(author, version, about, long_about = None)]
+struct InitArgs {
+    /// Write the schema of this database as the initial migration, and
+    /// record it as applied there
+    #[arg(long, value_name = "DATABASE", value_hint = clap::ValueHint::FilePath)]
+    from_db: Option<PathBuf>,
+    /// Create an empty initial migration
+    #[arg(long, conflicts_with = "from_db")]
+    initial: bool,
+}
+
+#[derive(clap::Args, Debug, Clone)]
+#[command(author, version, about, long_about = None)]
+struct LockArgs {
+    /// Why migrations are blocked, shown to the runs failing to take the lock
+    #[arg(long, default_value = "manual lock")]
+    reason: String,
+}
+
+#[derive(clap::Args, Debug, Clone)]
+#[command(author, version, about, long_about = None)]
+struct UnlockArgs {
+    /// Release the lock even though a migration may still be running
+    #[arg(long)]
+    force: bool,
+}
+
+#[derive(clap::Args, Debug, Clone)]
+#[command(author, version, about, long_about = None)]
+struct RenumberArgs {
+    /// First id to renumber, every migration from it gets consecutive ids
+    #[arg(long, value_name = "ID", default_value_t = 1)]
+    from: usize,
+    /// Migration folders, or ids, to put first and in this order, the other
+    /// ones following in id order
+    #[arg(long, value_name = "MIGRATION", value_delimiter = ',')]
+    order: Vec<String>,
+    /// Move the history entries of the renumbered migrations in the database
+    /// to their new ids
+    #[arg(long)]
+    update_history: bool,
+}
+
+#[derive(clap::Args, Debug, Clone)]
+#[command(author, version, about, long_about = None)]
+struct SquashArgs {
+    /// Last migration replaced by the baseline, which every database must
+    /// have reached
+    #[arg(long, value_name = "VERSION")]
+    to: usize,
+}
+
+#[derive(clap::Args, Debug, Clone)]
+#[command(author, version, about, long_about = None)]
+struct SignArgs {
+    /// PEM encoded Ed25519 private key
+    #[arg(long, value_hint = clap::ValueHint::FilePath)]
+    key: PathBuf,
+    /// Write the signature to this file instead of `.signature` in the
+    /// migration directory
+    #[arg(short, long, value_hint = clap::ValueHint::FilePath)]
+    output: Option<PathBuf>,
+}
+
+#[derive(clap::Args, Debug, Clone)]
+#[command(author, version, about, long_about = None)]
+struct GenerateArgs {
+    /// Kind of code to generate
+    #[arg(value_enum)]
+    target: command::GenerateTarget,
+    /// Write the code to this file instead of stdout
+    #[arg(short, long, value_hint = clap::ValueHint::FilePath)]
+    output: Option<PathBuf>,
+}
+
+#[derive(clap::Args, Debug, Clone)]
+#[command(author, version, about, long_about = None)]
+struct ImportArgs {
+    /// Naming scheme of the migrations to import
+    #[arg(long, value_enum, default_value_t)]
+    format: command::ImportFormat,
+    /// Directory of the migrations to import
+    #[arg(value_hint = clap::ValueHint::DirPath)]
+    dir: PathBuf,
 }
 
-#[derive(Clone, Debug, serde::Deserialize)]
+#[derive(clap::Args, Debug, Clone)]
+#[command(author, version, about, long_about = None)]
+struct ImportStateArgs {
+    /// JSON list of applied migrations, in the `history --json` format, or `-`
+    /// for stdin
+    #[arg(value_hint = clap::ValueHint::FilePath, required_unless_present = "sqlx")]
+    state: Option<PathBuf>,
+    /// Import the migrations recorded by sqlx in the `_sqlx_migrations` table
+    #[arg(long, conflicts_with = "state")]
+    sqlx: bool,
+}
+
+#[derive(Clone, Debug, Default, serde::Deserialize, serde::Serialize)]
 struct MigrateFileCfg {
-    source_path: PathBuf,
-    database_path: PathBuf,
+    #[serde(default)]
+    source_path: Option<PathBuf>,
+    #[serde(default)]
+    database_path: Option<PathBuf>,
+    #[serde(flatten)]
+    settings: Settings,
 }
 
-fn main() -> Result<()> {
-    tracing_subscriber::fmt::init();
+/// Optional config file keys, combined with the matching command line flags
+#[derive(Clone, Debug, Default, serde::Deserialize, serde::Serialize)]
+#[serde(default)]
+struct Settings {
+    open_flags: OpenFlagsArgs,
+    vacuum: bool,
+    analyze: bool,
+    strict_names: bool,
+    max_name_length: Option<usize>,
+    /// Regular expression migration names must match, e.g. `^[a-z0-9_]+$`
+    name_pattern: Option<String>,
+    /// Shell commands regenerating derived artifacts after a successful `up`
+    after_successful_up: Vec<String>,
+    /// URL a JSON report of every `up` and `down` run is posted to
+    notify_webhook: Option<String>,
+    /// Shell command run after every `up` and `down` run, with its report
+    /// in `MIGRATOR_*` environment variables
+    notify_command: Option<String>,
+    /// Back up the database before applying migrations
+    backup: bool,
+    /// Check foreign keys when committing down migrations
+    defer_foreign_keys: bool,
+    /// Directory of the backups, next to the database when not set
+    backup_dir: Option<PathBuf>,
+    /// Small reference tables whose rows `compare` diffs
+    compare_tables: Vec<String>,
+    /// Expand `${VAR}` placeholders in migration SQL
+    substitute_vars: bool,
+    /// Values of the placeholders, taking precedence over the environment
+    vars: HashMap<String, String>,
+    /// Databases attached before migrating, by schema name
+    attach: HashMap<String, PathBuf>,
+    /// Ask for confirmation before migrating the database
+    protected: bool,
+    /// Algorithm of the checksums stored in the history table
+    checksum_algorithm: checksum::Algorithm,
+    /// Where the schema version is stored, `table` for 64-bit versions
+    version_storage: version::Storage,
+    /// Require migration ids to be consecutive, true when not set
+    strict_sequence: Option<bool>,
+    /// Load Flyway's `V<id>__<name>.sql` and `R__<name>.sql` files instead
+    flyway_naming: bool,
+    /// Retries of the migration transaction while the database is locked
+    busy_retries: u32,
+    /// Kind of the migration transaction: deferred, immediate or exclusive
+    transaction_mode: TransactionMode,
+    /// Interrupt and roll back any migration running longer than this
+    timeout_secs: Option<u64>,
+    /// Tenant registry file used by `tenant` and `up --all-tenants`
+    tenant_registry: Option<PathBuf>,
+    /// Fixtures applied by `up --env dev|test`, `fixtures` next to the
+    /// migration directory when not set
+    fixtures_path: Option<PathBuf>,
+}
 
-    let args = MigrateCli::parse();
+impl Settings {
+    /// Maximum migration name length when strict names are enabled either in
+    /// the config file or with the command line flag
+    fn name_length_limit(&self, strict_names: bool) -> Option<usize> {
+        (strict_names || self.strict_names).then(|| {
+            self.max_name_length
+                .unwrap_or(loader::DEFAULT_MAX_NAME_LENGTH)
+        })
+    }
 
-    let current_dir = std::env::current_dir()?;
+    fn fixtures_path(&self, source: &Path) -> PathBuf {
+        self.fixtures_path.clone().unwrap_or_else(|| {
+            source
+                .parent()
+                .unwrap_or(Path::new(""))
+                .join(fixtures::DEFAULT_DIR)
+        })
+    }
 
-    // exit on error only in the case the file is found but couldn't be deserialiazed
-    let config: Result<MigrateFileCfg> = File::open(current_dir.join(".migrate-config.yaml"))
-        .map_err(|e| e.into())
-        .and_then(|v| serde_yaml::from_reader(v).map_err(Into::into));
-
-    let (source, db_path) = match (args.source.as_ref(), args.database.as_ref()) {
-        (None, None) => {
-            let config = config.context(
-                "'source_path' and 'database_path' not found in arguments or config file.",
-            )?;
-            (config.source_path, config.database_path)
+    fn tenant_registry(&self) -> PathBuf {
+        self.tenant_registry
+            .clone()
+            .unwrap_or_else(|| PathBuf::from(tenants::DEFAULT_REGISTRY))
+    }
+}
+
+// Parse the config file, expanding the `${VAR}` placeholders of its string
+// values from the environment. Relative source and database paths are
+// relative to the directory of the file.
+//
+// A `lenient` read, for `config check` which reports the problems itself,
+// keeps the valid keys of a config with invalid ones instead of failing.
+fn read_config(path: &Path, lenient: bool) -> Result<MigrateFileCfg> {
+    let mut value: serde_yaml::Value = serde_yaml::from_reader(File::open(path)?)?;
+    let expanded = command::expand_env(
+        &mut value,
+        &serde_yaml::to_value(MigrateFileCfg::default())?,
+        "",
+    );
+
+    let mut config: MigrateFileCfg = if lenient {
+        serde_yaml::from_value(command::valid_keys(&value, &parse_config).0)?
+    } else {
+        expanded?;
+        serde_yaml::from_value(value)?
+    };
+    let dir = path.parent().unwrap_or(Path::new(""));
+    for path in [&mut config.source_path, &mut config.database_path]
+        .into_iter()
+        .flatten()
+    {
+        if path.as_os_str() != SCRATCH_DATABASE {
+            *path = dir.join(&path);
+        }
+    }
+
+    Ok(config)
+}
+
+// Check the types of the keys of a config file
+fn parse_config(value: &serde_yaml::Value) -> Result<(), serde_yaml::Error> {
+    serde_yaml::from_value::<MigrateFileCfg>(value.clone()).map(|_| ())
+}
+
+// Path given on the command line, or else in the config file
+fn resolve_path(
+    arg: &Option<PathBuf>,
+    config: &Result<MigrateFileCfg>,
+    field: impl Fn(&MigrateFileCfg) -> &Option<PathBuf>,
+    name: &str,
+) -> Result<PathBuf> {
+    if let Some(path) = arg {
+        return Ok(path.clone());
+    }
+
+    let not_found = format!("'{name}' not found in arguments or config file.");
+    match config {
+        Ok(config) => field(config).clone().ok_or(anyhow!(not_found)),
+        Err(err) => Err(anyhow!("{err:#}").context(not_found)),
+    }
+}
+
+// Version of the migration tagged with the release `tag`
+fn tag_version(migrations: &Migrations, tag: &str) -> Result<usize> {
+    let tagged = migrations
+        .iter()
+        .filter(|(_, m)| m.get_tag() == Some(tag))
+        .map(|(v, _)| v)
+        .collect::<Vec<_>>();
+
+    match tagged[..] {
+        [version] => Ok(version),
+        [] => {
+            let known = migrations
+                .iter()
+                .filter_map(|(_, m)| m.get_tag())
+                .collect::<Vec<_>>();
+            anyhow::bail!(
+                "No migration is tagged {tag:?}, known tags: {}",
+                if known.is_empty() {
+                    "none".to_owned()
+                } else {
+                    known.join(", ")
+                }
+            )
         }
-        (None, Some(v)) => {
-            let config = config.context("'source_path' not found in arguments or config file.")?;
-            (config.source_path, v.clone())
+        _ => anyhow::bail!("Tag {tag:?} is on several migrations: {tagged:?}"),
+    }
+}
+
+/// Wait before the first retry of a locked database, doubled on each retry
+const BUSY_BACKOFF: Duration = Duration::from_millis(200);
+
+fn load_migrations(source: &Path, settings: &Settings) -> Result<Migrations> {
+    if !source.is_dir() {
+        anyhow::bail!(
+            "Migration directory {} does not exist. Did you mean to create a first migration with `migrator create <NAME>`, or to pass --ensure-dir?",
+            source.display()
+        );
+    }
+
+    let migrations = if settings.flyway_naming {
+        Migrations::from_flyway_directory(source)
+    } else if settings.strict_sequence.unwrap_or(true) {
+        Migrations::from_directory(source)
+    } else {
+        Migrations::from_directory_allowing_gaps(source)
+    }
+    .map_err(renumber_hint)?;
+    let migrations = migrations
+        .checksum_algorithm(settings.checksum_algorithm)
+        .version_storage(settings.version_storage)
+        .busy_retries(settings.busy_retries, BUSY_BACKOFF)
+        .transaction_mode(settings.transaction_mode)
+        .interrupt_flag(command::interrupt_flag());
+    let migrations = match settings.timeout_secs {
+        Some(secs) => migrations.timeout(Duration::from_secs(secs)),
+        None => migrations,
+    };
+
+    if settings.substitute_vars {
+        Ok(migrations.expand_vars(&settings.vars)?)
+    } else {
+        Ok(migrations)
+    }
+}
+
+// Suggest `renumber` when branches merged migrations with the same id
+fn renumber_hint(error: sqlite_migrator::error::Error) -> anyhow::Error {
+    use sqlite_migrator::error::Error;
+
+    let first_duplicate = match &error {
+        Error::InvalidDirectory(problems) => problems.iter().find_map(|p| match p.error {
+            Error::DuplicateId { id, .. } => Some(id),
+            _ => None,
+        }),
+        _ => None,
+    };
+    match first_duplicate {
+        Some(id) => anyhow!(
+            "{error}\nRun `migrator renumber --from {id}` to give the duplicated migrations and the ones after them consecutive ids"
+        ),
+        None => error.into(),
+    }
+}
+
+/// Config file read from the working directory unless `--config` is given
+const CONFIG_FILE_NAME: &str = ".migrate-config.yaml";
+
+/// Database path of throwaway in-memory databases
+const SCRATCH_DATABASE: &str = ":memory:";
+
+fn is_scratch(db_path: &Path) -> bool {
+    db_path == Path::new(SCRATCH_DATABASE)
+}
+
+fn open_connection(db_path: &Path, open_flags: OpenFlagsArgs) -> Result<Connection> {
+    let conn = Connection::open_with_flags(db_path, open_flags.to_flags())
+        .context(format!("Could not open database {}", db_path.display()))?;
+    if let Some(ms) = open_flags.busy_timeout {
+        conn.busy_timeout(Duration::from_millis(ms))?;
+    }
+
+    // in-memory databases are never on a network filesystem, whatever the
+    // current directory is
+    let network_fs = (!is_scratch(db_path))
+        .then(|| netfs::network_filesystem(db_path))
+        .flatten();
+    if let Some(fs_type) = network_fs {
+        if !open_flags.read_only && !open_flags.unsafe_network_fs {
+            anyhow::bail!(
+                "Database {} is on a network filesystem ({fs_type}) where SQLite locking is unreliable. Pass --unsafe-network-fs to migrate it anyway.",
+                db_path.display()
+            );
         }
-        (Some(v), None) => {
-            let config =
-                config.context("'database_path' not found in arguments or config file.")?;
-            (v.clone(), config.database_path)
+        // WAL needs shared memory, which network filesystems don't provide,
+        // and an exclusive lock keeps other clients out for the whole run
+        tracing::warn!(
+            "Database {} is on a network filesystem ({fs_type}), using exclusive locking without WAL",
+            db_path.display()
+        );
+        conn.pragma_update(None, "locking_mode", "EXCLUSIVE")?;
+        if !open_flags.read_only {
+            conn.pragma_update(None, "journal_mode", "DELETE")?;
         }
-        (Some(s), Some(d)) => (s.clone(), d.clone()),
+    } else if !open_flags.read_only {
+        conn.pragma_update(None, "journal_mode", "WAL")?;
+    }
+    conn.pragma_update(None, "foreign_keys", "ON")?;
+
+    Ok(conn)
+}
+
+// Attach the configured databases, outside of the migration transaction
+// where ATTACH isn't allowed
+fn attach_databases(conn: &Connection, attach: &HashMap<String, PathBuf>) -> Result<()> {
+    for (name, path) in attach {
+        conn.execute(
+            &format!("ATTACH DATABASE ?1 AS \"{}\"", name.replace('"', "\"\"")),
+            [path.to_string_lossy()],
+        )
+        .context(format!("Could not attach {} as {name}", path.display()))?;
+    }
+
+    Ok(())
+}
+
+// Post-migration maintenance, run outside of any transaction
+fn run_maintenance(conn: &Connection, vacuum: bool, analyze: bool) -> Result<()> {
+    if vacuum {
+        tracing::info!("Running VACUUM");
+        conn.execute_batch("VACUUM").context("query: VACUUM")?;
+    }
+    if analyze {
+        tracing::info!("Running ANALYZE");
+        conn.execute_batch("ANALYZE").context("query: ANALYZE")?;
+    }
+
+    Ok(())
+}
+
+// Run the configured commands after a successful `up`, exposing the database
+// path and the version it was migrated to through environment variables
+fn run_after_up_commands(db_path: &Path, version: usize, commands: &[String]) -> Result<()> {
+    if commands.is_empty() {
+        return Ok(());
+    }
+
+    let envs = [
+        ("MIGRATOR_DATABASE_PATH", db_path.display().to_string()),
+        ("MIGRATOR_VERSION", version.to_string()),
+    ];
+    for command in commands {
+        shell::run(command, &envs).context("after_successful_up command")?;
+    }
+
+    Ok(())
+}
+
+// Fail if SQLite reports any problem with the database file
+fn check_integrity(conn: &Connection, integrity_check: bool, quick_check: bool) -> Result<()> {
+    let pragma = match (integrity_check, quick_check) {
+        (true, _) => "integrity_check",
+        (false, true) => "quick_check",
+        (false, false) => return Ok(()),
+    };
+
+    tracing::info!("Running PRAGMA {pragma}");
+    let mut stmt = conn.prepare(&format!("PRAGMA {pragma}"))?;
+    let problems = stmt
+        .query_map([], |row| row.get::<_, String>(0))?
+        .collect::<Result<Vec<_>, _>>()
+        .context(format!("query: PRAGMA {pragma}"))?;
+
+    if problems != ["ok"] {
+        anyhow::bail!(
+            "PRAGMA {pragma} reported problems:\n{}",
+            problems.join("\n")
+        );
+    }
+
+    Ok(())
+}
+
+// Migrate up while holding the migration lock, then run the post-migration
+// checks, maintenance and commands
+fn up(
+    source: &Path,
+    db_path: &Path,
+    open_flags: OpenFlagsArgs,
+    settings: &Settings,
+    args: &UpArgs,
+    assumed: &[command::PromptCategory],
+) -> Result<Vec<MigrationRun>> {
+    let start = Instant::now();
+    let mut versions = (None, None);
+    let result = apply_up(
+        source,
+        db_path,
+        open_flags,
+        settings,
+        args,
+        assumed,
+        &mut versions,
+    );
+    notify(settings, "up", db_path, versions, start.elapsed(), &result);
+
+    result
+}
+
+// Report a run to the `notify_webhook` and `notify_command` of the config
+fn notify(
+    settings: &Settings,
+    command: &'static str,
+    db_path: &Path,
+    (from_version, to_version): (Option<usize>, Option<usize>),
+    duration: Duration,
+    result: &Result<Vec<MigrationRun>>,
+) {
+    if settings.notify_webhook.is_none() && settings.notify_command.is_none() {
+        return;
+    }
+
+    command::notify_run(
+        settings.notify_webhook.as_deref(),
+        settings.notify_command.as_deref(),
+        &command::RunReport {
+            command,
+            database: db_path,
+            from_version,
+            to_version,
+            duration,
+            result,
+        },
+    );
+}
+
+// Apply the migrations, setting the versions of the database before and
+// after the run as they are known
+fn apply_up(
+    source: &Path,
+    db_path: &Path,
+    open_flags: OpenFlagsArgs,
+    settings: &Settings,
+    args: &UpArgs,
+    assumed: &[command::PromptCategory],
+    versions: &mut (Option<usize>, Option<usize>),
+) -> Result<Vec<MigrationRun>> {
+    let migrations = load_migrations(source, settings)?.partial_commit(args.partial_commit);
+
+    if let Some(key) = &args.require_signature {
+        command::verify_signature(source, key, args.signature.as_deref())?;
+    }
+
+    let to = match &args.to_tag {
+        Some(tag) => Some(tag_version(&migrations, tag)?),
+        None => args.to,
+    };
+
+    let mut conn = open_connection(db_path, open_flags)?;
+    attach_databases(&conn, &settings.attach)?;
+    if !args.no_preflight {
+        preflight::check(&migrations, &conn)?;
+    }
+
+    let backup = args.backup || settings.backup;
+    let handle = conn.get_interrupt_handle();
+    let runs = command::exit_if_interrupted(command::interruptible(handle, || {
+        lock::with_lock(&mut conn, "up", migrations.get_busy_retry(), |conn| {
+            let cur_version: usize = migrations.current_version(conn)?.into();
+            versions.0 = Some(cur_version);
+            let target_version = match (args.n, to) {
+                (Some(n), _) => migrations
+                    .version_after(cur_version, n)
+                    .ok_or(anyhow!("The number of steps up is too large."))?,
+                (_, Some(to)) if to < cur_version => anyhow::bail!(
+                    "Version {to} is behind the current version {cur_version}, use `down --to` to roll back."
+                ),
+                (_, Some(to)) => to,
+                (None, None) => migrations.max_version().into(),
+            };
+            let pending = migrations
+                .iter()
+                .filter(|(v, _)| (cur_version + 1..=target_version).contains(v))
+                .collect::<Vec<_>>();
+
+            command::confirm_migration(
+                &migrations,
+                cur_version,
+                target_version.min(migrations.max_version().into()),
+                db_path,
+                settings.protected,
+                assumed,
+            )?;
+
+            if !backup {
+                if let Some((version, m)) = pending.iter().find(|(_, m)| m.get_requires_backup()) {
+                    anyhow::bail!(
+                        "migration {:04} {} requires a backup: enable backups with --backup or the `backup` config key",
+                        version,
+                        m.get_comment().unwrap_or_default()
+                    );
+                }
+            } else if !pending.is_empty() {
+                command::backup(conn, db_path, settings.backup_dir.as_deref())?;
+            }
+
+            let runs = if args.n.is_some() || to.is_some() {
+                migrations.to_version(conn, target_version)?
+            } else {
+                migrations.to_latest(conn)?
+            };
+            versions.1 = Some(migrations.current_version(conn)?.into());
+
+            let with_fixtures =
+                args.with_fixtures || matches!(args.env.as_deref(), Some("dev" | "test"));
+            if with_fixtures && migrations.current_version(conn)? == migrations.max_version() {
+                let dir = settings.fixtures_path(source);
+                if dir.is_dir() {
+                    fixtures::apply(conn, &fixtures::load(&dir)?)?;
+                } else {
+                    tracing::warn!("No fixtures to apply, {} does not exist", dir.display());
+                }
+            }
+
+            Ok(runs)
+        })
+    }))?;
+
+    check_integrity(&conn, args.integrity_check, args.quick_check)?;
+    run_maintenance(
+        &conn,
+        args.vacuum || settings.vacuum,
+        args.analyze || settings.analyze,
+    )?;
+    run_after_up_commands(
+        db_path,
+        migrations.current_version(&conn)?.into(),
+        &settings.after_successful_up,
+    )?;
+
+    // the schema is all that is left of a scratch database
+    if is_scratch(db_path) {
+        command::print_schema(&conn)?;
+    }
+
+    Ok(runs)
+}
+
+// Revert the migrations, setting the versions of the database before and
+// after the run as they are known. The summary is printed before the
+// integrity check, so that it isn't lost when the check fails.
+fn down(
+    source: &Path,
+    db_path: &Path,
+    open_flags: OpenFlagsArgs,
+    settings: &Settings,
+    args: &DownArgs,
+    assumed: &[command::PromptCategory],
+    versions: &mut (Option<usize>, Option<usize>),
+) -> Result<Vec<MigrationRun>> {
+    let migrations = load_migrations(source, settings)?
+        .partial_commit(args.partial_commit)
+        .defer_foreign_keys(args.defer_foreign_keys || settings.defer_foreign_keys);
+    let to = match &args.to_tag {
+        Some(tag) => Some(tag_version(&migrations, tag)?),
+        None => args.to,
+    };
+
+    let mut conn = open_connection(db_path, open_flags)?;
+    attach_databases(&conn, &settings.attach)?;
+    if !args.no_preflight {
+        preflight::check(&migrations, &conn)?;
+    }
+
+    let handle = conn.get_interrupt_handle();
+    let runs = command::exit_if_interrupted(command::interruptible(handle, || {
+        lock::with_lock(&mut conn, "down", migrations.get_busy_retry(), |conn| {
+            let cur_version: usize = migrations.current_version(conn)?.into();
+            versions.0 = Some(cur_version);
+            let end_version = if let Some(steps_down) = args.n {
+                migrations
+                    .version_before(cur_version, steps_down)
+                    .ok_or(anyhow!("The number of steps down is too large."))?
+            } else if let Some(end_version) = to {
+                if end_version >= cur_version {
+                    anyhow::bail!(
+                        "Version {end_version} is not lower than the current version {cur_version}."
+                    );
+                }
+                end_version
+            } else {
+                0
+            };
+
+            command::confirm_migration(
+                &migrations,
+                cur_version,
+                end_version,
+                db_path,
+                settings.protected,
+                assumed,
+            )?;
+            let runs = migrations.to_version(conn, end_version)?;
+            versions.1 = Some(migrations.current_version(conn)?.into());
+
+            Ok(runs)
+        })
+    }))?;
+    command::print_summary(&runs, args.stats);
+
+    check_integrity(&conn, args.integrity_check, args.quick_check)?;
+
+    Ok(runs)
+}
+
+fn main() -> Result<()> {
+    let args = MigrateCli::parse();
+
+    // logs go to stderr so that command output on stdout stays parseable
+    let logs = tracing_subscriber::fmt().with_writer(std::io::stderr);
+    match args.log_format {
+        LogFormat::Text => logs.init(),
+        LogFormat::Json => logs.json().init(),
+    }
+
+    command::install_interrupt_handler()?;
+
+    let config_path = args
+        .config
+        .clone()
+        .unwrap_or_else(|| PathBuf::from(CONFIG_FILE_NAME));
+    // `init` writes it and `config check` reports it missing
+    let writes_config = matches!(args.command, Commands::Init(_) | Commands::Config(_));
+    if args.config.is_some() && !writes_config && !config_path.exists() {
+        anyhow::bail!("Config file {} does not exist", config_path.display());
+    }
+
+    // exit on error only in the case the file is found but couldn't be deserialiazed
+    let config = read_config(&config_path, matches!(args.command, Commands::Config(_)));
+
+    let mut settings = config
+        .as_ref()
+        .map(|c| c.settings.clone())
+        .unwrap_or_default();
+    if let Some(retries) = args.busy_retries {
+        settings.busy_retries = retries;
+    }
+    let open_flags = settings.open_flags.merge(args.open_flags);
+
+    // only resolved by the commands needing them
+    let source = resolve_path(&args.source, &config, |c| &c.source_path, "source_path");
+    let db_path = if args.scratch {
+        Ok(PathBuf::from(SCRATCH_DATABASE))
+    } else {
+        resolve_path(
+            &args.database,
+            &config,
+            |c| &c.database_path,
+            "database_path",
+        )
     };
 
+    if args.ensure_dir {
+        if let Ok(source) = &source {
+            std::fs::create_dir_all(source).context(format!(
+                "Failed to create migration directory {}",
+                source.display()
+            ))?;
+        }
+    }
+
     match args.command {
         Commands::Create(ref v) => {
-            if let Err(err) = command::create(&source, &v.migration_name) {
+            let options = command::CreateOptions {
+                max_name_length: settings.name_length_limit(v.strict_names),
+                name_pattern: settings.name_pattern.clone(),
+                id: v.id,
+                after: v.after,
+                no_down: v.no_down,
+                edit: v.edit,
+                edit_down: v.edit_down,
+                up_sql: if v.from_stdin {
+                    Some(
+                        std::io::read_to_string(std::io::stdin())
+                            .context("Could not read SQL from stdin")?,
+                    )
+                } else {
+                    v.up_sql.clone()
+                },
+                down_sql: v.down_sql.clone(),
+            };
+            if let Err(err) = command::create(&source?, &v.migration_name, &options) {
                 tracing::error!("{}", err.to_string());
                 anyhow::bail!(err);
             }
         }
-        Commands::Up(UpArgs { n }) => {
-            let migrations = Migrations::from_directory(&source)?;
+        Commands::Init(InitArgs {
+            ref from_db,
+            initial,
+        }) => {
+            if let Some(from_db) = from_db.as_ref().filter(|db| !db.exists()) {
+                anyhow::bail!("Database {} does not exist", from_db.display());
+            }
+            // next to the config file by default
+            let config_dir = match config_path.parent() {
+                Some(dir) if args.config.is_some() => {
+                    let dir = Some(dir).filter(|d| !d.as_os_str().is_empty());
+                    std::fs::canonicalize(dir.unwrap_or(Path::new(".")))
+                        .context("Could not find the directory of the config file")?
+                }
+                _ => PathBuf::new(),
+            };
+            let mut source = source.unwrap_or_else(|_| config_dir.join(command::DEFAULT_SOURCE));
+            let mut database = db_path
+                .ok()
+                .or_else(|| from_db.clone())
+                .unwrap_or_else(|| config_dir.join(command::DEFAULT_DATABASE));
+            // written absolute to a config file outside of the working
+            // directory, as they are relative to the file when read
+            if args.config.is_some() {
+                source = std::path::absolute(source)?;
+                database = std::path::absolute(database)?;
+            }
+            if from_db.is_some() {
+                command::ensure_empty(&source)?;
+            }
+            command::scaffold(&config_path, &source, &database, initial)?;
+            let Some(from_db) = from_db else {
+                return Ok(());
+            };
+
+            let mut conn = open_connection(from_db, open_flags)?;
+            let initial = command::init_from_db(&source, &conn)?;
+
+            let baselined = load_migrations(&source, &settings).and_then(|migrations| {
+                lock::with_lock(&mut conn, "init", migrations.get_busy_retry(), |conn| {
+                    command::baseline(&migrations, conn)
+                })
+            });
+            // leave the migration directory empty for another try
+            if baselined.is_err() {
+                if let Err(err) = std::fs::remove_dir_all(&initial) {
+                    tracing::warn!("Could not remove {}: {}", initial.display(), err);
+                }
+            }
+            baselined?;
+        }
+        Commands::Up(UpCommandArgs {
+            up: ref up_args,
+            database_glob: Some(ref pattern),
+            continue_on_error,
+            ..
+        }) => {
+            let source = source?;
+            command::up_glob(pattern, continue_on_error, up_args.stats, |db_path| {
+                up(
+                    &source,
+                    db_path,
+                    open_flags,
+                    &settings,
+                    up_args,
+                    &args.assume_yes_for,
+                )
+            })?;
+        }
+        Commands::Up(UpCommandArgs {
+            up: ref up_args,
+            all_tenants: true,
+            continue_on_error,
+            ..
+        }) => {
+            let source = source?;
+            command::up_tenants(
+                &settings.tenant_registry(),
+                continue_on_error,
+                up_args.stats,
+                |db_path| {
+                    up(
+                        &source,
+                        db_path,
+                        open_flags,
+                        &settings,
+                        up_args,
+                        &args.assume_yes_for,
+                    )
+                },
+            )?;
+        }
+        Commands::Up(UpCommandArgs {
+            up: ref up_args, ..
+        }) => {
+            let runs = up(
+                &source?,
+                &db_path?,
+                open_flags,
+                &settings,
+                up_args,
+                &args.assume_yes_for,
+            )?;
+            command::print_summary(&runs, up_args.stats);
+        }
+        Commands::Daemon(DaemonArgs {
+            ref schedule,
+            ref webhook,
+            up: ref up_args,
+        }) => {
+            let (source, db_path) = (source?, db_path?);
+            // reported by `up` like the `notify_webhook` of the config
+            let settings = Settings {
+                notify_webhook: webhook.clone().or(settings.notify_webhook),
+                ..settings
+            };
+            command::daemon(schedule, || {
+                up(
+                    &source,
+                    &db_path,
+                    open_flags,
+                    &settings,
+                    up_args,
+                    &args.assume_yes_for,
+                )
+            })?;
+        }
+        Commands::Down(ref down_args) => {
+            let db_path = db_path?;
+            let start = Instant::now();
+            let mut versions = (None, None);
+            let result = down(
+                &source?,
+                &db_path,
+                open_flags,
+                &settings,
+                down_args,
+                &args.assume_yes_for,
+                &mut versions,
+            );
+            notify(
+                &settings,
+                "down",
+                &db_path,
+                versions,
+                start.elapsed(),
+                &result,
+            );
+            result?;
+        }
+        Commands::Status(StatusArgs { porcelain, check }) => {
+            let migrations = load_migrations(&source?, &settings)?;
 
-            let mut conn = Connection::open(&db_path)?;
+            let conn = open_connection(&db_path?, open_flags)?;
 
-            conn.pragma_update(None, "journal_mode", "WAL")?;
-            conn.pragma_update(None, "foreign_keys", "ON")?;
+            if check {
+                command::status_check(&migrations, &conn)?;
+            } else {
+                command::status(&migrations, &conn, porcelain)?;
+            }
+        }
+        Commands::Lint(LintArgs {
+            strict_names,
+            round_trip,
+        }) => {
+            let migrations = load_migrations(&source?, &settings)?;
+            command::lint(
+                &migrations,
+                settings.name_length_limit(strict_names),
+                settings.name_pattern.as_deref(),
+                round_trip,
+            )?;
+        }
+        Commands::History(HistoryArgs {
+            action: Some(HistoryCommands::Diff { left, right }),
+            ..
+        }) => {
+            command::history_diff(&left, &right)?;
+        }
+        Commands::History(HistoryArgs {
+            action: None,
+            json,
+            failures,
+        }) => {
+            let conn = open_connection(&db_path?, open_flags)?;
 
-            if let Some(version) = n {
-                let cur_version: usize = migrations.current_version(&conn)?.into();
-                migrations.to_version(&mut conn, cur_version + version)?;
+            if failures {
+                command::history_failures(&conn, json)?;
             } else {
-                migrations.to_latest(&mut conn)?;
+                command::history(&conn, json)?;
             }
         }
-        Commands::Down(DownArgs { n }) => {
-            let migrations = Migrations::from_directory(&source)?;
+        Commands::Test => {
+            let migrations = load_migrations(&source?, &settings)?;
 
-            let mut conn = Connection::open(db_path)?;
+            command::test(&migrations)?;
+        }
+        Commands::Verify => {
+            let migrations = load_migrations(&source?, &settings)?;
 
-            conn.pragma_update(None, "journal_mode", "WAL")?;
-            conn.pragma_update(None, "foreign_keys", "ON")?;
+            let conn = open_connection(&db_path?, open_flags)?;
 
-            if let Some(steps_down) = n {
-                let cur_version: usize = migrations.current_version(&conn)?.into();
-                let end_version = cur_version
-                    .checked_sub(steps_down)
-                    .ok_or(anyhow!("The number of steps down is too large."))?;
-                migrations.to_version(&mut conn, end_version)?;
-            } else {
-                migrations.to_version(&mut conn, 0)?;
+            command::verify(&migrations, &conn)?;
+        }
+        Commands::Repair(RepairArgs { yes }) => {
+            let migrations = load_migrations(&source?, &settings)?;
+
+            let mut conn = open_connection(&db_path?, open_flags)?;
+
+            command::repair(&migrations, &mut conn, yes)?;
+        }
+        Commands::Compare(CompareArgs {
+            first,
+            second,
+            mut data_tables,
+        }) => {
+            for table in &settings.compare_tables {
+                if !data_tables.contains(table) {
+                    data_tables.push(table.clone());
+                }
+            }
+            command::compare(&first, &second, &data_tables)?;
+        }
+        Commands::Plan(PlanArgs { format }) => {
+            let migrations = load_migrations(&source?, &settings)?;
+
+            let conn = open_connection(&db_path?, open_flags)?;
+
+            command::plan(&migrations, &conn, format)?;
+        }
+        Commands::Pending => {
+            let migrations = load_migrations(&source?, &settings)?;
+
+            let read_only = OpenFlagsArgs {
+                read_only: true,
+                ..open_flags
+            };
+            let conn = open_connection(&db_path?, read_only)?;
+
+            command::pending(&migrations, &conn)?;
+        }
+        Commands::ImportState(ImportStateArgs { ref state, sqlx }) => {
+            let migrations = load_migrations(&source?, &settings)?;
+
+            let mut conn = open_connection(&db_path?, open_flags)?;
+
+            lock::with_lock(
+                &mut conn,
+                "import-state",
+                migrations.get_busy_retry(),
+                |conn| match state {
+                    Some(state) if !sqlx => command::import_state(&migrations, conn, state),
+                    _ => command::import_sqlx_state(&migrations, conn),
+                },
+            )?;
+        }
+        Commands::VerifyDowngradeSafety(VerifyDowngradeSafetyArgs {
+            ref queries,
+            ref schema,
+        }) => {
+            let migrations = load_migrations(&source?, &settings)?;
+
+            let conn = open_connection(&db_path?, open_flags)?;
+
+            command::verify_downgrade_safety(
+                &migrations,
+                &conn,
+                queries.as_deref(),
+                schema.as_deref(),
+            )?;
+        }
+        Commands::Tenant(TenantArgs { ref action }) => {
+            let registry = settings.tenant_registry();
+            match action {
+                TenantCommands::Add { name, database } => {
+                    command::tenant_add(&registry, name, database)?
+                }
+                TenantCommands::Remove { name } => command::tenant_remove(&registry, name)?,
+                TenantCommands::List => command::tenant_list(&registry)?,
             }
         }
+        Commands::Version => {
+            let migrations = load_migrations(&source?, &settings)?;
+
+            let read_only = OpenFlagsArgs {
+                read_only: true,
+                ..open_flags
+            };
+            let conn = open_connection(&db_path?, read_only)?;
+
+            command::version(&migrations, &conn)?;
+        }
+        Commands::Config(ConfigArgs {
+            action: ConfigCommands::Check,
+        }) => {
+            let effective = MigrateFileCfg {
+                source_path: source.as_ref().ok().cloned(),
+                database_path: db_path.as_ref().ok().cloned(),
+                settings: Settings {
+                    open_flags,
+                    ..settings
+                },
+            };
+            let missing = [("source_path", &source), ("database_path", &db_path)]
+                .into_iter()
+                .filter(|(_, path)| path.is_err())
+                .map(|(key, _)| key)
+                .collect::<Vec<_>>();
+            command::config_check(
+                &config_path,
+                &command::ConfigSchema {
+                    known: &serde_yaml::to_value(MigrateFileCfg::default())?,
+                    parse: &parse_config,
+                    missing: &missing,
+                    effective: &serde_yaml::to_value(effective)?,
+                },
+            )?;
+        }
+        Commands::Doctor => {
+            let read_only = OpenFlagsArgs {
+                read_only: true,
+                ..open_flags
+            };
+            command::doctor(
+                &config_path,
+                config.as_ref().map(|_| ()),
+                &source,
+                &db_path,
+                |source| load_migrations(source, &settings),
+                |db_path| open_connection(db_path, read_only),
+            )?;
+        }
+        Commands::Snapshot(SnapshotArgs { ref check }) => {
+            let migrations = load_migrations(&source?, &settings)?;
+
+            command::snapshot(&migrations, check.as_deref())?;
+        }
+        Commands::Graph(GraphArgs { format }) => {
+            let migrations = load_migrations(&source?, &settings)?;
+
+            command::graph(&migrations, format)?;
+        }
+        Commands::Export(ExportArgs {
+            from,
+            to,
+            ref output,
+        }) => {
+            let migrations = load_migrations(&source?, &settings)?;
+
+            command::export(&migrations, from, to, output.as_deref())?;
+        }
+        Commands::Lock(LockArgs { ref reason }) => {
+            let conn = open_connection(&db_path?, open_flags)?;
+
+            command::lock(&conn, reason)?;
+        }
+        Commands::Unlock(UnlockArgs { force }) => {
+            let conn = open_connection(&db_path?, open_flags)?;
+
+            command::unlock(&conn, force)?;
+        }
+        Commands::Sign(SignArgs {
+            ref key,
+            ref output,
+        }) => {
+            let source = source?;
+            let migrations = load_migrations(&source, &settings)?;
+
+            command::sign(&migrations, &source, key, output.as_deref())?;
+        }
+        Commands::Generate(GenerateArgs { target, ref output }) => {
+            let source = source?;
+            let migrations = load_migrations(&source, &settings)?;
+
+            command::generate(&migrations, &source, target, output.as_deref())?;
+        }
+        Commands::Import(ImportArgs { format, ref dir }) => {
+            command::import(&source?, dir, format)?;
+        }
+        Commands::Renumber(RenumberArgs {
+            from,
+            ref order,
+            update_history,
+        }) => {
+            let source = source?;
+            // before renaming anything, so that a missing database fails early
+            let mut conn = if update_history {
+                Some(open_connection(&db_path?, open_flags)?)
+            } else {
+                None
+            };
+            command::renumber(&source, from, order, conn.as_mut())?;
+        }
+        Commands::Squash(SquashArgs { to }) => {
+            let source = source?;
+            let migrations = load_migrations(&source, &settings)?;
+            command::squash(&source, &migrations, to)?;
+        }
     }
 
-    println!("{:#?}", args);
     Ok(())
 }
@@ -0,0 +1,41 @@
+//! Per-migration metadata read from an optional `migration.yaml` file placed
+//! next to `up.sql`.
+use std::{fs::File, path::Path};
+
+use anyhow::{Context, Result};
+
+pub const META_FILE_NAME: &str = "migration.yaml";
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, serde::Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct MigrationMeta {
+    /// Shell commands run before the migration, outside the transaction
+    pub before: Vec<String>,
+    /// Shell commands run after the migration, outside the transaction
+    pub after: Vec<String>,
+    /// Refuse to apply the migration unless a backup is taken first
+    pub requires_backup: bool,
+    /// The migration can't be reverted, `down` fails instead of running it
+    pub irreversible: bool,
+    /// Interrupt and roll back the migration once it runs this long
+    pub timeout_secs: Option<u64>,
+    /// Query returning a single value; when it is false (0 or NULL) the
+    /// migration is recorded as skipped instead of being run
+    pub only_if: Option<String>,
+    /// Check foreign keys when committing the transaction reverting the
+    /// migration, rather than after each statement
+    pub defer_foreign_keys: bool,
+    /// Release shipping the migration as its last one, targeted by
+    /// `--to-tag`
+    pub tag: Option<String>,
+    /// The migration creates the schema of the archived migrations it
+    /// replaces, written by `squash`
+    pub baseline: bool,
+}
+
+impl MigrationMeta {
+    pub fn from_file(path: &Path) -> Result<Self> {
+        let file = File::open(path).context(anyhow::format_err!("Could not open {:?}", path))?;
+        serde_yaml::from_reader(file).context(anyhow::format_err!("Could not parse {:?}", path))
+    }
+}
@@ -1,30 +1,63 @@
 use std::{
+    borrow::Cow,
     cmp::{self, Ordering},
+    collections::HashMap,
     fmt,
-    fs::DirEntry,
     num::NonZeroUsize,
-    path::Path,
+    ops::Range,
     ptr::addr_of,
+    sync::{
+        atomic::{self, AtomicBool},
+        Arc,
+    },
+    time::{Duration, Instant},
 };
 
-use anyhow::{anyhow, Context, Result};
+use anyhow::Context;
 
-use rusqlite::{Connection, OptionalExtension, Transaction};
+use rusqlite::{Connection, OptionalExtension, Transaction, TransactionBehavior};
 use tracing::{debug, info, trace, warn};
 
-use crate::loader::{from_directory, MigrationFile};
+use crate::{
+    busy, checksum, compare,
+    data::TableData,
+    downgrade::schema_copy,
+    error::{Error, Result},
+    failures,
+    history::{self, HistoryEntry},
+    repeatable::{self, Repeatable},
+    schema, shell, statements,
+    timeout::Watchdog,
+    vars, version,
+};
+#[cfg(feature = "loader")]
+use std::path::Path;
+
+#[cfg(feature = "loader")]
+use crate::loader::{
+    from_directory, from_directory_allowing_gaps, from_flyway_directory, MigrationFile,
+};
+
+pub type HookResult = anyhow::Result<()>;
 
-pub type HookResult = Result<()>;
+/// Migration a hook is run for, so that one hook can serve several migrations
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct HookContext<'a> {
+    pub version: NonZeroUsize,
+    /// Comment of the migration, its folder name when loaded from a directory
+    pub name: Option<&'a str>,
+    pub direction: Direction,
+}
 
 /// Helper trait to make hook functions clonable.
-pub trait MigrationHook: Fn(&Transaction) -> HookResult + Send + Sync {
+pub trait MigrationHook: Fn(&Transaction, &HookContext) -> HookResult + Send + Sync {
     /// Clone self.
     fn clone_box(&self) -> Box<dyn MigrationHook>;
 }
 
 impl<T> MigrationHook for T
 where
-    T: 'static + Clone + Send + Sync + Fn(&Transaction) -> HookResult,
+    T: 'static + Clone + Send + Sync + Fn(&Transaction, &HookContext) -> HookResult,
 {
     fn clone_box(&self) -> Box<dyn MigrationHook> {
         Box::new(self.clone())
@@ -43,44 +76,391 @@ impl Clone for Box<dyn MigrationHook> {
     }
 }
 
+/// A migration, built from its up SQL with [`M::up`] or from a Rust hook
+/// with [`M::rust`].
+///
+/// # Transactions
+///
+/// A migration with before or after shell commands or SQL runs in a
+/// transaction of its own, so that they run right before and after it and
+/// for it only, e.g. a `PRAGMA foreign_keys = OFF` before SQL. They don't run
+/// when the [`M::only_if`] guard skips the migration.
 #[derive(Debug, Clone)]
 pub struct M {
+    /// Id of the migration folder, for migrations loaded from a directory
+    version: Option<NonZeroUsize>,
     up: String,
+    /// Up SQL as written, before [`Migrations::expand_vars`]
+    raw_up: Option<String>,
     up_hook: Option<Box<dyn MigrationHook>>,
     down: Option<String>,
     down_hook: Option<Box<dyn MigrationHook>>,
     foreign_key_check: bool,
     comment: Option<String>,
+    description: Option<String>,
+    tag: Option<String>,
+    before_commands: Vec<String>,
+    after_commands: Vec<String>,
+    before_sql: Option<String>,
+    /// before.sql as written, before [`Migrations::expand_vars`]
+    raw_before_sql: Option<String>,
+    after_sql: Option<String>,
+    requires_backup: bool,
+    irreversible: bool,
+    defer_foreign_keys: bool,
+    baseline: bool,
+    timeout: Option<Duration>,
+    only_if: Option<String>,
+    assertions: Option<String>,
+    data: Vec<TableData>,
+    checksum_algorithm: checksum::Algorithm,
 }
 
 impl M {
     pub const fn up(sql: String) -> Self {
         Self {
+            version: None,
             up: sql,
+            raw_up: None,
             up_hook: None,
             down: None,
             down_hook: None,
             foreign_key_check: false,
             comment: None,
+            description: None,
+            tag: None,
+            before_commands: Vec::new(),
+            after_commands: Vec::new(),
+            before_sql: None,
+            raw_before_sql: None,
+            after_sql: None,
+            requires_backup: false,
+            irreversible: false,
+            defer_foreign_keys: false,
+            baseline: false,
+            timeout: None,
+            only_if: None,
+            assertions: None,
+            data: Vec::new(),
+            checksum_algorithm: checksum::Algorithm::Sha256,
         }
     }
 
+    /// Data migration written in Rust, e.g. re-encoding a JSON column, run in
+    /// the migration transaction in place of up SQL. Chain
+    /// [`M::down_hook`] to make it revertible.
+    pub fn rust(hook: impl MigrationHook + 'static) -> Self {
+        Self::up(String::new()).up_hook(hook)
+    }
+
     pub fn comment(mut self, comment: String) -> Self {
         self.comment = Some(comment);
         self
     }
 
+    /// Human-readable description, recorded in the history table
+    pub fn description(mut self, description: String) -> Self {
+        self.description = Some(description);
+        self
+    }
+
+    /// Name of the release this migration is the last one of, e.g. `v2.3`
+    pub fn tag(mut self, tag: String) -> Self {
+        self.tag = Some(tag);
+        self
+    }
+
+    pub fn get_tag(&self) -> Option<&str> {
+        self.tag.as_deref()
+    }
+
     pub fn down(mut self, sql: String) -> Self {
         self.down = Some(sql);
         self
     }
+
+    /// Rust hook run in the migration transaction, after the up SQL
+    pub fn up_hook(mut self, hook: impl MigrationHook + 'static) -> Self {
+        self.up_hook = Some(Box::new(hook));
+        self
+    }
+
+    /// Rust hook run in the migration transaction, before the down SQL. A
+    /// migration without down SQL is then reverted by the hook alone.
+    pub fn down_hook(mut self, hook: impl MigrationHook + 'static) -> Self {
+        self.down_hook = Some(Box::new(hook));
+        self.down.get_or_insert_with(String::new);
+        self
+    }
+
+    /// Fail the migration if it leaves rows violating foreign keys
+    pub fn foreign_key_check(mut self, foreign_key_check: bool) -> Self {
+        self.foreign_key_check = foreign_key_check;
+        self
+    }
+
+    /// Refuse to apply this migration unless a backup is taken first
+    pub fn requires_backup(mut self, requires_backup: bool) -> Self {
+        self.requires_backup = requires_backup;
+        self
+    }
+
+    pub fn get_requires_backup(&self) -> bool {
+        self.requires_backup
+    }
+
+    /// Refuse to revert this migration, e.g. a data migration that loses
+    /// information
+    pub fn irreversible(mut self, irreversible: bool) -> Self {
+        self.irreversible = irreversible;
+        self
+    }
+
+    pub fn get_irreversible(&self) -> bool {
+        self.irreversible
+    }
+
+    /// Defer foreign key checks to the commit of the transaction reverting
+    /// this migration, see [`Migrations::defer_foreign_keys`]
+    pub fn defer_foreign_keys(mut self, defer_foreign_keys: bool) -> Self {
+        self.defer_foreign_keys = defer_foreign_keys;
+        self
+    }
+
+    /// Mark this migration as a baseline replacing the migrations before it,
+    /// see [`Migrations::baseline_version`]
+    pub fn baseline(mut self, baseline: bool) -> Self {
+        self.baseline = baseline;
+        self
+    }
+
+    pub fn get_baseline(&self) -> bool {
+        self.baseline
+    }
+
+    /// Interrupt and roll back the migration once it runs longer than
+    /// `timeout`, overriding [`Migrations::timeout`]
+    pub fn timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = Some(timeout);
+        self
+    }
+
+    /// Guard query returning a single value. When it is false (0 or NULL) the
+    /// migration is recorded as skipped instead of being run.
+    pub fn only_if(mut self, query: String) -> Self {
+        self.only_if = Some(query);
+        self
+    }
+
+    pub fn get_only_if(&self) -> Option<&str> {
+        self.only_if.as_deref()
+    }
+
+    /// Queries each returning a single value, checked after the up SQL runs.
+    /// The migration is rolled back if any of them is false (0 or NULL).
+    pub fn assert(mut self, queries: String) -> Self {
+        self.assertions = Some(queries);
+        self
+    }
+
+    pub fn get_assertions(&self) -> Option<&str> {
+        self.assertions.as_deref()
+    }
+
+    /// Rows inserted after the up SQL runs, in the same transaction
+    pub fn data(mut self, data: TableData) -> Self {
+        self.data.push(data);
+        self
+    }
+
+    pub fn get_data(&self) -> &[TableData] {
+        &self.data
+    }
+
+    /// Comment of the migration, the folder name for migrations loaded from a
+    /// directory
+    pub fn get_comment(&self) -> Option<&str> {
+        self.comment.as_deref()
+    }
+
+    pub fn get_description(&self) -> Option<&str> {
+        self.description.as_deref()
+    }
+
+    pub fn get_up(&self) -> &str {
+        &self.up
+    }
+
+    pub fn get_down(&self) -> Option<&str> {
+        self.down.as_deref()
+    }
+
+    /// Up SQL followed by the INSERT statements of the data rows, applying
+    /// the migration as a plain script
+    pub fn get_up_script(&self) -> Cow<'_, str> {
+        if self.data.is_empty() {
+            return Cow::Borrowed(&self.up);
+        }
+
+        let up = self.up.trim_end();
+        // on its own line, as the up SQL may end with a comment
+        let mut sql = if up.ends_with(';') {
+            format!("{up}\n")
+        } else {
+            format!("{up}\n;\n")
+        };
+        for data in &self.data {
+            sql.push_str(&data.insert_sql());
+        }
+        Cow::Owned(sql)
+    }
+
+    /// Checksum of the up SQL, preceded by the before SQL and followed by the
+    /// data rows if any, as written before variable expansion, as stored in
+    /// the history table
+    pub fn checksum(&self) -> String {
+        self.checksum_algorithm.checksum(&self.checksummed_sql())
+    }
+
+    /// Whether a checksum stored in the history table, with any algorithm,
+    /// matches the up SQL
+    pub fn checksum_matches(&self, stored: &str) -> bool {
+        checksum::matches(stored, &self.checksummed_sql())
+    }
+
+    fn checksummed_sql(&self) -> Cow<'_, str> {
+        let up = self.raw_up.as_deref().unwrap_or(&self.up);
+        let before = self.raw_before_sql.as_ref().or(self.before_sql.as_ref());
+        if before.is_none() && self.data.is_empty() {
+            return Cow::Borrowed(up);
+        }
+
+        let mut sql = before.map(|b| format!("{b}\n")).unwrap_or_default();
+        sql.push_str(up);
+        for data in &self.data {
+            sql.push('\n');
+            sql.push_str(&data.insert_sql());
+        }
+        Cow::Owned(sql)
+    }
+
+    /// Hooks run when applying the migration in `direction`, in order
+    pub fn hooks(&self, direction: Direction) -> Vec<HookDescription> {
+        let shell = |stage, commands: &[String]| {
+            commands
+                .iter()
+                .map(move |c| HookDescription {
+                    stage,
+                    command: Some(c.clone()),
+                })
+                .collect::<Vec<_>>()
+        };
+        let rust_hook = match direction {
+            Direction::Up => self.up_hook.is_some(),
+            Direction::Down => self.down_hook.is_some(),
+        };
+
+        let mut hooks = shell(HookStage::Before, &self.before_commands);
+        if rust_hook {
+            hooks.push(HookDescription {
+                stage: HookStage::During,
+                command: None,
+            });
+        }
+        hooks.extend(shell(HookStage::After, &self.after_commands));
+        hooks
+    }
+
+    fn hook_context(&self, version: usize, direction: Direction) -> HookContext<'_> {
+        HookContext {
+            version: NonZeroUsize::new(version).expect("version should not be equal to 0"),
+            name: self.comment.as_deref(),
+            direction,
+        }
+    }
+
+    /// Shell command run before this migration, outside the transaction, see
+    /// [transactions](M#transactions).
+    pub fn before_command(mut self, command: String) -> Self {
+        self.before_commands.push(command);
+        self
+    }
+
+    /// Shell command run after this migration, outside the transaction, see
+    /// [transactions](M#transactions).
+    pub fn after_command(mut self, command: String) -> Self {
+        self.after_commands.push(command);
+        self
+    }
+
+    /// SQL run on the connection right before the migration transaction
+    /// begins, whichever the direction, e.g. `PRAGMA foreign_keys = OFF`, see
+    /// [transactions](M#transactions). This SQL is part of the checksum.
+    pub fn before_sql(mut self, sql: String) -> Self {
+        self.before_sql = Some(sql);
+        self
+    }
+
+    /// SQL run on the connection once the migration transaction is
+    /// committed, whichever the direction, e.g. `ANALYZE`, see
+    /// [transactions](M#transactions).
+    pub fn after_sql(mut self, sql: String) -> Self {
+        self.after_sql = Some(sql);
+        self
+    }
 }
 
+#[cfg(feature = "loader")]
 impl<'a> From<&'a MigrationFile> for M {
     fn from(value: &'a MigrationFile) -> Self {
-        M::up(value.up.clone())
+        let m = M::up(value.up.clone())
             .comment(value.name.clone())
             .down(value.down.clone().unwrap_or_default())
+            .requires_backup(value.meta.requires_backup)
+            .irreversible(value.meta.irreversible)
+            .defer_foreign_keys(value.meta.defer_foreign_keys)
+            .baseline(value.meta.baseline);
+        let m = match &value.meta.only_if {
+            Some(query) => m.only_if(query.clone()),
+            None => m,
+        };
+        let m = match value.meta.timeout_secs {
+            Some(secs) => m.timeout(Duration::from_secs(secs)),
+            None => m,
+        };
+        let m = match &value.meta.tag {
+            Some(tag) => m.tag(tag.clone()),
+            None => m,
+        };
+        let m = match &value.description {
+            Some(description) => m.description(description.clone()),
+            None => m,
+        };
+        let m = match &value.before_sql {
+            Some(sql) => m.before_sql(sql.clone()),
+            None => m,
+        };
+        let m = match &value.after_sql {
+            Some(sql) => m.after_sql(sql.clone()),
+            None => m,
+        };
+        let m = match &value.assert {
+            Some(queries) => m.assert(queries.clone()),
+            None => m,
+        };
+        let m = value.data.iter().fold(m, |m, data| m.data(data.clone()));
+        let m = value
+            .meta
+            .before
+            .iter()
+            .fold(m, |m, cmd| m.before_command(cmd.clone()));
+        let mut m = value
+            .meta
+            .after
+            .iter()
+            .fold(m, |m, cmd| m.after_command(cmd.clone()));
+        m.version = Some(value.id);
+        m
     }
 }
 
@@ -131,32 +511,591 @@ impl cmp::PartialOrd for SchemaVersion {
     }
 }
 
+/// Direction a migration is run in
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum Direction {
+    Up,
+    Down,
+}
+
+impl fmt::Display for Direction {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Direction::Up => f.pad("up"),
+            Direction::Down => f.pad("down"),
+        }
+    }
+}
+
+/// A migration run by [`Migrations::to_latest`] or [`Migrations::to_version`]
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct MigrationRun {
+    pub version: NonZeroUsize,
+    pub name: Option<String>,
+    pub direction: Direction,
+    /// Wall-clock time of the migration SQL and hooks
+    pub duration: Duration,
+    /// The migration was not run because its `only_if` guard was false
+    pub skipped: bool,
+    /// Number of statements executed
+    pub statements: usize,
+    /// Rows inserted, updated or deleted by the statements
+    pub rows_changed: u64,
+}
+
+/// When a hook runs relative to the migration transaction
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum HookStage {
+    /// Shell command run before the transaction
+    Before,
+    /// Rust hook run inside the transaction
+    During,
+    /// Shell command run after the transaction commits
+    After,
+}
+
+impl fmt::Display for HookStage {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            HookStage::Before => f.pad("before"),
+            HookStage::During => f.pad("during"),
+            HookStage::After => f.pad("after"),
+        }
+    }
+}
+
+/// A hook run along with a migration, described for plans since it has no
+/// SQL to show
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct HookDescription {
+    pub stage: HookStage,
+    /// The shell command, or `None` for Rust hooks
+    pub command: Option<String>,
+}
+
+impl fmt::Display for HookDescription {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.command {
+            Some(command) => write!(f, "{} shell: {}", self.stage, command),
+            None => write!(f, "{} rust hook", self.stage),
+        }
+    }
+}
+
+/// Progress of a migration run, reported to the observer set with
+/// [`Migrations::with_observer`]
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub enum MigrationEvent {
+    /// Migrations are about to run from version `from` to version `to`
+    Started {
+        from: usize,
+        to: usize,
+        direction: Direction,
+    },
+    /// A migration ran in the transaction, which is not committed yet
+    MigrationApplied {
+        version: NonZeroUsize,
+        direction: Direction,
+        duration: Duration,
+    },
+    /// A migration failed, the run is rolled back
+    Failed {
+        version: NonZeroUsize,
+        error: String,
+    },
+    /// The transaction is committed, the database is at `version`
+    Committed { version: usize },
+}
+
+/// Kind of the migration transaction, see SQLite's `BEGIN` documentation
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, serde::Deserialize, serde::Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum TransactionMode {
+    /// Take locks when first needed. Another writer may then make the
+    /// migration fail midway, when its read lock is upgraded.
+    Deferred,
+    /// Take the write lock upfront, readers can still use the database
+    #[default]
+    Immediate,
+    /// Lock readers out too in rollback journal mode, for maintenance windows
+    Exclusive,
+}
+
+impl From<TransactionMode> for TransactionBehavior {
+    fn from(mode: TransactionMode) -> Self {
+        match mode {
+            TransactionMode::Deferred => TransactionBehavior::Deferred,
+            TransactionMode::Immediate => TransactionBehavior::Immediate,
+            TransactionMode::Exclusive => TransactionBehavior::Exclusive,
+        }
+    }
+}
+
+#[derive(Clone)]
+struct Observer(Arc<dyn Fn(MigrationEvent) + Send + Sync>);
+
+impl std::fmt::Debug for Observer {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "Observer({:#x})",
+            Arc::as_ptr(&self.0) as *const () as usize
+        )
+    }
+}
+
 /// Set of migrations
 // PartialEq, Eq,
 #[derive(Debug, Clone)]
 pub struct Migrations {
     ms: Vec<M>,
+    observer: Option<Observer>,
+    version_storage: version::Storage,
+    busy_retry: busy::Retry,
+    partial_commit: bool,
+    defer_foreign_keys: bool,
+    transaction_mode: TransactionMode,
+    interrupt_flag: Option<Arc<AtomicBool>>,
+    timeout: Option<Duration>,
+    repeatable: Vec<Repeatable>,
+}
+
+/// Builder of a migration set defined in code, without the filesystem loader.
+/// Migrations are added in version order, either from their SQL or as `M`
+/// values carrying hooks and metadata.
+#[derive(Debug, Clone, Default)]
+pub struct MigrationsBuilder {
+    ms: Vec<M>,
+}
+
+impl MigrationsBuilder {
+    #[must_use]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Add the next migration, which can carry hooks and metadata
+    #[must_use]
+    pub fn migration(mut self, m: M) -> Self {
+        self.ms.push(m);
+        self
+    }
+
+    /// Add the next migration from its name and SQL
+    #[must_use]
+    pub fn sql(self, name: &str, up: &str, down: Option<&str>) -> Self {
+        let m = M::up(up.to_owned()).comment(name.to_owned());
+        self.migration(match down {
+            Some(down) => m.down(down.to_owned()),
+            None => m,
+        })
+    }
+
+    #[must_use]
+    pub fn build(self) -> Migrations {
+        Migrations::new(self.ms)
+    }
 }
 
 impl Migrations {
     #[must_use]
     pub fn new(ms: Vec<M>) -> Self {
-        Self { ms }
+        Self {
+            ms,
+            observer: None,
+            version_storage: version::Storage::default(),
+            busy_retry: busy::Retry::default(),
+            partial_commit: false,
+            defer_foreign_keys: false,
+            transaction_mode: TransactionMode::Immediate,
+            interrupt_flag: None,
+            timeout: None,
+            repeatable: Vec::new(),
+        }
+    }
+
+    /// Migrations defined in code, e.g. with `include_str!`, as
+    /// `(name, up, down)` entries in version order
+    #[must_use]
+    pub fn from_slices(ms: &[(&str, &str, Option<&str>)]) -> Self {
+        ms.iter()
+            .fold(MigrationsBuilder::new(), |builder, (name, up, down)| {
+                builder.sql(name, up, *down)
+            })
+            .build()
     }
 
+    #[cfg(feature = "loader")]
     pub fn from_directory(dir: &Path) -> Result<Self> {
         let migrations = from_directory(dir)?
             .into_iter()
             .collect::<Option<Vec<_>>>()
-            .ok_or(anyhow::format_err!("Could not load migrations".to_string()))?;
+            .ok_or(Error::NoMigrationFiles)?;
+
+        Ok(Self::new(migrations))
+    }
+
+    /// Load the migrations of `dir` like [`Migrations::from_directory`], but
+    /// allowing gaps between ids, e.g. of deleted migrations or date prefixes.
+    /// The version of each migration is then its id rather than its position.
+    #[cfg(feature = "loader")]
+    pub fn from_directory_allowing_gaps(dir: &Path) -> Result<Self> {
+        Ok(Self::new(from_directory_allowing_gaps(dir)?))
+    }
+
+    /// Load a directory following Flyway's naming: `V<id>__<name>.sql`
+    /// migrations, reverted by the matching `U<id>__<name>.sql`, and
+    /// `R__<name>.sql` repeatable migrations. Gaps between ids are allowed.
+    #[cfg(feature = "loader")]
+    pub fn from_flyway_directory(dir: &Path) -> Result<Self> {
+        let (ms, repeatable) = from_flyway_directory(dir)?;
+        Ok(Self::new(ms).repeatable(repeatable))
+    }
+
+    /// Add `m` as migration `version` of the set, e.g. a Rust data migration
+    /// built with [`M::rust`] among the SQL migrations of a directory. Fails
+    /// when `version` is already used.
+    pub fn insert(mut self, version: NonZeroUsize, mut m: M) -> Result<Self> {
+        // migrations defined in code are numbered by position until now
+        for (i, m) in self.ms.iter_mut().enumerate() {
+            m.version
+                .get_or_insert(NonZeroUsize::new(i + 1).expect("i + 1 is not 0"));
+        }
+
+        let position = self.ms.partition_point(|m| m.version < Some(version));
+        if self
+            .ms
+            .get(position)
+            .is_some_and(|m| m.version == Some(version))
+        {
+            return Err(Error::InvalidId(format!(
+                "Migration id {version} is already used"
+            )));
+        }
+        m.version = Some(version);
+        self.ms.insert(position, m);
+
+        Ok(self)
+    }
+
+    /// Call `observer` with the progress of every run, e.g. to report it in
+    /// the UI or metrics of the embedding application
+    #[must_use]
+    pub fn with_observer(
+        mut self,
+        observer: impl Fn(MigrationEvent) + Send + Sync + 'static,
+    ) -> Self {
+        self.observer = Some(Observer(Arc::new(observer)));
+        self
+    }
+
+    fn notify(&self, event: MigrationEvent) {
+        if let Some(observer) = &self.observer {
+            (observer.0)(event);
+        }
+    }
+
+    // Report the failure of migration `version` to the observer
+    fn failed<E: fmt::Display>(&self, version: usize, error: E) -> E {
+        self.notify(MigrationEvent::Failed {
+            version: NonZeroUsize::new(version).expect("version should not be equal to 0"),
+            error: error.to_string(),
+        });
+        error
+    }
+
+    // Report the failure of the migration at `index` to the observer and
+    // record it in the failures table, once its transaction is rolled back
+    fn record_failure(
+        &self,
+        conn: &Connection,
+        index: usize,
+        direction: Direction,
+        error: Error,
+    ) -> Error {
+        let version = self.version_at(index);
+        let error = self.failed(version, error);
+        let failure = failures::Failure::new(
+            version,
+            self.ms[index].comment.as_deref().unwrap_or_default(),
+            &direction.to_string(),
+            &error,
+        );
+        if let Err(e) = failures::record(conn, &failure) {
+            warn!("Could not record the failure of migration {version}: {e:#}");
+        }
+        error
+    }
+
+    /// Expand `${VAR}` placeholders in the SQL of every migration, see
+    /// [`vars::expand`]. Checksums still cover the up SQL as written, so that
+    /// they don't change with the variables.
+    pub fn expand_vars(mut self, vars: &HashMap<String, String>) -> Result<Self> {
+        for (i, m) in self.ms.iter_mut().enumerate() {
+            let version = version_at(i, m);
+            let context = || {
+                anyhow::format_err!(
+                    "migration {} {}",
+                    version,
+                    m.comment.as_deref().unwrap_or_default()
+                )
+            };
+            let up = vars::expand(&m.up, vars).with_context(context)?;
+            m.raw_up.get_or_insert(std::mem::replace(&mut m.up, up));
+            if let Some(before) = &m.before_sql {
+                let expanded = vars::expand(before, vars).with_context(context)?;
+                let before = m.before_sql.replace(expanded);
+                m.raw_before_sql = m.raw_before_sql.take().or(before);
+            }
+            if let Some(after) = &m.after_sql {
+                m.after_sql = Some(vars::expand(after, vars).with_context(context)?);
+            }
+            if let Some(down) = &m.down {
+                m.down = Some(vars::expand(down, vars).with_context(context)?);
+            }
+            if let Some(assertions) = &m.assertions {
+                m.assertions = Some(vars::expand(assertions, vars).with_context(context)?);
+            }
+        }
+        for r in &mut self.repeatable {
+            r.sql = vars::expand(&r.sql, vars)
+                .with_context(|| anyhow::format_err!("repeatable migration {}", r.name))?;
+        }
+
+        Ok(self)
+    }
+
+    /// Compute the checksums of new history entries with `algorithm`
+    pub fn checksum_algorithm(mut self, algorithm: checksum::Algorithm) -> Self {
+        for m in &mut self.ms {
+            m.checksum_algorithm = algorithm;
+        }
+        self
+    }
+
+    /// Store the schema version in `storage`, e.g. a table for versions
+    /// beyond the 32-bit range of `user_version`
+    pub fn version_storage(mut self, storage: version::Storage) -> Self {
+        self.version_storage = storage;
+        self
+    }
+
+    pub fn get_version_storage(&self) -> version::Storage {
+        self.version_storage
+    }
 
-        Ok(Self { ms: migrations })
+    /// Rerun these scripts after [`Migrations::to_latest`] whenever their SQL
+    /// changed since they last ran, in order
+    #[must_use]
+    pub fn repeatable(mut self, repeatable: Vec<Repeatable>) -> Self {
+        self.repeatable = repeatable;
+        self
+    }
+
+    pub fn get_repeatable(&self) -> &[Repeatable] {
+        &self.repeatable
+    }
+
+    // Run the repeatable migrations whose SQL changed, in one transaction
+    fn run_repeatable(&self, conn: &mut Connection) -> Result<()> {
+        if self.repeatable.is_empty() {
+            return Ok(());
+        }
+
+        let tx = self.begin(conn)?;
+        let mut ran = false;
+        for r in &self.repeatable {
+            if !repeatable::is_changed(&tx, r)? {
+                continue;
+            }
+            tx.execute_batch(&r.sql)
+                .with_context(|| anyhow::format_err!("repeatable migration {}", r.name))?;
+            repeatable::record(&tx, r)?;
+            info!("Ran repeatable migration {}", r.name);
+            ran = true;
+        }
+        // the schema recorded for the current version now includes them
+        if ran {
+            let version = self.version_storage.get(&tx)?;
+            history::set_schema_hash(&tx, version, &schema::hash(&tx)?)?;
+        }
+        tx.commit()?;
+
+        Ok(())
+    }
+
+    /// Retry starting the migration transaction up to `retries` times while
+    /// another connection holds the database, waiting `backoff` before the
+    /// first retry and twice as long before each following one
+    pub fn busy_retries(mut self, retries: u32, backoff: Duration) -> Self {
+        self.busy_retry = busy::Retry { retries, backoff };
+        self
+    }
+
+    pub fn get_busy_retry(&self) -> busy::Retry {
+        self.busy_retry
+    }
+
+    /// Each migration runs in a savepoint of the migration transaction. When
+    /// one fails, commit the migrations that succeeded before it rather than
+    /// rolling back all of them, failing with [`Error::PartiallyApplied`].
+    pub fn partial_commit(mut self, partial_commit: bool) -> Self {
+        self.partial_commit = partial_commit;
+        self
+    }
+
+    /// Set `PRAGMA defer_foreign_keys` in the transaction of down migrations,
+    /// so that foreign keys are checked when it commits rather than after
+    /// each statement, whatever order the referencing tables are dropped in.
+    /// Only rolling back a migration set with [`M::defer_foreign_keys`]
+    /// does so otherwise.
+    pub fn defer_foreign_keys(mut self, defer_foreign_keys: bool) -> Self {
+        self.defer_foreign_keys = defer_foreign_keys;
+        self
+    }
+
+    /// Kind of the migration transaction, [`TransactionMode::Immediate`] by
+    /// default
+    pub fn transaction_mode(mut self, mode: TransactionMode) -> Self {
+        self.transaction_mode = mode;
+        self
+    }
+
+    pub fn get_transaction_mode(&self) -> TransactionMode {
+        self.transaction_mode
+    }
+
+    /// Stop with [`Error::Interrupted`] once `flag` is set, e.g. by a signal
+    /// handler which also interrupts the connection with
+    /// [`Connection::get_interrupt_handle`]. The migration transaction is
+    /// rolled back.
+    pub fn interrupt_flag(mut self, flag: Arc<AtomicBool>) -> Self {
+        self.interrupt_flag = Some(flag);
+        self
+    }
+
+    /// Interrupt and roll back any migration running longer than `timeout`,
+    /// unless it sets its own timeout
+    pub fn timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = Some(timeout);
+        self
+    }
+
+    // Run `f`, the migration `m`, interrupting it once it runs longer than
+    // its timeout. The error of the statement interrupted is then wrapped in
+    // `Error::Timeout`.
+    fn with_timeout<T>(
+        &self,
+        conn: &Connection,
+        version: usize,
+        m: &M,
+        f: impl FnOnce() -> Result<T>,
+    ) -> Result<T> {
+        let Some(timeout) = m.timeout.or(self.timeout) else {
+            return f();
+        };
+
+        let watchdog = Watchdog::start(conn, timeout);
+        let res = f();
+        let fired = watchdog.stop();
+        match res {
+            Err(source) if fired => Err(Error::Timeout {
+                version,
+                timeout,
+                source: Box::new(source),
+            }),
+            res => res,
+        }
+    }
+
+    // Fail with `Error::Interrupted` for migration `version` if the run was
+    // interrupted, also when the interruption made a statement fail
+    fn check_interrupted(&self, version: usize) -> Result<()> {
+        match &self.interrupt_flag {
+            Some(flag) if flag.load(atomic::Ordering::SeqCst) => {
+                Err(Error::Interrupted { version })
+            }
+            _ => Ok(()),
+        }
+    }
+
+    /// Start a transaction of the configured mode, `BEGIN IMMEDIATE` by default
+    /// so that the write lock is taken upfront rather than failing when a read
+    /// lock is upgraded midway, retrying while the database is locked
+    pub(crate) fn begin<'c>(&self, conn: &'c Connection) -> Result<Transaction<'c>> {
+        self.busy_retry
+            .run(busy::is_busy, || {
+                Transaction::new_unchecked(conn, self.transaction_mode.into())
+            })
+            .map_err(|source| {
+                if busy::is_busy(&source) {
+                    Error::Busy {
+                        attempts: self.busy_retry.retries + 1,
+                        source,
+                    }
+                } else {
+                    source.into()
+                }
+            })
+    }
+
+    /// Migrations of the set, in version order
+    pub fn migrations(&self) -> &[M] {
+        &self.ms
+    }
+
+    /// Migrations of the set with their version, in version order
+    pub fn iter(&self) -> impl Iterator<Item = (usize, &M)> {
+        self.ms
+            .iter()
+            .enumerate()
+            .map(|(i, m)| (version_at(i, m), m))
+    }
+
+    /// Migration of the set with `version`
+    pub fn get(&self, version: usize) -> Option<&M> {
+        self.iter().find(|(v, _)| *v == version).map(|(_, m)| m)
+    }
+
+    /// Version `steps` migrations after `version`, or `None` when fewer
+    /// migrations follow it
+    pub fn version_after(&self, version: usize, steps: usize) -> Option<usize> {
+        if steps == 0 {
+            return Some(version);
+        }
+        let index = self.position(version) + steps - 1;
+        (index < self.ms.len()).then(|| self.version_at(index))
+    }
+
+    /// Version `steps` migrations before `version`, 0 being before the first
+    /// migration, or `None` when fewer migrations precede it
+    pub fn version_before(&self, version: usize, steps: usize) -> Option<usize> {
+        let position = self.position(version).checked_sub(steps)?;
+        Some(self.version_up_to(position))
+    }
+
+    fn version_at(&self, index: usize) -> usize {
+        version_at(index, &self.ms[index])
+    }
+
+    // Number of migrations with a version up to `version`, i.e. the position
+    // of the first migration after it
+    fn position(&self, version: usize) -> usize {
+        self.iter().take_while(|(v, _)| *v <= version).count()
+    }
+
+    // Version of the database once the first `position` migrations are applied
+    fn version_up_to(&self, position: usize) -> usize {
+        match position {
+            0 => 0,
+            p => self.version_at(p - 1),
+        }
     }
 
     fn db_version_to_schema(&self, db_version: usize) -> SchemaVersion {
         match db_version {
             0 => SchemaVersion::NoneSet,
-            v if v > 0 && v <= self.ms.len() => SchemaVersion::Inside(
+            v if v <= usize::from(self.max_version()) => SchemaVersion::Inside(
                 NonZeroUsize::new(v).expect("schema version should not be equal to 0"),
             ),
             v => SchemaVersion::Outside(
@@ -166,7 +1105,7 @@ impl Migrations {
     }
 
     pub fn current_version(&self, conn: &Connection) -> Result<SchemaVersion> {
-        Ok(user_version(conn).map(|v| self.db_version_to_schema(v))?)
+        Ok(self.db_version_to_schema(self.version_storage.get(conn)?))
     }
 
     fn goto_up(
@@ -174,94 +1113,431 @@ impl Migrations {
         conn: &mut Connection,
         current_version: usize,
         target_version: usize,
-    ) -> Result<()> {
+    ) -> Result<Vec<MigrationRun>> {
         debug_assert!(current_version <= target_version);
-        debug_assert!(target_version <= self.ms.len());
+        debug_assert!(target_version <= usize::from(self.max_version()));
+        let baseline = self.baseline_version();
+        if current_version > 0 && current_version < baseline {
+            return Err(Error::BeforeBaseline {
+                version: current_version,
+                baseline,
+            });
+        }
+        let (from, to) = (
+            self.position(current_version),
+            self.position(target_version),
+        );
+
+        self.notify(MigrationEvent::Started {
+            from: current_version,
+            to: target_version,
+            direction: Direction::Up,
+        });
+
+        let mut runs = Vec::with_capacity(to - from);
+        for range in self.transactions(from, to) {
+            let committed = (range.start > from).then(|| self.version_up_to(range.start));
+            runs.extend(
+                self.up_transaction(conn, range)
+                    .map_err(|e| after_commit(e, committed))?,
+            );
+        }
+
+        Ok(runs)
+    }
+
+    // Apply the migrations at the positions of `range` in one transaction,
+    // with the before and after commands and SQL of the first one around it
+    fn up_transaction(
+        &self,
+        conn: &mut Connection,
+        range: Range<usize>,
+    ) -> Result<Vec<MigrationRun>> {
+        let target_version = self.version_at(range.end - 1);
+        self.run_before(conn, range.start, Direction::Up)
+            .map_err(|e| self.record_failure(conn, range.start, Direction::Up, e))?;
 
         trace!("start migration transaction");
-        let tx = conn.transaction()?;
+        let tx = self.begin(conn)?;
+        history::ensure_table(&tx)?;
 
-        for v in current_version..target_version {
+        let mut runs = Vec::with_capacity(range.len());
+        for v in range.clone() {
             let m = &self.ms[v];
-            debug!("Running: {}", m.up);
+            let start = Instant::now();
+            let savepoint = savepoint(&tx, self.version_at(v))?;
 
-            tx.execute_batch(&m.up)
-                .context(anyhow::format_err!("query: {}", m.up))?;
+            let run = || -> Result<(bool, (usize, u64))> {
+                self.check_interrupted(self.version_at(v))?;
+                let skipped = match &m.only_if {
+                    Some(guard) => !check_guard(&tx, guard)?,
+                    None => false,
+                };
+                let mut stats = (0, 0);
+                if skipped {
+                    info!(
+                        "Skipping migration {}: only_if guard is false",
+                        self.version_at(v)
+                    );
+                } else {
+                    stats = execute_statements(&tx, self.version_at(v), &m.up)?;
+                    for data in &m.data {
+                        stats.1 += data.insert(&tx, self.version_at(v))?;
+                    }
 
-            if m.foreign_key_check {
-                validate_foreign_keys(&tx)?;
-            }
+                    if m.foreign_key_check {
+                        validate_foreign_keys(&tx)?;
+                    }
 
-            if let Some(hook) = &m.up_hook {
-                hook(&tx)?;
-            }
+                    if let Some(hook) = &m.up_hook {
+                        hook(&tx, &m.hook_context(self.version_at(v), Direction::Up))?;
+                    }
+
+                    if let Some(assertions) = &m.assertions {
+                        check_assertions(&tx, self.version_at(v), assertions)?;
+                    }
+                }
+                Ok((skipped, stats))
+            };
+            let (skipped, stats) = match self.with_timeout(&tx, self.version_at(v), m, run) {
+                Ok(result) => result,
+                Err(e) => {
+                    let e = self
+                        .check_interrupted(self.version_at(v))
+                        .err()
+                        .unwrap_or(e);
+                    let e = self.commit_prefix(
+                        tx,
+                        &savepoint,
+                        self.version_up_to(v),
+                        v - range.start,
+                        e,
+                    );
+                    return Err(self.record_failure(conn, v, Direction::Up, e));
+                }
+            };
+
+            let duration = start.elapsed();
+            self.notify(MigrationEvent::MigrationApplied {
+                version: NonZeroUsize::new(self.version_at(v))
+                    .expect("version should not be equal to 0"),
+                direction: Direction::Up,
+                duration,
+            });
+            info!(
+                version = self.version_at(v),
+                name = m.comment.as_deref().unwrap_or_default(),
+                direction = %Direction::Up,
+                duration_ms = duration.as_secs_f64() * 1000.0,
+                skipped,
+                "Applied migration"
+            );
+            let mut entry = history::HistoryEntry::new(
+                self.version_at(v),
+                m.comment.as_deref().unwrap_or_default(),
+                &m.checksum(),
+                duration,
+            );
+            entry.skipped = skipped;
+            entry.description = m.description.clone().unwrap_or_default();
+            history::record(&tx, &entry)?;
+            tx.execute_batch(&format!("RELEASE {savepoint}"))?;
+            runs.push(MigrationRun {
+                version: NonZeroUsize::new(self.version_at(v))
+                    .expect("version should not be equal to 0"),
+                name: m.comment.clone(),
+                direction: Direction::Up,
+                duration,
+                skipped,
+                statements: stats.0,
+                rows_changed: stats.1,
+            });
         }
 
-        set_user_version(&tx, target_version)?;
+        history::set_schema_hash(&tx, target_version, &schema::hash(&tx)?)?;
+        self.version_storage.set(&tx, target_version)?;
         tx.commit()?;
         trace!("commited migration transaction");
+        self.notify(MigrationEvent::Committed {
+            version: target_version,
+        });
 
-        Ok(())
+        self.run_after(conn, range.start, Direction::Up, &runs)?;
+
+        Ok(runs)
     }
 
-    /// Migrate downward. This is rolled back on error.
+    /// Migrate downward. This is rolled back on error, unless partial commits
+    /// are enabled.
     /// All versions are db versions
     fn goto_down(
         &self,
         conn: &mut Connection,
         current_version: usize,
         target_version: usize,
-    ) -> Result<()> {
+    ) -> Result<Vec<MigrationRun>> {
         debug_assert!(current_version >= target_version);
-        debug_assert!(target_version <= self.ms.len());
+        debug_assert!(target_version <= usize::from(self.max_version()));
+        let (from, to) = (
+            self.position(current_version),
+            self.position(target_version),
+        );
 
         // First, check if all the migrations have a "down" version
         if let Some((i, bad_m)) = self
             .ms
             .iter()
             .enumerate()
-            .skip(target_version)
-            .take(current_version - target_version)
-            .find(|(_, m)| m.down.is_none())
+            .skip(to)
+            .take(from - to)
+            .find(|(_, m)| m.irreversible || m.down.is_none())
         {
             warn!("Cannot revert: {:?}", bad_m);
-            anyhow::bail!(
-                "migration definition: down not defined migration_index: {}",
-                i
-            )
+            let version = self.version_at(i);
+            return Err(if bad_m.irreversible {
+                Error::Irreversible { version }
+            } else {
+                Error::MissingDown { version }
+            });
+        }
+
+        self.notify(MigrationEvent::Started {
+            from: current_version,
+            to: target_version,
+            direction: Direction::Down,
+        });
+
+        let mut runs = Vec::with_capacity(from - to);
+        for range in self.transactions(to, from).into_iter().rev() {
+            let committed = (range.end < from).then(|| self.version_up_to(range.end));
+            runs.extend(
+                self.down_transaction(conn, range)
+                    .map_err(|e| after_commit(e, committed))?,
+            );
         }
 
+        Ok(runs)
+    }
+
+    // Revert the migrations at the positions of `range` in one transaction,
+    // with the before and after commands and SQL of the last one around it
+    fn down_transaction(
+        &self,
+        conn: &mut Connection,
+        range: Range<usize>,
+    ) -> Result<Vec<MigrationRun>> {
+        let target_version = self.version_up_to(range.start);
+        let last = range.end - 1;
+        self.run_before(conn, last, Direction::Down)
+            .map_err(|e| self.record_failure(conn, last, Direction::Down, e))?;
+
         trace!("start migration transaction");
-        let tx = conn.transaction()?;
-        for v in (target_version..current_version).rev() {
+        let tx = self.begin(conn)?;
+        history::ensure_table(&tx)?;
+        // reset when the transaction ends
+        if self.defer_foreign_keys || self.ms[range.clone()].iter().any(|m| m.defer_foreign_keys) {
+            tx.pragma_update(None, "defer_foreign_keys", "ON")?;
+        }
+        let mut runs = Vec::with_capacity(range.len());
+        for v in range.clone().rev() {
             let m = &self.ms[v];
             if let Some(down) = &m.down {
-                debug!("Running: {}", &down);
+                let start = Instant::now();
+                let savepoint = savepoint(&tx, self.version_at(v))?;
 
-                if let Some(hook) = &m.down_hook {
-                    hook(&tx)?;
-                }
+                let run = || -> Result<(bool, (usize, u64))> {
+                    self.check_interrupted(self.version_at(v))?;
+                    // a migration skipped by its guard has nothing to revert
+                    let skipped = history::is_skipped(&tx, self.version_at(v))?;
+                    let mut stats = (0, 0);
+                    if !skipped {
+                        if let Some(hook) = &m.down_hook {
+                            hook(&tx, &m.hook_context(self.version_at(v), Direction::Down))?;
+                        }
+
+                        stats = execute_statements(&tx, self.version_at(v), down)?;
+                    }
+                    Ok((skipped, stats))
+                };
+                let (skipped, stats) = match self.with_timeout(&tx, self.version_at(v), m, run) {
+                    Ok(result) => result,
+                    Err(e) => {
+                        let e = self
+                            .check_interrupted(self.version_at(v))
+                            .err()
+                            .unwrap_or(e);
+                        let e = self.commit_prefix(
+                            tx,
+                            &savepoint,
+                            self.version_up_to(v + 1),
+                            last - v,
+                            e,
+                        );
+                        return Err(self.record_failure(conn, v, Direction::Down, e));
+                    }
+                };
 
-                tx.execute_batch(down)
-                    .context(anyhow::format_err!("query: {}", down))?;
+                let duration = start.elapsed();
+                self.notify(MigrationEvent::MigrationApplied {
+                    version: NonZeroUsize::new(self.version_at(v))
+                        .expect("version should not be equal to 0"),
+                    direction: Direction::Down,
+                    duration,
+                });
+                info!(
+                    version = self.version_at(v),
+                    name = m.comment.as_deref().unwrap_or_default(),
+                    direction = %Direction::Down,
+                    duration_ms = duration.as_secs_f64() * 1000.0,
+                    skipped,
+                    "Reverted migration"
+                );
+                runs.push(MigrationRun {
+                    version: NonZeroUsize::new(self.version_at(v))
+                        .expect("version should not be equal to 0"),
+                    name: m.comment.clone(),
+                    direction: Direction::Down,
+                    duration,
+                    skipped,
+                    statements: stats.0,
+                    rows_changed: stats.1,
+                });
+                history::remove(&tx, self.version_at(v))?;
+                tx.execute_batch(&format!("RELEASE {savepoint}"))?;
             } else {
                 unreachable!();
             }
         }
-        set_user_version(&tx, target_version)?;
+        history::set_schema_hash(&tx, target_version, &schema::hash(&tx)?)?;
+        self.version_storage.set(&tx, target_version)?;
         tx.commit()?;
         trace!("committed migration transaction");
+        self.notify(MigrationEvent::Committed {
+            version: target_version,
+        });
+
+        self.run_after(conn, last, Direction::Down, &runs)?;
+
+        Ok(runs)
+    }
+
+    // Split the positions `from..to` into the ranges of migrations run in one
+    // transaction. A migration with before or after commands or SQL runs in
+    // its own, so that they run right before and after it, and for it only.
+    fn transactions(&self, from: usize, to: usize) -> Vec<Range<usize>> {
+        let mut ranges: Vec<Range<usize>> = Vec::new();
+        for v in from..to {
+            let alone = |m: &M| {
+                m.before_sql.is_some()
+                    || m.after_sql.is_some()
+                    || !m.before_commands.is_empty()
+                    || !m.after_commands.is_empty()
+            };
+            match ranges.last_mut() {
+                Some(last) if !alone(&self.ms[v]) && !alone(&self.ms[last.start]) => {
+                    last.end = v + 1;
+                }
+                _ => ranges.push(v..v + 1),
+            }
+        }
+
+        ranges
+    }
+
+    // Whether the migration at `index` is skipped: going up when its `only_if`
+    // guard is false, going down when it was skipped going up
+    fn is_skipped(&self, conn: &Connection, index: usize, direction: Direction) -> Result<bool> {
+        Ok(match direction {
+            Direction::Up => match &self.ms[index].only_if {
+                Some(guard) => !check_guard(conn, guard)?,
+                None => false,
+            },
+            Direction::Down => history::is_skipped(conn, self.version_at(index))?,
+        })
+    }
+
+    // Run the before commands and before.sql of the migration at `index`,
+    // unless it is skipped
+    fn run_before(&self, conn: &Connection, index: usize, direction: Direction) -> Result<()> {
+        let m = &self.ms[index];
+        let version = self.version_at(index);
+        if (m.before_sql.is_none() && m.before_commands.is_empty())
+            || self.is_skipped(conn, index, direction)?
+        {
+            return Ok(());
+        }
+
+        run_commands(conn, m, version, direction, &m.before_commands)?;
+        run_outside_transaction(conn, version, &m.before_sql)
+    }
+
+    // Run the after.sql and after commands of the migration at `index` once
+    // its transaction, which made `runs`, is committed, unless it was skipped
+    fn run_after(
+        &self,
+        conn: &Connection,
+        index: usize,
+        direction: Direction,
+        runs: &[MigrationRun],
+    ) -> Result<()> {
+        let m = &self.ms[index];
+        let version = self.version_at(index);
+        if runs.iter().any(|r| r.version.get() == version && r.skipped) {
+            return Ok(());
+        }
+
+        run_outside_transaction(conn, version, &m.after_sql)?;
+        run_commands(conn, m, version, direction, &m.after_commands)?;
         Ok(())
     }
 
+    // After a migration failed in `savepoint`, commit the `done` migrations
+    // run before it at `version` when partial commits are enabled, else let
+    // `tx` roll back
+    fn commit_prefix(
+        &self,
+        tx: Transaction,
+        savepoint: &str,
+        version: usize,
+        done: usize,
+        error: Error,
+    ) -> Error {
+        // SQLite already rolled back the transaction on some errors, such as
+        // an interrupted write
+        if !self.partial_commit || done == 0 || tx.is_autocommit() {
+            return error;
+        }
+
+        let commit = || -> Result<()> {
+            tx.execute_batch(&format!("ROLLBACK TO {savepoint}; RELEASE {savepoint}"))?;
+            history::set_schema_hash(&tx, version, &schema::hash(&tx)?)?;
+            self.version_storage.set(&tx, version)?;
+            tx.commit()?;
+            Ok(())
+        };
+        if let Err(e) = commit() {
+            warn!("Could not commit the migrations preceding the failure: {e}");
+            return error;
+        }
+        info!("Committed {done} migrations before the failure, database at version {version}");
+        self.notify(MigrationEvent::Committed { version });
+
+        Error::PartiallyApplied {
+            version,
+            source: Box::new(error),
+        }
+    }
+
     /// Go to a given db version
-    fn goto(&self, conn: &mut Connection, target_db_version: usize) -> Result<()> {
-        let current_version = user_version(conn)?;
+    fn goto(&self, conn: &mut Connection, target_db_version: usize) -> Result<Vec<MigrationRun>> {
+        let current_version = self.version_storage.get(conn)?;
+        // with gaps between ids, land on the last migration up to the target
+        let target_db_version = self.version_up_to(self.position(target_db_version));
 
         let res = match target_db_version.cmp(&current_version) {
             Ordering::Less => {
-                if current_version > self.ms.len() {
-                    anyhow::bail!("migration definition: database too far ahead")
+                if current_version > usize::from(self.max_version()) {
+                    return Err(Error::DatabaseTooFarAhead);
                 }
                 debug!(
 						"rollback to older version requested, target_db_version: {}, current_version: {}",
@@ -271,7 +1547,7 @@ impl Migrations {
             }
             Ordering::Equal => {
                 debug!("no migration to run, db already up to date");
-                return Ok(()); // return directly, so the migration message is not printed
+                return Ok(Vec::new()); // return directly, so the migration message is not printed
             }
             Ordering::Greater => {
                 debug!(
@@ -288,47 +1564,83 @@ impl Migrations {
     }
 
     /// Maximum version defined in the migration set
-    fn max_schema_version(&self) -> SchemaVersion {
+    pub fn max_version(&self) -> SchemaVersion {
         match self.ms.len() {
             0 => SchemaVersion::NoneSet,
-            v => SchemaVersion::Inside(
-                NonZeroUsize::new(v).expect("schema version should not be equal to 0"),
+            n => SchemaVersion::Inside(
+                NonZeroUsize::new(self.version_at(n - 1))
+                    .expect("schema version should not be equal to 0"),
             ),
         }
     }
 
-    pub fn to_latest(&self, conn: &mut Connection) -> Result<()> {
-        let v_max = self.max_schema_version();
+    /// Version of the last baseline migration, 0 when there is none. The
+    /// history of databases migrated before it was written records the
+    /// migrations it replaced, up to its version, which
+    /// [`Migrations::verify`] and [`Migrations::repair`] leave as is.
+    pub fn baseline_version(&self) -> usize {
+        self.iter()
+            .filter(|(_, m)| m.baseline)
+            .map(|(v, _)| v)
+            .last()
+            .unwrap_or_default()
+    }
+
+    /// Alias of [`Migrations::max_version`]
+    pub fn latest(&self) -> SchemaVersion {
+        self.max_version()
+    }
+
+    /// Every version defined in the migration set, in ascending order
+    pub fn versions(&self) -> Vec<NonZeroUsize> {
+        self.iter()
+            .filter_map(|(v, _)| NonZeroUsize::new(v))
+            .collect()
+    }
+
+    /// Migrations not applied to the database yet, in version order. The
+    /// first one has the version following the current database version.
+    pub fn pending(&self, conn: &Connection) -> Result<Vec<&M>> {
+        let current = self.version_storage.get(conn)?;
+        Ok(self.ms.iter().skip(self.position(current)).collect())
+    }
+
+    /// Migrate to the latest version, then rerun the repeatable migrations
+    /// which changed, returning the migrations that were run
+    pub fn to_latest(&self, conn: &mut Connection) -> Result<Vec<MigrationRun>> {
+        let v_max = self.max_version();
         match v_max {
             SchemaVersion::NoneSet => {
                 warn!("no migration defined");
-                anyhow::bail!("migration definition: no migration defined")
+                Err(Error::NoMigrationDefined)
             }
             SchemaVersion::Inside(v) => {
                 debug!("some migrations defined (version: {v}), try to migrate");
-                self.goto(conn, v_max.into())
+                let runs = self.goto(conn, v_max.into())?;
+                self.run_repeatable(conn)?;
+                Ok(runs)
             }
             SchemaVersion::Outside(_) => unreachable!(),
         }
     }
 
-    pub fn to_version(&self, conn: &mut Connection, version: usize) -> Result<()> {
+    /// Migrate up or down to `version`, returning the migrations that were run
+    pub fn to_version(&self, conn: &mut Connection, version: usize) -> Result<Vec<MigrationRun>> {
         let target_version: SchemaVersion = self.db_version_to_schema(version);
-        let v_max = self.max_schema_version();
+        let v_max = self.max_version();
         match v_max {
             SchemaVersion::NoneSet => {
                 warn!("no migrations defined");
-                anyhow::bail!("migration definition: no migration defined")
+                Err(Error::NoMigrationDefined)
             }
             SchemaVersion::Inside(v) => {
                 debug!("some migrations defined (version: {v}), try to migrate");
                 if target_version > v_max {
                     warn!("specified version is higher than the max supported version");
-                    anyhow::bail!(
-                        "specified schema version {}: higher than max supported version {}",
-                        target_version,
-                        v_max
-                    )
+                    return Err(Error::SpecifiedSchemaVersion {
+                        requested: target_version,
+                        max: v_max,
+                    });
                 }
 
                 self.goto(conn, target_version.into())
@@ -339,46 +1651,322 @@ impl Migrations {
 
     pub fn validate(&self) -> Result<()> {
         let mut conn = Connection::open_in_memory()?;
-        self.to_latest(&mut conn)
+        self.to_latest(&mut conn)?;
+        Ok(())
+    }
+
+    /// Like [`Migrations::validate`], also checking that the down SQL of
+    /// migrations which have one truly reverses their up SQL: each one is applied,
+    /// reverted and applied again in an in-memory database, and the
+    /// normalized schema must then match the one before and after first
+    /// applying it. Fails with [`Error::RoundTrip`].
+    ///
+    /// Only the SQL is run, see [`Migrations::replay`].
+    pub fn validate_round_trip(&self) -> Result<()> {
+        let mut conn = Connection::open_in_memory()?;
+        for (version, _) in self.iter() {
+            self.round_trip(&mut conn, version)?;
+        }
+
+        Ok(())
+    }
+
+    /// Apply migration `version` to `conn`, to which the migrations preceding
+    /// it were replayed, then revert it and apply it again when it has down
+    /// SQL, checking the schema after each step as
+    /// [`Migrations::validate_round_trip`] does
+    pub fn round_trip(&self, conn: &mut Connection, version: usize) -> Result<()> {
+        let m = self.get(version).ok_or(Error::SpecifiedSchemaVersion {
+            requested: self.db_version_to_schema(version),
+            max: self.max_version(),
+        })?;
+
+        let before = schema_copy(conn)?;
+        self.replay_up(conn, version, m)?;
+
+        // migration folders without down.sql have an empty down
+        let reversible = m
+            .down
+            .as_deref()
+            .is_some_and(|down| !down.trim().is_empty());
+        if reversible && !m.irreversible {
+            let after = schema_copy(conn)?;
+            self.replay_down(conn, version, m)?;
+            check_round_trip(version, "reverting it", &before, conn)?;
+            self.replay_up(conn, version, m)?;
+            check_round_trip(version, "applying it again", &after, conn)?;
+        }
+
+        Ok(())
+    }
+
+    /// Run the SQL of the migrations up to `version` against `conn`, a
+    /// scratch database for checks such as [`Migrations::round_trip`].
+    ///
+    /// Only the SQL is run: up SQL, data rows and assertions, each migration
+    /// in its own transaction. Rust hooks, shell commands and before/after
+    /// SQL are left out, `only_if` guards are assumed true and neither the
+    /// database version nor the history are updated.
+    pub fn replay(&self, conn: &mut Connection, version: usize) -> Result<()> {
+        for (v, m) in self.iter().take_while(|(v, _)| *v <= version) {
+            self.replay_up(conn, v, m)?;
+        }
+
+        Ok(())
+    }
+
+    fn replay_up(&self, conn: &mut Connection, version: usize, m: &M) -> Result<()> {
+        let tx = conn.transaction()?;
+        if self.defer_foreign_keys || m.defer_foreign_keys {
+            tx.pragma_update(None, "defer_foreign_keys", "ON")?;
+        }
+        execute_statements(&tx, version, &m.up)?;
+        for data in &m.data {
+            data.insert(&tx, version)?;
+        }
+        if m.foreign_key_check {
+            validate_foreign_keys(&tx)?;
+        }
+        if let Some(assertions) = &m.assertions {
+            check_assertions(&tx, version, assertions)?;
+        }
+        tx.commit()?;
+
+        Ok(())
+    }
+
+    fn replay_down(&self, conn: &mut Connection, version: usize, m: &M) -> Result<()> {
+        let tx = conn.transaction()?;
+        if self.defer_foreign_keys || m.defer_foreign_keys {
+            tx.pragma_update(None, "defer_foreign_keys", "ON")?;
+        }
+        execute_statements(&tx, version, m.down.as_deref().unwrap_or_default())?;
+        tx.commit()?;
+
+        Ok(())
+    }
+
+    /// Record migrations applied by an external executor: the entries are
+    /// added to the history and the database version moves to the highest
+    /// one. Entries must follow the current version without skipping a
+    /// migration, and their checksums, when set, must match the migration set.
+    pub fn import_state(&self, conn: &mut Connection, entries: &[HistoryEntry]) -> Result<()> {
+        let tx = self.begin(conn)?;
+        history::ensure_table(&tx)?;
+
+        let mut version = self.version_storage.get(&tx)?;
+        let mut entries = entries.to_vec();
+        entries.sort_by_key(|e| e.version);
+
+        for mut entry in entries {
+            let m = self.get(entry.version).ok_or(Error::InvalidImport(format!(
+                "imported migration {} is not defined",
+                entry.version
+            )))?;
+            if self.version_after(version, 1) != Some(entry.version) {
+                return Err(Error::InvalidImport(format!(
+                    "imported migration {} does not follow the current version {}",
+                    entry.version, version
+                )));
+            }
+            if !entry.checksum.is_empty() && !m.checksum_matches(&entry.checksum) {
+                return Err(Error::InvalidImport(format!(
+                    "imported migration {} checksum does not match its definition",
+                    entry.version
+                )));
+            }
+
+            if entry.applied_at.is_empty() {
+                entry.applied_at = history::now();
+            }
+            history::record(&tx, &entry)?;
+            version = entry.version;
+        }
+
+        self.version_storage.set(&tx, version)?;
+        tx.commit()?;
+        info!("Database state imported, version {}", version);
+
+        Ok(())
     }
 }
 
-// Set user version field from the SQLite db
-fn set_user_version(conn: &Connection, v: usize) -> Result<()> {
-    trace!("set user version to: {}", v);
-    // We can’t fix this without breaking API compatibility
-    #[allow(clippy::cast_possible_truncation)]
-    let v = v as u32;
-    conn.pragma_update(None, "user_version", v)
-        .context(anyhow::format_err!(
-            "query: 'PRAGMA user_version = {v}; -- Approximate query'",
-        ))
+// Version of the migration at `index` of a set: its id when it was loaded
+// from a directory, else its position
+fn version_at(index: usize, m: &M) -> usize {
+    m.version.map_or(index + 1, NonZeroUsize::get)
 }
 
-// Validate that no foreign keys are violated
-fn validate_foreign_keys(conn: &Connection) -> Result<()> {
-    let pragma_fk_check = "PRAGMA foreign_key_check";
-    conn.query_row(pragma_fk_check, [], |row| {
-        Ok(anyhow::format_err!(
-            "table: {:?}, rowid: {:?}, parent: {:?}, fkid: {:?}",
-            row.get::<usize, String>(0)?,
-            row.get::<usize, i64>(1)?,
-            row.get::<usize, String>(2)?,
-            row.get::<usize, i64>(3)?,
-        ))
+// Error of a transaction of a run, once the previous ones were `committed` at
+// a version
+fn after_commit(error: Error, committed: Option<usize>) -> Error {
+    match (error, committed) {
+        (error @ Error::PartiallyApplied { .. }, _) | (error, None) => error,
+        (error, Some(version)) => Error::PartiallyApplied {
+            version,
+            source: Box::new(error),
+        },
+    }
+}
+
+// Start a savepoint named after the migration `version`, so that it can be
+// rolled back alone
+fn savepoint(tx: &Transaction, version: usize) -> Result<String> {
+    let name = format!("migration_{version}");
+    tx.execute_batch(&format!("SAVEPOINT {name}"))?;
+    Ok(name)
+}
+
+// Run the `before.sql` or `after.sql` of a migration on the connection,
+// outside of any transaction
+fn run_outside_transaction(conn: &Connection, version: usize, sql: &Option<String>) -> Result<()> {
+    if let Some(sql) = sql {
+        execute_statements(conn, version, sql)?;
+    }
+
+    Ok(())
+}
+
+// Run the external commands of a migration, exposing the database path,
+// version and direction to them through environment variables
+fn run_commands(
+    conn: &Connection,
+    m: &M,
+    version: usize,
+    direction: Direction,
+    commands: &[String],
+) -> anyhow::Result<()> {
+    if commands.is_empty() {
+        return Ok(());
+    }
+
+    let envs = [
+        (
+            "MIGRATOR_DATABASE_PATH",
+            conn.path().unwrap_or_default().to_owned(),
+        ),
+        ("MIGRATOR_VERSION", version.to_string()),
+        ("MIGRATOR_NAME", m.comment.clone().unwrap_or_default()),
+        ("MIGRATOR_DIRECTION", direction.to_string()),
+    ];
+
+    for command in commands {
+        shell::run(command, &envs).context(anyhow::format_err!(
+            "migration {} {} command",
+            version,
+            direction
+        ))?;
+    }
+
+    Ok(())
+}
+
+// Run the statements of a migration one by one, reporting the failing one.
+// Returns the number of statements and of rows they changed.
+pub(crate) fn execute_statements(
+    conn: &Connection,
+    version: usize,
+    sql: &str,
+) -> Result<(usize, u64)> {
+    let total_changes = || -> rusqlite::Result<u64> {
+        conn.query_row("SELECT total_changes()", [], |row| row.get(0))
+    };
+    let changes_before = total_changes()?;
+
+    let statements = statements::split(sql);
+    for statement in &statements {
+        debug!("Running: {}", statement.sql);
+        conn.execute_batch(&statement.sql)
+            .map_err(|source| Error::SqlError {
+                version,
+                line: statement.line,
+                query: statement.sql.clone(),
+                source,
+            })?;
+    }
+
+    Ok((statements.len(), total_changes()? - changes_before))
+}
+
+// Check the assertion queries of a migration, failing on the first false one
+fn check_assertions(conn: &Connection, version: usize, queries: &str) -> Result<()> {
+    for query in statements::split(queries) {
+        let holds = is_true(conn, &query.sql).map_err(|source| Error::SqlError {
+            version,
+            line: query.line,
+            query: query.sql.clone(),
+            source,
+        })?;
+        if !holds {
+            return Err(Error::AssertionFailed {
+                version,
+                line: query.line,
+                query: query.sql,
+            });
+        }
+    }
+
+    Ok(())
+}
+
+// Evaluate an `only_if` guard query, false being 0, NULL or no row
+fn check_guard(conn: &Connection, guard: &str) -> anyhow::Result<bool> {
+    is_true(conn, guard).context(anyhow::format_err!("only_if guard: {}", guard))
+}
+
+// Evaluate a query returning a single value, false being 0, NULL or no row
+fn is_true(conn: &Connection, query: &str) -> rusqlite::Result<bool> {
+    let value = conn
+        .query_row(query, [], |row| row.get::<_, rusqlite::types::Value>(0))
+        .optional()?;
+
+    Ok(match value {
+        None | Some(rusqlite::types::Value::Null) => false,
+        Some(rusqlite::types::Value::Integer(i)) => i != 0,
+        Some(rusqlite::types::Value::Real(f)) => f != 0.0,
+        Some(_) => true,
     })
-    .optional()
-    .context(anyhow::format_err!("query: {}", pragma_fk_check))
-    .and_then(|o| match o {
-        Some(e) => Err(anyhow::format_err!("foreign key error: {}", e)),
-        None => Ok(()),
+}
+
+// Compare the schema left by a round-trip step with a copy of the expected
+// one
+fn check_round_trip(
+    version: usize,
+    step: &'static str,
+    expected: &Connection,
+    actual: &Connection,
+) -> Result<()> {
+    let differences = compare::schema_diff(expected, actual)?;
+    if differences.is_empty() {
+        return Ok(());
+    }
+
+    Err(Error::RoundTrip {
+        version,
+        step,
+        difference: differences
+            .iter()
+            .map(ToString::to_string)
+            .collect::<Vec<_>>()
+            .join("; "),
     })
 }
 
-// Read user version field from the SQLite db
-fn user_version(conn: &Connection) -> Result<usize, rusqlite::Error> {
-    // We can’t fix this without breaking API compatibility
-    #[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss)]
-    conn.query_row("PRAGMA user_version", [], |row| row.get(0))
-        .map(|v: i64| v as usize)
+// Validate that no foreign keys are violated
+fn validate_foreign_keys(conn: &Connection) -> Result<()> {
+    let violation = conn
+        .query_row("PRAGMA foreign_key_check", [], |row| {
+            Ok(Error::ForeignKeyViolation {
+                table: row.get(0)?,
+                rowid: row.get(1)?,
+                parent: row.get(2)?,
+                fkid: row.get(3)?,
+            })
+        })
+        .optional()?;
+
+    match violation {
+        Some(e) => Err(e),
+        None => Ok(()),
+    }
 }
@@ -0,0 +1,47 @@
+//! Detection of network filesystems, on which SQLite's file locking is
+//! unreliable and WAL mode doesn't work.
+use std::{fs, path::Path};
+
+// Filesystem types of /proc/mounts considered network filesystems
+const NETWORK_FS_TYPES: &[&str] = &[
+    "nfs",
+    "nfs4",
+    "cifs",
+    "smb3",
+    "smbfs",
+    "afs",
+    "9p",
+    "ceph",
+    "glusterfs",
+    "fuse.sshfs",
+];
+
+/// Filesystem type of the network filesystem holding `path`, or `None` when
+/// it is on a local filesystem or the filesystem can't be determined. Only
+/// implemented on Linux, where mounts are read from `/proc/mounts`.
+pub fn network_filesystem(path: &Path) -> Option<String> {
+    // the database file may not exist yet
+    let dir = match path.parent() {
+        _ if path.is_dir() => path,
+        Some(parent) if !parent.as_os_str().is_empty() => parent,
+        _ => Path::new("."),
+    };
+    let dir = fs::canonicalize(dir).ok()?;
+    let mounts = fs::read_to_string("/proc/mounts").ok()?;
+
+    // the mount point holding the path is the longest one prefixing it
+    let (_, fs_type) = mounts
+        .lines()
+        .filter_map(|line| {
+            let mut fields = line.split_whitespace();
+            let mount_point = fields.nth(1)?.replace("\\040", " ");
+            let fs_type = fields.next()?;
+            dir.starts_with(&mount_point)
+                .then(|| (mount_point.len(), fs_type.to_owned()))
+        })
+        .max_by_key(|(len, _)| *len)?;
+
+    NETWORK_FS_TYPES
+        .contains(&fs_type.as_str())
+        .then_some(fs_type)
+}
@@ -0,0 +1,93 @@
+//! Checks run before migrating, so that a read-only database, a full disk or
+//! edited migrations are reported before the first migration starts rather
+//! than halfway through a run.
+use std::path::Path;
+
+use anyhow::Result;
+use rusqlite::{Connection, DatabaseName};
+
+use crate::migration::Migrations;
+
+/// Free space required next to the database, as a multiple of its size: the
+/// rollback journal or WAL, and temporary copies made by some `ALTER TABLE`,
+/// can each grow up to the size of the database
+const REQUIRED_SPACE_FACTOR: u64 = 2;
+
+/// Check that the database can be migrated, failing with every problem found
+pub fn check(migrations: &Migrations, conn: &Connection) -> Result<()> {
+    let mut problems = Vec::new();
+
+    if conn.is_readonly(DatabaseName::Main)? {
+        problems.push(
+            "the database is opened read-only: check the permissions of the file, and don't pass --read-only"
+                .to_owned(),
+        );
+    }
+
+    // in-memory and temporary databases have no path
+    if let Some(path) = conn.path().filter(|p| !p.is_empty()).map(Path::new) {
+        let dir = match path.parent() {
+            Some(parent) if !parent.as_os_str().is_empty() => parent,
+            _ => Path::new("."),
+        };
+        if dir.metadata().is_ok_and(|m| m.permissions().readonly()) {
+            problems.push(format!(
+                "the directory {} is not writable, SQLite can't create its journal files there",
+                dir.display()
+            ));
+        }
+
+        let size = path.metadata().map(|m| m.len()).unwrap_or_default();
+        let required = size * REQUIRED_SPACE_FACTOR;
+        match available_space(dir) {
+            Some(available) if available < required => problems.push(format!(
+                "only {available} bytes are free on the disk of {}, {required} bytes are needed for the journal and temporary copies of a {size} bytes database",
+                dir.display()
+            )),
+            _ => {}
+        }
+    }
+
+    let discrepancies = migrations.verify(conn)?;
+    if !discrepancies.is_empty() {
+        problems.push(format!(
+            "applied migrations don't match the migration directory, restore them or run `migrator repair` if the changes are intended:\n{}",
+            discrepancies
+                .iter()
+                .map(|d| format!("    {d}"))
+                .collect::<Vec<_>>()
+                .join("\n")
+        ));
+    }
+
+    if !problems.is_empty() {
+        anyhow::bail!("Preflight checks failed:\n  - {}", problems.join("\n  - "));
+    }
+
+    Ok(())
+}
+
+// Bytes available to unprivileged users on the filesystem holding `dir`
+#[cfg(unix)]
+fn available_space(dir: &Path) -> Option<u64> {
+    use std::{ffi::CString, mem::MaybeUninit, os::unix::ffi::OsStrExt};
+
+    let path = CString::new(dir.as_os_str().as_bytes()).ok()?;
+    let mut stat = MaybeUninit::<libc::statvfs>::uninit();
+    // SAFETY: `path` is a valid C string, and `stat` is only read after
+    // statvfs succeeded in filling it
+    let stat = unsafe {
+        if libc::statvfs(path.as_ptr(), stat.as_mut_ptr()) != 0 {
+            return None;
+        }
+        stat.assume_init()
+    };
+
+    #[allow(clippy::useless_conversion)]
+    Some(u64::from(stat.f_bavail) * u64::from(stat.f_frsize))
+}
+
+#[cfg(not(unix))]
+fn available_space(_dir: &Path) -> Option<u64> {
+    None
+}
@@ -0,0 +1,60 @@
+//! Repeatable migrations, Flyway's `R__<name>.sql` scripts rerun after the
+//! versioned migrations whenever their SQL changes, e.g. to recreate views.
+use anyhow::{Context, Result};
+use rusqlite::{Connection, OptionalExtension};
+
+use crate::{checksum, history};
+
+pub const REPEATABLE_TABLE: &str = "_migrator_repeatable";
+
+/// Script rerun whenever its SQL changes
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Repeatable {
+    /// File name of the script, identifying it in the repeatable table
+    pub name: String,
+    pub sql: String,
+}
+
+fn ensure_table(conn: &Connection) -> Result<()> {
+    conn.execute_batch(&format!(
+        "CREATE TABLE IF NOT EXISTS {REPEATABLE_TABLE} (
+            name TEXT PRIMARY KEY,
+            checksum TEXT NOT NULL,
+            applied_at TEXT NOT NULL
+        )"
+    ))
+    .context("Could not create the repeatable migration table")
+}
+
+/// Whether the script changed since it last ran, or never ran
+pub fn is_changed(conn: &Connection, repeatable: &Repeatable) -> Result<bool> {
+    ensure_table(conn)?;
+    let stored: Option<String> = conn
+        .query_row(
+            &format!("SELECT checksum FROM {REPEATABLE_TABLE} WHERE name = ?1"),
+            [&repeatable.name],
+            |row| row.get(0),
+        )
+        .optional()?;
+
+    Ok(stored.is_none_or(|stored| !checksum::matches(&stored, &repeatable.sql)))
+}
+
+/// Record that the script ran with its current SQL
+pub fn record(conn: &Connection, repeatable: &Repeatable) -> Result<()> {
+    ensure_table(conn)?;
+    conn.execute(
+        &format!(
+            "INSERT OR REPLACE INTO {REPEATABLE_TABLE} (name, checksum, applied_at)
+             VALUES (?1, ?2, ?3)"
+        ),
+        [
+            repeatable.name.clone(),
+            checksum::checksum(&repeatable.sql),
+            history::now(),
+        ],
+    )
+    .context("Could not record repeatable migration")?;
+
+    Ok(())
+}
@@ -0,0 +1,181 @@
+//! Schema of a database, without SQLite's and the migrator's internal tables.
+use anyhow::Result;
+use rusqlite::Connection;
+
+use crate::checksum;
+
+/// `CREATE` statements of the tables, indexes, views and triggers of the
+/// database, in the order they were created
+pub fn dump(conn: &Connection) -> Result<Vec<String>> {
+    let mut stmt = conn.prepare(
+        "SELECT sql FROM sqlite_master
+         WHERE sql IS NOT NULL AND name NOT LIKE 'sqlite_%' AND tbl_name NOT LIKE '\\_migrator\\_%' ESCAPE '\\'
+         ORDER BY rowid",
+    )?;
+    let statements = stmt
+        .query_map([], |row| row.get(0))?
+        .collect::<Result<Vec<_>, _>>()?;
+    Ok(statements)
+}
+
+/// Normalized fingerprint of the schema, one `<type> <name>: <sql>` line per
+/// object sorted by type and name. The SQL has its whitespace normalized, so
+/// that only changes to the schema itself, and not to the formatting of the
+/// statements or the order they ran in, change the snapshot.
+pub fn snapshot(conn: &Connection) -> Result<String> {
+    let mut stmt = conn.prepare(
+        "SELECT type, name, sql FROM sqlite_master
+         WHERE sql IS NOT NULL AND name NOT LIKE 'sqlite_%' AND tbl_name NOT LIKE '\\_migrator\\_%' ESCAPE '\\'
+         ORDER BY type, name",
+    )?;
+    let lines = stmt
+        .query_map([], |row| {
+            Ok(format!(
+                "{} {}: {}",
+                row.get::<_, String>(0)?,
+                row.get::<_, String>(1)?,
+                normalize(&row.get::<_, String>(2)?)
+            ))
+        })?
+        .collect::<Result<Vec<_>, _>>()?;
+
+    Ok(lines.iter().map(|l| format!("{l}\n")).collect())
+}
+
+/// Checksum of the [`snapshot`] of the schema, recorded in the history table
+/// after each migration run to detect manual changes
+pub fn hash(conn: &Connection) -> Result<String> {
+    Ok(checksum::checksum(&snapshot(conn)?))
+}
+
+/// Collapse whitespace runs to a single space and drop the whitespace next to
+/// parentheses and commas, leaving quoted strings and identifiers untouched.
+pub fn normalize(sql: &str) -> String {
+    let mut normalized = String::with_capacity(sql.len());
+    let mut quote = None;
+    let mut pending_space = false;
+
+    for c in sql.trim().chars() {
+        if let Some(q) = quote {
+            normalized.push(c);
+            if c == q {
+                quote = None;
+            }
+            continue;
+        }
+
+        if c.is_whitespace() {
+            pending_space = true;
+            continue;
+        }
+        if pending_space && !matches!(c, ',' | '(' | ')') && !normalized.ends_with(['(', ',']) {
+            normalized.push(' ');
+        }
+        pending_space = false;
+
+        quote = match c {
+            '\'' | '"' | '`' => Some(c),
+            '[' => Some(']'),
+            _ => None,
+        };
+        normalized.push(c);
+    }
+
+    normalized
+}
+
+/// A table, as needed to draw the schema
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Table {
+    pub name: String,
+    pub columns: Vec<Column>,
+    pub foreign_keys: Vec<ForeignKey>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Column {
+    pub name: String,
+    /// Declared type, empty when the column has none
+    pub decl_type: String,
+    pub primary_key: bool,
+    pub not_null: bool,
+}
+
+/// A foreign key, with one column pair per column of composite keys
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ForeignKey {
+    pub table: String,
+    /// `(column, referenced column)` pairs. The referenced column is `None`
+    /// when the key references the primary key implicitly.
+    pub columns: Vec<(String, Option<String>)>,
+}
+
+/// Tables of the database with their columns and foreign keys, sorted by name
+pub fn tables(conn: &Connection) -> Result<Vec<Table>> {
+    let mut stmt = conn.prepare(
+        "SELECT name FROM sqlite_master
+         WHERE type = 'table' AND name NOT LIKE 'sqlite_%' AND name NOT LIKE '\\_migrator\\_%' ESCAPE '\\'
+         ORDER BY name",
+    )?;
+    let names = stmt
+        .query_map([], |row| row.get::<_, String>(0))?
+        .collect::<Result<Vec<_>, _>>()?;
+
+    names
+        .into_iter()
+        .map(|name| {
+            Ok(Table {
+                columns: columns(conn, &name)?,
+                foreign_keys: foreign_keys(conn, &name)?,
+                name,
+            })
+        })
+        .collect()
+}
+
+fn columns(conn: &Connection, table: &str) -> Result<Vec<Column>> {
+    let mut stmt = conn.prepare("SELECT name, type, \"notnull\", pk FROM pragma_table_info(?1)")?;
+    let columns = stmt
+        .query_map([table], |row| {
+            Ok(Column {
+                name: row.get(0)?,
+                decl_type: row.get(1)?,
+                not_null: row.get(2)?,
+                primary_key: row.get::<_, i64>(3)? > 0,
+            })
+        })?
+        .collect::<Result<Vec<_>, _>>()?;
+    Ok(columns)
+}
+
+fn foreign_keys(conn: &Connection, table: &str) -> Result<Vec<ForeignKey>> {
+    let mut stmt = conn.prepare(
+        "SELECT id, \"table\", \"from\", \"to\" FROM pragma_foreign_key_list(?1) ORDER BY id, seq",
+    )?;
+    let rows = stmt
+        .query_map([table], |row| {
+            Ok((
+                row.get::<_, i64>(0)?,
+                row.get::<_, String>(1)?,
+                row.get::<_, String>(2)?,
+                row.get::<_, Option<String>>(3)?,
+            ))
+        })?
+        .collect::<Result<Vec<_>, _>>()?;
+
+    let mut keys: Vec<(i64, ForeignKey)> = Vec::new();
+    for (id, parent, from, to) in rows {
+        match keys.last_mut() {
+            Some((last_id, key)) if *last_id == id => key.columns.push((from, to)),
+            _ => keys.push((
+                id,
+                ForeignKey {
+                    table: parent,
+                    columns: vec![(from, to)],
+                },
+            )),
+        }
+    }
+
+    Ok(keys.into_iter().map(|(_, key)| key).collect())
+}
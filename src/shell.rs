@@ -0,0 +1,31 @@
+//! Execution of external commands configured for migrations.
+use std::process::Command;
+
+use anyhow::{Context, Result};
+use tracing::debug;
+
+/// Run `command` through the platform shell with the extra `envs` set, failing
+/// if it exits unsuccessfully.
+pub fn run(command: &str, envs: &[(&str, String)]) -> Result<()> {
+    let mut cmd = if cfg!(windows) {
+        let mut cmd = Command::new("cmd");
+        cmd.args(["/C", command]);
+        cmd
+    } else {
+        let mut cmd = Command::new("sh");
+        cmd.args(["-c", command]);
+        cmd
+    };
+
+    debug!("Running command: {}", command);
+    let status = cmd
+        .envs(envs.iter().map(|(k, v)| (k, v)))
+        .status()
+        .context(anyhow::format_err!("Could not spawn command `{}`", command))?;
+
+    if !status.success() {
+        anyhow::bail!("command `{}` failed with {}", command, status);
+    }
+
+    Ok(())
+}
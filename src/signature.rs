@@ -0,0 +1,91 @@
+//! Ed25519 signatures over a migration directory, so that regulated
+//! environments only apply approved migrations.
+//!
+//! The signature covers the path and raw bytes of every file in the
+//! directory, see [`digest`]: adding, removing, renaming or editing a
+//! migration, its metadata, data files or hooks invalidates it.
+use std::{fs, path::Path};
+
+use anyhow::{Context, Result};
+use ed25519_dalek::{Signature, Signer, SigningKey, Verifier, VerifyingKey};
+use sha2::{Digest, Sha256};
+
+/// File of the signature in the migration directory, hidden so that the
+/// loader skips it
+pub const SIGNATURE_FILE: &str = ".signature";
+
+/// SHA-256 of the files of the migration directory `dir`, in path order.
+///
+/// The bytes are hashed as they are on disk, before any variable
+/// substitution, so the same directory signs the same in every environment.
+/// Hidden entries, which the loader ignores, are left out along with the
+/// signature file itself.
+pub fn digest(dir: &Path) -> Result<Vec<u8>> {
+    let mut files = Vec::new();
+    collect_files(dir, "", &mut files)?;
+    files.sort();
+
+    let mut hasher = Sha256::new();
+    for relative in files {
+        let path = dir.join(&relative);
+        let content = fs::read(&path).context(format!("Could not read {}", path.display()))?;
+        for part in [relative.as_bytes(), &content] {
+            // length prefixes keep the boundaries between parts unambiguous
+            hasher.update((part.len() as u64).to_be_bytes());
+            hasher.update(part);
+        }
+    }
+
+    Ok(hasher.finalize().to_vec())
+}
+
+// `/` separated paths relative to the migration directory, so that the
+// digest doesn't depend on the platform
+fn collect_files(dir: &Path, prefix: &str, files: &mut Vec<String>) -> Result<()> {
+    let entries = fs::read_dir(dir).context(format!("Could not read {}", dir.display()))?;
+    for entry in entries {
+        let entry = entry?;
+        let name = entry.file_name().to_string_lossy().into_owned();
+        if name.starts_with('.') {
+            continue;
+        }
+
+        let relative = format!("{prefix}{name}");
+        if entry.path().is_dir() {
+            collect_files(&entry.path(), &format!("{relative}/"), files)?;
+        } else {
+            files.push(relative);
+        }
+    }
+
+    Ok(())
+}
+
+/// Hex encoded signature of the migration directory `dir`
+pub fn sign(dir: &Path, key: &SigningKey) -> Result<String> {
+    Ok(key
+        .sign(&digest(dir)?)
+        .to_bytes()
+        .iter()
+        .map(|b| format!("{b:02x}"))
+        .collect())
+}
+
+/// Check a hex encoded signature of the migration directory `dir` made with
+/// the private key of `key`
+pub fn verify(dir: &Path, signature: &str, key: &VerifyingKey) -> Result<()> {
+    let signature = signature.trim();
+    let bytes = (0..signature.len())
+        .step_by(2)
+        .map(|i| {
+            signature
+                .get(i..i + 2)
+                .and_then(|byte| u8::from_str_radix(byte, 16).ok())
+        })
+        .collect::<Option<Vec<_>>>()
+        .context("The signature is not hex encoded")?;
+    let signature = Signature::from_slice(&bytes).context("Invalid signature")?;
+
+    key.verify(&digest(dir)?, &signature)
+        .context("The signature doesn't match the migrations, they were changed after signing or signed with another key")
+}
@@ -0,0 +1,231 @@
+//! Splitting of migration SQL into single statements, so they can be run and
+//! reported on one at a time.
+use std::ffi::CString;
+
+/// Line separating statements explicitly. When a migration contains it, the
+/// SQL is split on these lines only.
+pub const STATEMENT_BREAK: &str = "-- migrator:statement-break";
+
+/// A statement of a migration and the line it starts on, counting from 1.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Statement {
+    pub sql: String,
+    pub line: usize,
+}
+
+/// Split `sql` into statements, on the explicit statement breaks if there are
+/// any, or else on the semicolons ending complete statements. Semicolons in
+/// literals, comments and `BEGIN ... END` trigger bodies don't split.
+pub fn split(sql: &str) -> Vec<Statement> {
+    if sql.lines().any(|l| l.trim() == STATEMENT_BREAK) {
+        split_on_breaks(sql)
+    } else {
+        split_on_semicolons(sql)
+    }
+}
+
+/// Whether a statement destroys data: `DROP` statements, `ALTER TABLE ...
+/// DROP COLUMN` and `DELETE` without a `WHERE` clause.
+pub fn is_destructive(statement: &str) -> bool {
+    let code = strip_comments(statement).to_uppercase();
+    let words = code
+        .split(|c: char| c.is_whitespace() || c == ';')
+        .filter(|w| !w.is_empty())
+        .collect::<Vec<_>>();
+
+    match words.first() {
+        Some(&"DROP") => true,
+        Some(&"ALTER") => words.contains(&"DROP"),
+        Some(&"DELETE") => !words.contains(&"WHERE"),
+        _ => false,
+    }
+}
+
+fn split_on_breaks(sql: &str) -> Vec<Statement> {
+    let mut statements = Vec::new();
+    let mut current = String::new();
+    let mut start = 1;
+
+    for (i, line) in sql.lines().enumerate() {
+        if line.trim() == STATEMENT_BREAK {
+            push(&mut statements, &current, start);
+            current.clear();
+            start = i + 2;
+        } else {
+            current.push_str(line);
+            current.push('\n');
+        }
+    }
+    push(&mut statements, &current, start);
+
+    statements
+}
+
+fn split_on_semicolons(sql: &str) -> Vec<Statement> {
+    let mut statements = Vec::new();
+    let mut start = 0;
+
+    for (end, _) in sql.match_indices(';') {
+        let candidate = &sql[start..=end];
+        if is_complete(candidate) {
+            push(&mut statements, candidate, line_of(sql, start));
+            start = end + 1;
+        }
+    }
+    push(&mut statements, &sql[start..], line_of(sql, start));
+
+    statements
+}
+
+// Add the statement unless it's blank or only comments, starting its line
+// count at its first non-blank line
+fn push(statements: &mut Vec<Statement>, sql: &str, line: usize) {
+    let trimmed = sql.trim_start();
+    let code = strip_comments(trimmed);
+    if code.trim().is_empty() || code.trim() == ";" {
+        return;
+    }
+
+    let skipped_lines = sql[..sql.len() - trimmed.len()].matches('\n').count();
+    statements.push(Statement {
+        sql: trimmed.trim_end().to_owned(),
+        line: line + skipped_lines,
+    });
+}
+
+// `sql` with its `--` and `/* */` comments replaced by spaces, leaving the
+// comment markers of quoted literals and identifiers alone
+fn strip_comments(sql: &str) -> String {
+    let mut code = String::with_capacity(sql.len());
+    let mut chars = sql.chars().peekable();
+
+    while let Some(c) = chars.next() {
+        match c {
+            '\'' | '"' | '`' | '[' => {
+                let close = if c == '[' { ']' } else { c };
+                code.push(c);
+                // a doubled quote inside the literal is read as closing and
+                // reopening it
+                for c in chars.by_ref() {
+                    code.push(c);
+                    if c == close {
+                        break;
+                    }
+                }
+            }
+            '-' if chars.peek() == Some(&'-') => {
+                for c in chars.by_ref() {
+                    if c == '\n' {
+                        code.push('\n');
+                        break;
+                    }
+                }
+            }
+            '/' if chars.peek() == Some(&'*') => {
+                chars.next();
+                let mut previous = ' ';
+                for c in chars.by_ref() {
+                    if previous == '*' && c == '/' {
+                        break;
+                    }
+                    previous = c;
+                }
+                code.push(' ');
+            }
+            c => code.push(c),
+        }
+    }
+
+    code
+}
+
+fn line_of(sql: &str, offset: usize) -> usize {
+    sql[..offset].matches('\n').count() + 1
+}
+
+// SQLite's own check that the text ends with a complete statement, aware of
+// literals, comments and trigger bodies
+fn is_complete(sql: &str) -> bool {
+    let Ok(sql) = CString::new(sql) else {
+        return false;
+    };
+
+    // SAFETY: `sql` is a valid NUL-terminated string for the whole call
+    unsafe { rusqlite::ffi::sqlite3_complete(sql.as_ptr()) != 0 }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sqls(sql: &str) -> Vec<(String, usize)> {
+        split(sql).into_iter().map(|s| (s.sql, s.line)).collect()
+    }
+
+    #[test]
+    fn trigger_bodies_are_not_split() {
+        let sql = "CREATE TABLE t(a);
+CREATE TRIGGER tr AFTER INSERT ON t BEGIN
+  UPDATE t SET a = 1;
+  DELETE FROM t WHERE a = 2;
+END;
+SELECT 1;";
+
+        let statements = sqls(sql);
+        assert_eq!(statements.len(), 3);
+        assert_eq!(statements[1].1, 2);
+        assert!(statements[1].0.ends_with("END;"));
+        assert_eq!(statements[2], ("SELECT 1;".to_owned(), 6));
+    }
+
+    #[test]
+    fn semicolons_in_literals_are_not_split() {
+        assert_eq!(
+            sqls("INSERT INTO t VALUES (';', \"a;b\");\nSELECT 1;"),
+            [
+                ("INSERT INTO t VALUES (';', \"a;b\");".to_owned(), 1),
+                ("SELECT 1;".to_owned(), 2),
+            ]
+        );
+    }
+
+    #[test]
+    fn comment_only_chunks_are_dropped() {
+        assert_eq!(
+            sqls("SELECT 1;\n-- trailing; comment\n/* block; comment */\n"),
+            [("SELECT 1;".to_owned(), 1)]
+        );
+        assert!(sqls("-- only a comment\n").is_empty());
+    }
+
+    #[test]
+    fn explicit_breaks_take_over() {
+        let sql = "SELECT 1;\nSELECT 2;\n-- migrator:statement-break\n\nSELECT 3;\n";
+        assert_eq!(
+            sqls(sql),
+            [
+                ("SELECT 1;\nSELECT 2;".to_owned(), 1),
+                ("SELECT 3;".to_owned(), 5),
+            ]
+        );
+    }
+
+    #[test]
+    fn destructive_statements() {
+        assert!(is_destructive("DROP TABLE t;"));
+        assert!(is_destructive("ALTER TABLE t DROP COLUMN a;"));
+        assert!(is_destructive("DELETE FROM t;"));
+        assert!(is_destructive("DELETE FROM t -- WHERE a = 1\n;"));
+        assert!(!is_destructive("DELETE FROM t WHERE a = 1;"));
+        assert!(!is_destructive("ALTER TABLE t ADD COLUMN b;"));
+        assert!(!is_destructive("-- DROP TABLE t\nSELECT 1;"));
+        assert!(!is_destructive("UPDATE t SET a = '/* DROP */ --';"));
+    }
+
+    #[test]
+    fn block_comments_dont_hide_destructive_statements() {
+        assert!(is_destructive("/* cleanup */ DROP TABLE t;"));
+        assert!(is_destructive("/* multi\nline */\nDELETE FROM t;"));
+        assert!(is_destructive("-- note\n/* a */ /* b */ DROP INDEX i;"));
+    }
+}
@@ -0,0 +1,91 @@
+//! Registry of tenant databases migrated together with `up --all-tenants`,
+//! stored as a JSON file.
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
+
+use anyhow::{bail, Context, Result};
+
+use crate::history;
+
+/// Registry file used when none is configured
+pub const DEFAULT_REGISTRY: &str = "migrator-tenants.json";
+
+/// A tenant and its database
+#[derive(Debug, Clone, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+pub struct Tenant {
+    pub name: String,
+    pub database: PathBuf,
+    /// Deactivated tenants are kept in the registry but no longer migrated
+    #[serde(default = "default_active")]
+    pub active: bool,
+    /// RFC 3339 timestamp
+    #[serde(default)]
+    pub added_at: String,
+}
+
+fn default_active() -> bool {
+    true
+}
+
+#[derive(Debug, Clone, Default, serde::Serialize, serde::Deserialize)]
+pub struct Registry {
+    pub tenants: Vec<Tenant>,
+}
+
+impl Registry {
+    /// Read the registry at `path`, empty if the file doesn't exist yet.
+    pub fn load(path: &Path) -> Result<Self> {
+        if !path.exists() {
+            return Ok(Self::default());
+        }
+
+        let content = fs::read_to_string(path)
+            .context(format!("Failed to read tenant registry {}", path.display()))?;
+        serde_json::from_str(&content)
+            .context(format!("Invalid tenant registry {}", path.display()))
+    }
+
+    pub fn save(&self, path: &Path) -> Result<()> {
+        fs::write(path, serde_json::to_string_pretty(self)? + "\n").context(format!(
+            "Failed to write tenant registry {}",
+            path.display()
+        ))
+    }
+
+    /// Register a tenant, or reactivate it if it was deactivated.
+    pub fn add(&mut self, name: &str, database: &Path) -> Result<()> {
+        if let Some(tenant) = self.tenants.iter_mut().find(|t| t.name == name) {
+            if tenant.active {
+                bail!("Tenant {name} is already registered");
+            }
+            tenant.active = true;
+            tenant.database = database.to_owned();
+            return Ok(());
+        }
+
+        self.tenants.push(Tenant {
+            name: name.to_owned(),
+            database: database.to_owned(),
+            active: true,
+            added_at: history::now(),
+        });
+        Ok(())
+    }
+
+    pub fn deactivate(&mut self, name: &str) -> Result<()> {
+        match self.tenants.iter_mut().find(|t| t.name == name) {
+            Some(tenant) if tenant.active => {
+                tenant.active = false;
+                Ok(())
+            }
+            Some(_) => bail!("Tenant {name} is already deactivated"),
+            None => bail!("Unknown tenant {name}"),
+        }
+    }
+
+    pub fn active(&self) -> impl Iterator<Item = &Tenant> {
+        self.tenants.iter().filter(|t| t.active)
+    }
+}
@@ -0,0 +1,48 @@
+//! Helpers for application test suites embedding the migrator.
+use std::{fs, path::Path};
+
+use anyhow::{Context, Result};
+use rusqlite::Connection;
+
+use crate::migration::Migrations;
+
+impl Migrations {
+    /// Open a fresh in-memory database with every migration applied.
+    pub fn open_migrated_in_memory(&self) -> Result<Connection> {
+        let mut conn = Connection::open_in_memory()?;
+        conn.pragma_update(None, "foreign_keys", "ON")?;
+        self.to_latest(&mut conn)?;
+
+        Ok(conn)
+    }
+}
+
+/// Load the migrations from `dir` and apply them to a fresh in-memory database.
+#[cfg(feature = "loader")]
+pub fn open_migrated_in_memory(dir: &Path) -> Result<Connection> {
+    Migrations::from_directory(dir)?.open_migrated_in_memory()
+}
+
+/// Execute a single SQL fixture file against `conn`.
+pub fn load_fixture(conn: &Connection, path: &Path) -> Result<()> {
+    let sql = fs::read_to_string(path)
+        .with_context(|| format!("Failed to read fixture {}", path.display()))?;
+
+    conn.execute_batch(&sql)
+        .with_context(|| format!("Failed to load fixture {}", path.display()))
+}
+
+/// Execute every `.sql` file in `dir` against `conn`, in file name order.
+pub fn load_fixtures(conn: &Connection, dir: &Path) -> Result<()> {
+    let mut entries = fs::read_dir(dir)?.collect::<Result<Vec<_>, std::io::Error>>()?;
+    entries.sort_by_key(|e| e.file_name());
+
+    for entry in entries {
+        let path = entry.path();
+        if path.extension().is_some_and(|ext| ext == "sql") {
+            load_fixture(conn, &path)?;
+        }
+    }
+
+    Ok(())
+}
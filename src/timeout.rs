@@ -0,0 +1,54 @@
+//! Watchdog interrupting a migration which runs longer than its timeout, so
+//! that an accidental full-table rewrite can't hang a deploy forever.
+use std::{
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        mpsc, Arc,
+    },
+    thread::{self, JoinHandle},
+    time::Duration,
+};
+
+use rusqlite::Connection;
+use tracing::warn;
+
+pub struct Watchdog {
+    stop: mpsc::Sender<()>,
+    fired: Arc<AtomicBool>,
+    thread: JoinHandle<()>,
+}
+
+impl Watchdog {
+    /// Interrupt the statement running on `conn` once `timeout` elapsed,
+    /// unless the watchdog is stopped before
+    pub fn start(conn: &Connection, timeout: Duration) -> Self {
+        let (stop, stopped) = mpsc::channel();
+        let fired = Arc::new(AtomicBool::new(false));
+        let handle = conn.get_interrupt_handle();
+
+        let thread = thread::spawn({
+            let fired = fired.clone();
+            move || {
+                if let Err(mpsc::RecvTimeoutError::Timeout) = stopped.recv_timeout(timeout) {
+                    warn!("Migration exceeded its timeout of {timeout:?}, interrupting it");
+                    fired.store(true, Ordering::SeqCst);
+                    handle.interrupt();
+                }
+            }
+        });
+
+        Self {
+            stop,
+            fired,
+            thread,
+        }
+    }
+
+    /// Stop the watchdog, returning whether it interrupted the migration
+    pub fn stop(self) -> bool {
+        // the thread also stops on a closed channel, so a send error is fine
+        let _ = self.stop.send(());
+        let _ = self.thread.join();
+        self.fired.load(Ordering::SeqCst)
+    }
+}
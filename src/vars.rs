@@ -0,0 +1,49 @@
+//! `${VAR}` placeholder substitution in migration SQL.
+use std::collections::HashMap;
+
+use anyhow::Result;
+
+/// Replace every `${NAME}` in `sql` with the value of `NAME` in `vars`, or in
+/// the environment when not found there. `${NAME:-default}` falls back to
+/// `default` when the variable is undefined or empty, as in shells.
+/// `$${NAME}` is left as the literal `${NAME}`. Fails on undefined variables
+/// and unterminated placeholders.
+pub fn expand(sql: &str, vars: &HashMap<String, String>) -> Result<String> {
+    let mut expanded = String::with_capacity(sql.len());
+    let mut rest = sql;
+
+    while let Some(start) = rest.find("${") {
+        if rest[..start].ends_with('$') {
+            // escaped placeholder, drop one `$` and keep the rest as is
+            expanded.push_str(&rest[..start - 1]);
+            expanded.push_str("${");
+            rest = &rest[start + 2..];
+            continue;
+        }
+
+        expanded.push_str(&rest[..start]);
+        let Some(len) = rest[start + 2..].find('}') else {
+            anyhow::bail!("Unterminated placeholder {:?}", &rest[start..]);
+        };
+        let placeholder = &rest[start + 2..start + 2 + len];
+        let (name, default) = match placeholder.split_once(":-") {
+            Some((name, default)) => (name, Some(default)),
+            None => (placeholder, None),
+        };
+
+        let value = match (
+            vars.get(name).cloned().or_else(|| std::env::var(name).ok()),
+            default,
+        ) {
+            (Some(value), Some(default)) if value.is_empty() => default.to_owned(),
+            (Some(value), _) => value,
+            (None, Some(default)) => default.to_owned(),
+            (None, None) => anyhow::bail!("Undefined variable {name:?}"),
+        };
+        expanded.push_str(&value);
+        rest = &rest[start + 2 + len + 1..];
+    }
+    expanded.push_str(rest);
+
+    Ok(expanded)
+}
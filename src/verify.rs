@@ -0,0 +1,124 @@
+//! Comparison of the migrations recorded in the history table with the
+//! migration set.
+use std::fmt;
+
+use anyhow::Result;
+use rusqlite::Connection;
+
+use crate::{history, migration::Migrations};
+
+/// A difference between an applied migration and its definition
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Discrepancy {
+    /// The migration changed after it was applied
+    Changed { version: usize, name: String },
+    /// The applied migration is not part of the migration set anymore
+    Missing { version: usize, name: String },
+    /// The migration precedes the current version but was never recorded as
+    /// applied
+    NotApplied { version: usize, name: String },
+}
+
+impl fmt::Display for Discrepancy {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Discrepancy::Changed { version, name } => {
+                write!(f, "{version:04} {name}: changed since it was applied")
+            }
+            Discrepancy::Missing { version, name } => {
+                write!(f, "{version:04} {name}: applied but missing from disk")
+            }
+            Discrepancy::NotApplied { version, name } => {
+                write!(
+                    f,
+                    "{version:04} {name}: precedes the current version but was never applied"
+                )
+            }
+        }
+    }
+}
+
+impl Migrations {
+    /// Compare the checksums stored in the history table with the migration
+    /// set, returning every discrepancy found. Entries recorded before a
+    /// baseline replaced their migrations are not compared.
+    pub fn verify(&self, conn: &Connection) -> Result<Vec<Discrepancy>> {
+        let entries = history::entries(conn)?;
+        let current: usize = self.current_version(conn)?.into();
+        let baseline = self.baseline_version();
+
+        let mut discrepancies = Vec::new();
+        for entry in &entries {
+            let m = self
+                .get(entry.version)
+                .filter(|m| m.get_comment().unwrap_or_default() == entry.name);
+
+            match m {
+                // recorded before the baseline replaced the migration
+                None if entry.version <= baseline => {}
+                None => discrepancies.push(Discrepancy::Missing {
+                    version: entry.version,
+                    name: entry.name.clone(),
+                }),
+                Some(m) if !entry.checksum.is_empty() && !m.checksum_matches(&entry.checksum) => {
+                    discrepancies.push(Discrepancy::Changed {
+                        version: entry.version,
+                        name: entry.name.clone(),
+                    })
+                }
+                Some(_) => {}
+            }
+        }
+
+        for (version, m) in self.iter().take_while(|(v, _)| *v <= current) {
+            if !entries.iter().any(|e| e.version == version) {
+                discrepancies.push(Discrepancy::NotApplied {
+                    version,
+                    name: m.get_comment().unwrap_or_default().to_owned(),
+                });
+            }
+        }
+
+        Ok(discrepancies)
+    }
+
+    /// Update the names and checksums stored in the history table to match
+    /// the migration set, returning the entries that were out of date.
+    /// Entries whose version is not part of the set anymore, or recorded
+    /// before a baseline replaced them, are left as is.
+    pub fn repair(&self, conn: &mut Connection) -> Result<Vec<Discrepancy>> {
+        let tx = self.begin(conn)?;
+
+        let baseline = self.baseline_version();
+        let mut repaired = Vec::new();
+        for entry in history::entries(&tx)? {
+            let Some(m) = self.get(entry.version) else {
+                continue;
+            };
+            let name = m.get_comment().unwrap_or_default();
+            if entry.version <= baseline && entry.name != name {
+                continue;
+            }
+            let checksum = m.checksum();
+
+            if entry.name != name {
+                repaired.push(Discrepancy::Missing {
+                    version: entry.version,
+                    name: entry.name,
+                });
+            } else if !m.checksum_matches(&entry.checksum) {
+                repaired.push(Discrepancy::Changed {
+                    version: entry.version,
+                    name: entry.name,
+                });
+            } else {
+                continue;
+            }
+
+            history::update(&tx, entry.version, name, &checksum)?;
+        }
+
+        tx.commit()?;
+        Ok(repaired)
+    }
+}
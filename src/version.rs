@@ -0,0 +1,142 @@
+//! Access to the schema version, stored in SQLite's `user_version` header
+//! field, without a `Migrations` set.
+//!
+//! `user_version` only holds 32-bit values. Migration sets needing larger
+//! versions store them in a table instead, see [`Storage`].
+use anyhow::{bail, Context, Result};
+use rusqlite::{Connection, OptionalExtension};
+use tracing::trace;
+
+pub const VERSION_TABLE: &str = "_migrator_version";
+
+/// Where the schema version of a database is stored
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, serde::Deserialize, serde::Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Storage {
+    /// SQLite's `user_version` header field, up to `i32::MAX`
+    #[default]
+    UserVersion,
+    /// Single row of the `_migrator_version` table, up to `i64::MAX`. Until
+    /// the table is created, the version is read from `user_version` so that
+    /// existing databases can switch to it.
+    Table,
+}
+
+impl Storage {
+    /// Read the schema version of the database
+    pub fn get(self, conn: &Connection) -> Result<usize> {
+        match self {
+            Storage::UserVersion => user_version(conn),
+            Storage::Table => match table_version(conn)? {
+                Some(v) => Ok(v),
+                None => user_version(conn),
+            },
+        }
+    }
+
+    /// Set the schema version of the database, failing for versions this
+    /// storage can't hold instead of truncating them
+    pub fn set(self, conn: &Connection, v: usize) -> Result<()> {
+        match self {
+            Storage::UserVersion => set_user_version(conn, v),
+            Storage::Table => set_table_version(conn, v),
+        }
+    }
+
+    /// SQL setting the schema version to `v`, for external executors
+    pub fn set_sql(self, v: usize) -> String {
+        match self {
+            Storage::UserVersion => format!("PRAGMA user_version = {v};"),
+            Storage::Table => format!(
+                "{}; INSERT OR REPLACE INTO {VERSION_TABLE} (id, version) VALUES (0, {v});",
+                create_table_sql()
+            ),
+        }
+    }
+}
+
+/// Read the schema version of the database.
+///
+/// `user_version` is a signed 32-bit integer; negative values, which the
+/// migrator never writes, are reported as errors rather than wrapped.
+pub fn user_version(conn: &Connection) -> Result<usize> {
+    let v: i32 = conn
+        .query_row("PRAGMA user_version", [], |row| row.get(0))
+        .context("query: PRAGMA user_version")?;
+
+    usize::try_from(v).context(format!("Invalid negative user_version {v}"))
+}
+
+/// Set the schema version of the database.
+///
+/// Fails for versions not fitting in `user_version`, instead of truncating
+/// them.
+pub fn set_user_version(conn: &Connection, v: usize) -> Result<()> {
+    trace!("set user version to: {}", v);
+    let Ok(value) = i32::try_from(v) else {
+        bail!(
+            "Version {v} is too large for user_version (maximum {})",
+            i32::MAX
+        );
+    };
+
+    conn.pragma_update(None, "user_version", value)
+        .context(anyhow::format_err!("query: PRAGMA user_version = {v}"))
+}
+
+fn create_table_sql() -> String {
+    format!(
+        "CREATE TABLE IF NOT EXISTS {VERSION_TABLE} (id INTEGER PRIMARY KEY CHECK (id = 0), version INTEGER NOT NULL)"
+    )
+}
+
+// Version stored in the version table, `None` when the table doesn't exist
+fn table_version(conn: &Connection) -> Result<Option<usize>> {
+    let exists = conn
+        .query_row(
+            "SELECT 1 FROM sqlite_master WHERE type = 'table' AND name = ?1",
+            [VERSION_TABLE],
+            |_| Ok(()),
+        )
+        .optional()?
+        .is_some();
+    if !exists {
+        return Ok(None);
+    }
+
+    let v: Option<i64> = conn
+        .query_row(
+            &format!("SELECT version FROM {VERSION_TABLE} WHERE id = 0"),
+            [],
+            |row| row.get(0),
+        )
+        .optional()
+        .context(format!("query: SELECT version FROM {VERSION_TABLE}"))?;
+
+    match v {
+        Some(v) => Ok(Some(
+            usize::try_from(v).context(format!("Invalid version {v} in {VERSION_TABLE}"))?,
+        )),
+        None => Ok(Some(0)),
+    }
+}
+
+fn set_table_version(conn: &Connection, v: usize) -> Result<()> {
+    trace!("set version table to: {}", v);
+    let Ok(value) = i64::try_from(v) else {
+        bail!(
+            "Version {v} is too large for {VERSION_TABLE} (maximum {})",
+            i64::MAX
+        );
+    };
+
+    conn.execute_batch(&create_table_sql())
+        .context(format!("query: CREATE TABLE {VERSION_TABLE}"))?;
+    conn.execute(
+        &format!("INSERT OR REPLACE INTO {VERSION_TABLE} (id, version) VALUES (0, ?1)"),
+        [value],
+    )
+    .context(format!("query: UPDATE {VERSION_TABLE} SET version = {v}"))?;
+
+    Ok(())
+}